@@ -3,10 +3,11 @@ use criterion::{criterion_group, criterion_main, Criterion};
 fn fib_benchmark(c: &mut Criterion) {
     let n = 30;
     c.bench_function(&format!("fib-{n}"), |b| {
-        let mut vm = spore_vm::Vm::new(spore_vm::Settings {
-            enable_aggressive_inline: true,
-            ..Default::default()
-        });
+        let mut vm = spore_vm::Vm::new(
+            spore_vm::Settings::builder()
+                .with_aggressive_inline(true)
+                .build(),
+        );
         vm.eval_str("(define (fib n) (if (< n 2) n (+ (fib (+ n -1)) (fib (+ n -2)))))")
             .unwrap();
         b.iter(move || {
@@ -20,10 +21,11 @@ fn fib_benchmark(c: &mut Criterion) {
 
 fn arithmetic_benchmark(c: &mut Criterion) {
     c.bench_function("arithmetic", |b| {
-        let mut vm = spore_vm::Vm::new(spore_vm::Settings {
-            enable_aggressive_inline: true,
-            ..Default::default()
-        });
+        let mut vm = spore_vm::Vm::new(
+            spore_vm::Settings::builder()
+                .with_aggressive_inline(true)
+                .build(),
+        );
         let src = r#"
 (define (benchmark)
   (let ([x (+ 10 20 30)]
@@ -43,10 +45,11 @@ fn arithmetic_benchmark(c: &mut Criterion) {
 
 fn struct_benchmark(c: &mut Criterion) {
     c.bench_function("struct", |b| {
-        let mut vm = spore_vm::Vm::new(spore_vm::Settings {
-            enable_aggressive_inline: true,
-            ..Default::default()
-        });
+        let mut vm = spore_vm::Vm::new(
+            spore_vm::Settings::builder()
+                .with_aggressive_inline(true)
+                .build(),
+        );
         let src = r#"
 (define my-struct (struct 'x 0 'y 10 'width 20 'height 30))
 (struct-set! my-struct 'right (+ (struct-get my-struct 'x) (struct-get my-struct 'width)))
@@ -61,10 +64,27 @@ fn struct_benchmark(c: &mut Criterion) {
     });
 }
 
+fn buffer_benchmark(c: &mut Criterion) {
+    c.bench_function("buffer-insert", |b| {
+        let mut vm = spore_vm::Vm::new(
+            spore_vm::Settings::builder()
+                .with_aggressive_inline(true)
+                .build(),
+        );
+        let src = r#"
+(define buffer (make-buffer))
+(for-each (lambda (i) (buffer-insert! buffer i "x")) (range-seq 10000))
+(buffer-length buffer)
+"#;
+        b.iter(move || vm.eval_str(src).unwrap().try_int().unwrap())
+    });
+}
+
 criterion_group!(
     benches,
     fib_benchmark,
     arithmetic_benchmark,
-    struct_benchmark
+    struct_benchmark,
+    buffer_benchmark
 );
 criterion_main!(benches);
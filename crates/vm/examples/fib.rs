@@ -3,10 +3,12 @@ use spore_vm::Settings;
 fn main() {
     let n = 35;
 
-    let mut vm = spore_vm::Vm::new(Settings {
-        enable_aggressive_inline: true,
-        enable_source_maps: false,
-    });
+    let mut vm = spore_vm::Vm::new(
+        Settings::builder()
+            .with_aggressive_inline(true)
+            .with_source_maps(false)
+            .build(),
+    );
     let src = r#"
 (define (fib n)
   (if (< n 2) (return n))
@@ -0,0 +1,308 @@
+use crate::{
+    builtins::{check_arity, filled_vec},
+    error::{VmError, VmResult},
+    val::{ArrayVal, NativeFunctionContext, UnsafeVal, ValBuilder, ValId},
+};
+
+/// Create an array containing all the args. Unlike vectors, the elements of an array may be
+/// replaced with `array-set!`.
+pub fn array(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let args: Vec<UnsafeVal> = ctx.args().map(|x| x.as_unsafe_val()).collect();
+    // Unsafe OK: All values come from the arguments.
+    Ok(unsafe { ctx.new_array(args.into()) })
+}
+
+/// Create an array of a given length. Every element is set to the optional fill value or void.
+/// `(make-array len fill)`
+pub fn make_array(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let (len, fill) = match ctx.args().map(|x| x.as_unsafe_val()).collect::<Vec<_>>()[..] {
+        [len] => (len, UnsafeVal::Void),
+        [len, fill] => (len, fill),
+        _ => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "make-array".into(),
+                expected: if ctx.arg_count() == 0 { 1 } else { 2 },
+                actual: ctx.arg_count(),
+            })
+        }
+    };
+    let len = match len {
+        UnsafeVal::Int(len) => len,
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "make-array",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let len = usize::try_from(len).map_err(|_| VmError::CustomError {
+        src: None,
+        message: format!("make-array length must not be negative but got {len}"),
+    })?;
+    let array = filled_vec("make-array", len, fill)?;
+    // Unsafe OK: The fill value is an argument.
+    Ok(unsafe { ctx.new_array(array.into()) })
+}
+
+/// Get the element of an array at an index. `(array-ref a idx)`
+pub fn array_ref(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "array-ref", 2)?;
+    let (id, idx) = array_and_index_args(&ctx, "array-ref")?;
+    let v = ctx.vm().objects.get_array(id)[idx];
+    // Unsafe OK: The element is kept alive by the array which is an arg.
+    Ok(unsafe { ctx.with_unsafe_val(v) })
+}
+
+/// Replace the element of an array at an index and return the array. `(array-set! a idx val)`
+pub fn array_set(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "array-set!", 3)?;
+    let (id, idx) = array_and_index_args(&ctx, "array-set!")?;
+    let val = ctx.arg(2).unwrap().as_unsafe_val();
+    // Unsafe OK: Garbage collection does not run while the array is modified.
+    unsafe { ctx.vm_mut() }.objects.get_array_mut(id)[idx] = val;
+    // Unsafe OK: The array is an argument.
+    Ok(unsafe { ctx.with_unsafe_val(id.into()) })
+}
+
+/// Get the number of elements in an array.
+pub fn array_length(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "array-length", 1)?;
+    let id = array_arg(&ctx, "array-length")?;
+    let len = ctx.vm().objects.get_array(id).len() as i64;
+    Ok(ValBuilder::new(len.into()))
+}
+
+fn array_arg(ctx: &NativeFunctionContext, function: &'static str) -> VmResult<ValId<ArrayVal>> {
+    match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::Array(id) => Ok(id),
+        v => Err(VmError::TypeError {
+            src: None,
+            context: function,
+            expected: UnsafeVal::ARRAY_TYPE_NAME,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        }),
+    }
+}
+
+/// Get the array and a bounds checked index for `array-ref` and `array-set!`.
+fn array_and_index_args(
+    ctx: &NativeFunctionContext,
+    function: &'static str,
+) -> VmResult<(ValId<ArrayVal>, usize)> {
+    let id = array_arg(ctx, function)?;
+    let idx = match ctx.arg(1).unwrap().as_unsafe_val() {
+        UnsafeVal::Int(idx) => idx,
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: function,
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let len = ctx.vm().objects.get_array(id).len();
+    match usize::try_from(idx) {
+        Ok(idx) if idx < len => Ok((id, idx)),
+        _ => Err(VmError::CustomError {
+            src: None,
+            message: format!("{function} index {idx} is out of bounds for array of length {len}"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    #[test]
+    fn array_contains_args() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(array 1 \"two\" 'three)").unwrap().to_string(),
+            "(array 1 \"two\" 'three)"
+        );
+        assert_eq!(vm.eval_str("(array)").unwrap().to_string(), "(array)");
+        assert_eq!(
+            vm.eval_str("(type-of (array 1))").unwrap().to_string(),
+            "'array"
+        );
+        assert_eq!(
+            vm.eval_str("(array-length (array 1 2 3))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn make_array_fills_elements() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(make-array 3 0)").unwrap().to_string(),
+            "(array 0 0 0)"
+        );
+        assert_eq!(
+            vm.eval_str("(make-array 2)").unwrap().to_string(),
+            "(array <void> <void>)"
+        );
+        assert_eq!(
+            vm.eval_str("(make-array 0)").unwrap().to_string(),
+            "(array)"
+        );
+        let src = "(make-array -1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 15).with_src(src.into())),
+                message: "make-array length must not be negative but got -1".into(),
+            }
+        );
+        let err = vm
+            .eval_str("(make-array 1000000000000000000)")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("make-array could not allocate 1000000000000000000 elements"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn array_set_is_observed_through_other_references() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define a (make-array 3 0))").unwrap();
+        vm.eval_str("(define b a)").unwrap();
+        vm.eval_str("(define s (struct 'arr a))").unwrap();
+        vm.eval_str("(array-set! b 1 \"one\")").unwrap();
+        assert_eq!(
+            vm.eval_str("(array-ref a 1)").unwrap().to_string(),
+            "\"one\""
+        );
+        assert_eq!(vm.eval_str("a").unwrap().to_string(), "(array 0 \"one\" 0)");
+        assert_eq!(
+            vm.eval_str("(struct-get s 'arr)").unwrap().to_string(),
+            "(array 0 \"one\" 0)"
+        );
+    }
+
+    #[test]
+    fn array_set_returns_array() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(array-set! (array 1 2) 0 3)")
+                .unwrap()
+                .to_string(),
+            "(array 3 2)"
+        );
+    }
+
+    #[test]
+    fn array_index_out_of_bounds_returns_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define a (array 10 20 30))").unwrap();
+        for idx in [3, -1] {
+            for (function, extra) in [("array-ref", ""), ("array-set!", " 0")] {
+                let src = format!("({function} a {idx}{extra})");
+                assert_eq!(
+                    vm.eval_str(&src).unwrap_err(),
+                    VmError::CustomError {
+                        src: Some(Span::new(0, src.len() as u32).with_src(src.as_str().into())),
+                        message: format!(
+                            "{function} index {idx} is out of bounds for array of length 3"
+                        ),
+                    }
+                );
+            }
+        }
+        assert_eq!(vm.eval_str("a").unwrap().to_string(), "(array 10 20 30)");
+    }
+
+    #[test]
+    fn array_functions_with_bad_args_return_error() {
+        let mut vm = Vm::default();
+        let src = "(array-ref (vector 1) 0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 24).with_src(src.into())),
+                context: "array-ref",
+                expected: UnsafeVal::ARRAY_TYPE_NAME,
+                actual: UnsafeVal::VECTOR_TYPE_NAME,
+                value: "(vector 1)".into(),
+            }
+        );
+        let src = "(make-array 1.0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 16).with_src(src.into())),
+                context: "make-array",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::FLOAT_TYPE_NAME,
+                value: "1.0".into(),
+            }
+        );
+        let src = "(make-array)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 12).with_src(src.into())),
+                function: "make-array".into(),
+                expected: 1,
+                actual: 0,
+            }
+        );
+        let src = "(make-array 1 2 3)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 18).with_src(src.into())),
+                function: "make-array".into(),
+                expected: 2,
+                actual: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn arrays_are_equal_if_elements_are_equal() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str("(= (array 1 (list 2)) (array 1 (list 2)))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(= (array 1 2) (vector 1 2))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn array_elements_are_kept_alive_by_garbage_collector() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define a (make-array 2))").unwrap();
+        vm.eval_str("(array-set! a 0 (list \"string\" 1))").unwrap();
+        vm.eval_str("(array-set! a 1 (vector 2))").unwrap();
+        vm.collect_garbage();
+        assert_eq!(
+            vm.eval_str("a").unwrap().to_string(),
+            "(array (\"string\" 1) (vector 2))"
+        );
+        let a = vm.val_by_name("a").unwrap();
+        for element in a.try_array(&vm).unwrap().iter() {
+            assert!(vm.objects.is_alive(element.as_unsafe_val()));
+        }
+    }
+}
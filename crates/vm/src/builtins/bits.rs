@@ -0,0 +1,187 @@
+use crate::{
+    builtins::check_arity,
+    error::{VmError, VmResult},
+    val::{NativeFunctionContext, UnsafeVal, Val, ValBuilder},
+};
+
+/// Get the `idx` argument as an int.
+fn int_arg(ctx: &NativeFunctionContext, idx: usize, context: &'static str) -> VmResult<i64> {
+    let v = ctx.arg(idx).unwrap();
+    v.try_int().map_err(|v| VmError::TypeError {
+        src: None,
+        context,
+        expected: UnsafeVal::INT_TYPE_NAME,
+        actual: v.type_name(),
+        value: v.format_quoted(ctx.vm()).to_string(),
+    })
+}
+
+/// Get the shift amount at argument `idx`. Shift amounts must be within `[0, 64)`.
+fn shift_amount_arg(
+    ctx: &NativeFunctionContext,
+    idx: usize,
+    context: &'static str,
+) -> VmResult<u32> {
+    match int_arg(ctx, idx, context)? {
+        x @ 0..=63 => Ok(x as u32),
+        x => Err(VmError::TypeError {
+            src: None,
+            context,
+            expected: "int between 0 and 63",
+            actual: UnsafeVal::INT_TYPE_NAME,
+            value: x.to_string(),
+        }),
+    }
+}
+
+pub fn bit_and(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bit-and", 2)?;
+    let a = int_arg(&ctx, 0, "bit-and arg(idx=0)")?;
+    let b = int_arg(&ctx, 1, "bit-and arg(idx=1)")?;
+    Ok(Val::new_int(a & b).into())
+}
+
+pub fn bit_or(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bit-or", 2)?;
+    let a = int_arg(&ctx, 0, "bit-or arg(idx=0)")?;
+    let b = int_arg(&ctx, 1, "bit-or arg(idx=1)")?;
+    Ok(Val::new_int(a | b).into())
+}
+
+pub fn bit_xor(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bit-xor", 2)?;
+    let a = int_arg(&ctx, 0, "bit-xor arg(idx=0)")?;
+    let b = int_arg(&ctx, 1, "bit-xor arg(idx=1)")?;
+    Ok(Val::new_int(a ^ b).into())
+}
+
+pub fn bit_not(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bit-not", 1)?;
+    let a = int_arg(&ctx, 0, "bit-not arg(idx=0)")?;
+    Ok(Val::new_int(!a).into())
+}
+
+pub fn shift_left(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "shift-left", 2)?;
+    let a = int_arg(&ctx, 0, "shift-left arg(idx=0)")?;
+    let amount = shift_amount_arg(&ctx, 1, "shift-left arg(idx=1)")?;
+    Ok(Val::new_int(a << amount).into())
+}
+
+/// Shift right. This is an arithmetic shift so the sign of negative numbers is preserved.
+pub fn shift_right(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "shift-right", 2)?;
+    let a = int_arg(&ctx, 0, "shift-right arg(idx=0)")?;
+    let amount = shift_amount_arg(&ctx, 1, "shift-right arg(idx=1)")?;
+    Ok(Val::new_int(a >> amount).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    fn eval_int(vm: &mut Vm, src: &str) -> i64 {
+        vm.eval_str(src).unwrap().try_int().unwrap()
+    }
+
+    #[test]
+    fn bit_and_or_xor_combine_bits() {
+        let mut vm = Vm::default();
+        assert_eq!(eval_int(&mut vm, "(bit-and 12 10)"), 8);
+        assert_eq!(eval_int(&mut vm, "(bit-or 12 10)"), 14);
+        assert_eq!(eval_int(&mut vm, "(bit-xor 12 10)"), 6);
+        assert_eq!(eval_int(&mut vm, "(bit-and -1 7)"), 7);
+    }
+
+    #[test]
+    fn bit_not_flips_all_bits() {
+        let mut vm = Vm::default();
+        assert_eq!(eval_int(&mut vm, "(bit-not 0)"), -1);
+        assert_eq!(eval_int(&mut vm, "(bit-not 5)"), -6);
+    }
+
+    #[test]
+    fn shift_moves_bits() {
+        let mut vm = Vm::default();
+        assert_eq!(eval_int(&mut vm, "(shift-left 1 0)"), 1);
+        assert_eq!(eval_int(&mut vm, "(shift-left 1 4)"), 16);
+        assert_eq!(eval_int(&mut vm, "(shift-left 1 63)"), i64::MIN);
+        assert_eq!(eval_int(&mut vm, "(shift-right 16 4)"), 1);
+        assert_eq!(eval_int(&mut vm, "(shift-right -16 2)"), -4);
+        assert_eq!(eval_int(&mut vm, "(shift-right -1 63)"), -1);
+    }
+
+    #[test]
+    fn shift_with_out_of_range_amount_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(shift-left 1 64)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 17).with_src(src.into())),
+                context: "shift-left arg(idx=1)",
+                expected: "int between 0 and 63",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "64".into(),
+            }
+        );
+        let src = "(shift-right 1 -1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 18).with_src(src.into())),
+                context: "shift-right arg(idx=1)",
+                expected: "int between 0 and 63",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "-1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn bit_functions_with_wrong_args_return_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(bit-and 1)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 11).with_src("(bit-and 1)".into())),
+                function: "bit-and".into(),
+                expected: 2,
+                actual: 1,
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(bit-not 1 2)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 13).with_src("(bit-not 1 2)".into())),
+                function: "bit-not".into(),
+                expected: 1,
+                actual: 2,
+            }
+        );
+        let src = "(bit-xor 1 2.0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 15).with_src(src.into())),
+                context: "bit-xor arg(idx=1)",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::FLOAT_TYPE_NAME,
+                value: "2.0".into(),
+            }
+        );
+        let src = "(bit-or \"1\" 2)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 14).with_src(src.into())),
+                context: "bit-or arg(idx=0)",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"1\"".into(),
+            }
+        );
+    }
+}
@@ -11,6 +11,7 @@ pub fn new_box(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
             Ok(unsafe { ctx.new_mutable_box(v) })
         }
         args => Err(VmError::ArityError {
+            src: None,
             function: "new-box".into(),
             expected: 1,
             actual: args,
@@ -21,6 +22,7 @@ pub fn new_box(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
 pub fn set_box(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     if ctx.arg_count() != 2 {
         return Err(VmError::ArityError {
+            src: None,
             function: "set-box!".into(),
             expected: 2,
             actual: ctx.arg_count(),
@@ -47,6 +49,7 @@ pub fn set_box(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
 pub fn unbox(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     if ctx.arg_count() != 1 {
         return Err(VmError::ArityError {
+            src: None,
             function: "unbox".into(),
             expected: 1,
             actual: ctx.arg_count(),
@@ -69,6 +72,40 @@ pub fn unbox(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     }
 }
 
+/// Replace the value of a box with the result of calling a function on its current value. Returns
+/// the new value.
+pub fn box_update(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "box-update!".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let id = match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::MutableBox(id) => id,
+        arg => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "box-update!",
+                expected: UnsafeVal::MUTABLE_BOX_TYPE_NAME,
+                actual: arg.type_name(),
+                value: arg.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let func = ctx.arg(1).unwrap().as_unsafe_val();
+    let old_val = *ctx.vm().objects.get_mutable_box(id);
+    // Unsafe OK: Garbage collection does not run while calling `func` and the box is reachable
+    // through the arguments.
+    let vm = unsafe { ctx.vm_mut() };
+    let new_val = vm.call_reentrant(func, &[old_val])?;
+    vm.objects.set_mutable_box(id, new_val);
+    // Unsafe OK: `new_val` is now held by the box.
+    Ok(unsafe { ctx.with_unsafe_val(new_val) })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{parser::span::Span, Vm};
@@ -81,6 +118,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(new-box)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 9).with_src("(new-box)".into())),
                 function: "new-box".into(),
                 expected: 1,
                 actual: 0
@@ -89,6 +127,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(new-box 0 1)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 13).with_src("(new-box 0 1)".into())),
                 function: "new-box".into(),
                 expected: 1,
                 actual: 2
@@ -119,6 +158,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(unbox)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 7).with_src("(unbox)".into())),
                 function: "unbox".into(),
                 expected: 1,
                 actual: 0
@@ -127,6 +167,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(unbox (new-box 0) 1)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 21).with_src("(unbox (new-box 0) 1)".into())),
                 function: "unbox".into(),
                 expected: 1,
                 actual: 2
@@ -168,6 +209,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(set-box!)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 10).with_src("(set-box!)".into())),
                 function: "set-box!".into(),
                 expected: 2,
                 actual: 0
@@ -176,6 +218,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(set-box! (new-box 0))").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 22).with_src("(set-box! (new-box 0))".into())),
                 function: "set-box!".into(),
                 expected: 2,
                 actual: 1
@@ -195,10 +238,57 @@ mod tests {
         assert_eq!(
             vm.eval_str("(set-box! (new-box 0) 1 2)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 26).with_src("(set-box! (new-box 0) 1 2)".into())),
                 function: "set-box!".into(),
                 expected: 2,
                 actual: 3
             }
         );
     }
+
+    #[test]
+    fn box_update_stores_result_of_function() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define val (new-box 1))").unwrap();
+        assert_eq!(
+            vm.eval_str("(box-update! val inc)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            vm.eval_str("(box-update! val (lambda (x) (+ x 10)))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            12
+        );
+        assert_eq!(vm.eval_str("(unbox val)").unwrap().try_int().unwrap(), 12);
+    }
+
+    #[test]
+    fn box_update_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(box-update! (new-box 0))").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 25).with_src("(box-update! (new-box 0))".into())),
+                function: "box-update!".into(),
+                expected: 2,
+                actual: 1
+            }
+        );
+        let src = "(box-update! 0 inc)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 19).with_src(src.into())),
+                context: "box-update!",
+                expected: UnsafeVal::MUTABLE_BOX_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "0".to_string(),
+            }
+        );
+    }
 }
@@ -0,0 +1,481 @@
+use compact_str::CompactString;
+
+use crate::{
+    builtins::check_arity,
+    error::{VmError, VmResult},
+    val::{custom::CustomValMut, CustomType, NativeFunctionContext, UnsafeVal, Val, ValBuilder},
+};
+
+/// A mutable piece of text that supports editing in place.
+///
+/// The text is stored as a gap buffer. Edits are made by moving the gap to the edit position so
+/// repeated edits around the same position, like typing at a cursor, only move a small amount of
+/// text.
+///
+/// Positions are measured in chars rather than bytes so that edits always land on UTF-8 char
+/// boundaries.
+#[derive(Debug, Default)]
+pub struct TextBuffer {
+    /// The UTF-8 bytes of the text with a gap of unused bytes in `gap_start..gap_end`.
+    data: Vec<u8>,
+    /// The start of the gap. This is also the logical position of the gap within the text.
+    gap_start: usize,
+    /// The end of the gap, exclusive.
+    gap_end: usize,
+    /// The number of chars before the gap.
+    gap_start_chars: usize,
+    /// The number of chars in the buffer.
+    char_count: usize,
+}
+
+impl CustomType for TextBuffer {}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<text-buffer length={}>", self.len())
+    }
+}
+
+impl TextBuffer {
+    /// The minimum number of bytes to reserve for the gap when it must grow.
+    const MIN_GAP: usize = 64;
+
+    /// Create a new buffer containing `text`.
+    pub fn new(text: &str) -> TextBuffer {
+        let char_count = text.chars().count();
+        TextBuffer {
+            data: text.as_bytes().to_vec(),
+            gap_start: text.len(),
+            gap_end: text.len(),
+            gap_start_chars: char_count,
+            char_count,
+        }
+    }
+
+    /// The length of the buffer in chars.
+    pub fn len(&self) -> usize {
+        self.char_count
+    }
+
+    /// The number of lines in the buffer. An empty buffer has a single line.
+    pub fn line_count(&self) -> usize {
+        let (before, after) = self.halves();
+        let newlines = |s: &str| s.bytes().filter(|b| *b == b'\n').count();
+        newlines(before) + newlines(after) + 1
+    }
+
+    /// Insert `text` at char position `pos`. Returns `false` if `pos` is not within the buffer.
+    pub fn insert(&mut self, pos: usize, text: &str) -> bool {
+        let byte_pos = match self.byte_offset(pos) {
+            Some(b) => b,
+            None => return false,
+        };
+        self.move_gap(byte_pos, pos);
+        self.reserve_gap(text.len());
+        self.data[self.gap_start..self.gap_start + text.len()].copy_from_slice(text.as_bytes());
+        let text_chars = text.chars().count();
+        self.gap_start += text.len();
+        self.gap_start_chars += text_chars;
+        self.char_count += text_chars;
+        true
+    }
+
+    /// Delete the text in char range `[start, end)`. Returns `false` if the range is not within
+    /// the buffer.
+    pub fn delete(&mut self, start: usize, end: usize) -> bool {
+        if start > end {
+            return false;
+        }
+        let (byte_start, byte_end) = match (self.byte_offset(start), self.byte_offset(end)) {
+            (Some(s), Some(e)) => (s, e),
+            _ => return false,
+        };
+        self.move_gap(byte_start, start);
+        self.gap_end += byte_end - byte_start;
+        self.char_count -= end - start;
+        true
+    }
+
+    /// Get the contents of the buffer.
+    pub fn contents(&self) -> String {
+        let (before, after) = self.halves();
+        let mut contents = String::with_capacity(before.len() + after.len());
+        contents.push_str(before);
+        contents.push_str(after);
+        contents
+    }
+
+    /// Get the text before and after the gap.
+    fn halves(&self) -> (&str, &str) {
+        // Unwrap OK: Text is only inserted as whole strings at char boundaries and the gap is only
+        // moved to char boundaries so both halves are always valid UTF-8.
+        (
+            std::str::from_utf8(&self.data[..self.gap_start]).unwrap(),
+            std::str::from_utf8(&self.data[self.gap_end..]).unwrap(),
+        )
+    }
+
+    fn gap_len(&self) -> usize {
+        self.gap_end - self.gap_start
+    }
+
+    /// Get the logical byte offset of char position `pos` or `None` if `pos` is not within the
+    /// buffer. The search starts from the gap so positions near the last edit are found quickly.
+    fn byte_offset(&self, pos: usize) -> Option<usize> {
+        if pos > self.char_count {
+            return None;
+        }
+        let (before, after) = self.halves();
+        if pos >= self.gap_start_chars {
+            let offset = after
+                .char_indices()
+                .nth(pos - self.gap_start_chars)
+                .map(|(idx, _)| idx)
+                .unwrap_or(after.len());
+            Some(self.gap_start + offset)
+        } else {
+            before
+                .char_indices()
+                .nth_back(self.gap_start_chars - pos - 1)
+                .map(|(idx, _)| idx)
+        }
+    }
+
+    /// Move the gap so that it starts at logical byte position `pos` which is char position
+    /// `char_pos`.
+    fn move_gap(&mut self, pos: usize, char_pos: usize) {
+        if pos < self.gap_start {
+            let moved = self.gap_start - pos;
+            self.data
+                .copy_within(pos..self.gap_start, self.gap_end - moved);
+            self.gap_start = pos;
+            self.gap_end -= moved;
+        } else if pos > self.gap_start {
+            let moved = pos - self.gap_start;
+            self.data
+                .copy_within(self.gap_end..self.gap_end + moved, self.gap_start);
+            self.gap_start = pos;
+            self.gap_end += moved;
+        }
+        self.gap_start_chars = char_pos;
+    }
+
+    /// Grow the gap so that it can hold at least `n` bytes.
+    fn reserve_gap(&mut self, n: usize) {
+        if self.gap_len() >= n {
+            return;
+        }
+        let extra = n.max(self.data.len()).max(Self::MIN_GAP) - self.gap_len();
+        let after_len = self.data.len() - self.gap_end;
+        self.data.resize(self.data.len() + extra, 0);
+        let new_gap_end = self.gap_end + extra;
+        self.data
+            .copy_within(self.gap_end..self.gap_end + after_len, new_gap_end);
+        self.gap_end = new_gap_end;
+    }
+}
+
+fn buffer_arg<'a>(
+    ctx: &'a NativeFunctionContext,
+    function: &'static str,
+) -> VmResult<CustomValMut<'a, TextBuffer>> {
+    let v = ctx.arg(0).unwrap();
+    v.try_custom_mut(ctx.vm()).map_err(|_| VmError::TypeError {
+        src: None,
+        context: function,
+        expected: "text-buffer",
+        actual: v.type_name(),
+        value: v.format_quoted(ctx.vm()).to_string(),
+    })
+}
+
+fn position_arg(ctx: &NativeFunctionContext, idx: usize, context: &'static str) -> VmResult<usize> {
+    let v = ctx.arg(idx).unwrap();
+    match v.try_int() {
+        Ok(pos) if pos >= 0 => Ok(pos as usize),
+        _ => Err(VmError::TypeError {
+            src: None,
+            context,
+            expected: "non-negative int",
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        }),
+    }
+}
+
+/// Create a new text buffer. `(make-buffer)` creates an empty buffer and `(make-buffer text)`
+/// creates a buffer that starts with `text`.
+pub fn make_buffer(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let buffer = match ctx.arg_count() {
+        0 => TextBuffer::default(),
+        1 => {
+            let v = ctx.arg(0).unwrap();
+            let text = v.try_str(ctx.vm()).map_err(|v| VmError::TypeError {
+                src: None,
+                context: "make-buffer",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })?;
+            TextBuffer::new(text)
+        }
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "make-buffer".into(),
+                expected: 1,
+                actual: n,
+            })
+        }
+    };
+    Ok(ctx.new_custom(buffer))
+}
+
+/// Insert a string into the buffer. `(buffer-insert! buffer pos text)` inserts `text` at char
+/// position `pos`.
+pub fn buffer_insert(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "buffer-insert!", 3)?;
+    let pos = position_arg(&ctx, 1, "buffer-insert! arg(idx = 1)")?;
+    let text_val = ctx.arg(2).unwrap();
+    let text = text_val.try_str(ctx.vm()).map_err(|v| VmError::TypeError {
+        src: None,
+        context: "buffer-insert! arg(idx = 2)",
+        expected: UnsafeVal::STRING_TYPE_NAME,
+        actual: v.type_name(),
+        value: v.format_quoted(ctx.vm()).to_string(),
+    })?;
+    let mut buffer = buffer_arg(&ctx, "buffer-insert!")?;
+    if !buffer.insert(pos, text) {
+        return Err(VmError::CustomError {
+            src: None,
+            message: format!(
+                "buffer-insert! position {pos} is out of range for buffer of length {len}",
+                len = buffer.len()
+            ),
+        });
+    }
+    Ok(Val::new_void().into())
+}
+
+/// Delete a range of text from the buffer. `(buffer-delete! buffer start end)` removes the text
+/// from char position `start` up to, but not including, `end`.
+pub fn buffer_delete(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "buffer-delete!", 3)?;
+    let start = position_arg(&ctx, 1, "buffer-delete! arg(idx = 1)")?;
+    let end = position_arg(&ctx, 2, "buffer-delete! arg(idx = 2)")?;
+    let mut buffer = buffer_arg(&ctx, "buffer-delete!")?;
+    if !buffer.delete(start, end) {
+        return Err(VmError::CustomError {
+            src: None,
+            message: format!(
+                "buffer-delete! range [{start}, {end}) is out of range for buffer of length {len}",
+                len = buffer.len()
+            ),
+        });
+    }
+    Ok(Val::new_void().into())
+}
+
+/// Get the contents of the buffer as a string.
+pub fn buffer_to_string(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "buffer->string", 1)?;
+    let text: CompactString = buffer_arg(&ctx, "buffer->string")?.contents().into();
+    Ok(ctx.new_string(text))
+}
+
+/// Get the number of chars in the buffer.
+pub fn buffer_length(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "buffer-length", 1)?;
+    let len = buffer_arg(&ctx, "buffer-length")?.len();
+    Ok(Val::new_int(len as i64).into())
+}
+
+/// Get the number of lines in the buffer.
+pub fn buffer_line_count(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "buffer-line-count", 1)?;
+    let count = buffer_arg(&ctx, "buffer-line-count")?.line_count();
+    Ok(Val::new_int(count as i64).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    #[test]
+    fn buffer_insert_and_delete_modify_text() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (make-buffer \"hello world\"))")
+            .unwrap();
+        vm.eval_str("(buffer-insert! b 5 \",\")").unwrap();
+        vm.eval_str("(buffer-insert! b 12 \"!\")").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer->string b)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "hello, world!"
+        );
+        vm.eval_str("(buffer-delete! b 0 7)").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer->string b)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "world!"
+        );
+        assert_eq!(
+            vm.eval_str("(buffer-length b)").unwrap().try_int().unwrap(),
+            6
+        );
+    }
+
+    #[test]
+    fn buffer_line_count_counts_newlines() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (make-buffer))").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer-line-count b)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            1
+        );
+        vm.eval_str("(buffer-insert! b 0 \"a\nb\nc\")").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer-line-count b)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            vm.eval_str("(buffer-length b)").unwrap().try_int().unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn buffer_functions_with_bad_args_return_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (make-buffer \"abc\"))").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer-insert! b 4 \"d\")").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 24).with_src("(buffer-insert! b 4 \"d\")".into())),
+                message: "buffer-insert! position 4 is out of range for buffer of length 3".into()
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(buffer-delete! b 2 1)").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 22).with_src("(buffer-delete! b 2 1)".into())),
+                message: "buffer-delete! range [2, 1) is out of range for buffer of length 3"
+                    .into()
+            }
+        );
+        let src = "(buffer-length \"abc\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 21).with_src(src.into())),
+                context: "buffer-length",
+                expected: "text-buffer",
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"abc\"".into(),
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(buffer-insert! b)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 18).with_src("(buffer-insert! b)".into())),
+                function: "buffer-insert!".into(),
+                expected: 3,
+                actual: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn text_buffer_edits_at_moving_cursor_match_string() {
+        let mut buffer = TextBuffer::default();
+        let mut reference: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // A simple linear congruential generator keeps the test deterministic.
+        let mut seed: u64 = 42;
+        let mut next_rand = |max: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as usize % (max + 1)
+        };
+        for i in 0..5000 {
+            match next_rand(9) {
+                0 => cursor = next_rand(reference.len()),
+                1 => {
+                    let end = (cursor + next_rand(3)).min(reference.len());
+                    assert!(buffer.delete(cursor, end));
+                    reference.drain(cursor..end);
+                }
+                _ => {
+                    let text = match i % 7 {
+                        0 => "\n",
+                        1 => "é😀",
+                        _ => "ab",
+                    };
+                    assert!(buffer.insert(cursor, text));
+                    reference.splice(cursor..cursor, text.chars());
+                    cursor += text.chars().count();
+                }
+            }
+        }
+        let reference: String = reference.into_iter().collect();
+        assert_eq!(buffer.contents(), reference);
+        assert_eq!(buffer.len(), reference.chars().count());
+        assert_eq!(buffer.line_count(), reference.split('\n').count());
+    }
+
+    #[test]
+    fn text_buffer_out_of_range_edits_are_rejected() {
+        let mut buffer = TextBuffer::new("abc");
+        assert!(!buffer.insert(4, "d"));
+        assert!(!buffer.delete(1, 4));
+        assert!(buffer.insert(1, "xyz"));
+        assert!(!buffer.delete(7, 7));
+        assert_eq!(buffer.contents(), "axyzbc");
+    }
+
+    #[test]
+    fn buffer_positions_count_multibyte_chars_once() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (make-buffer \"café\"))").unwrap();
+        vm.eval_str("(define cursor (new-box (buffer-length b)))")
+            .unwrap();
+        vm.eval_str(
+            "(define (type! text) (buffer-insert! b (unbox cursor) text) (set-box! cursor (+ (unbox cursor) (string-char-length text))))",
+        )
+        .unwrap();
+        vm.eval_str("(type! \"😀\")").unwrap();
+        vm.eval_str("(type! \"!\")").unwrap();
+        vm.eval_str("(buffer-insert! b 1 \"ñ\")").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer->string b)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "cñafé😀!"
+        );
+        assert_eq!(vm.eval_str("(unbox cursor)").unwrap().try_int().unwrap(), 6);
+        assert_eq!(
+            vm.eval_str("(buffer-length b)").unwrap().try_int().unwrap(),
+            7
+        );
+        vm.eval_str("(buffer-delete! b 4 6)").unwrap();
+        assert_eq!(
+            vm.eval_str("(buffer->string b)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "cñaf!"
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use crate::{
+    builtins::{check_arity, filled_vec},
+    error::{VmError, VmResult},
+    val::{BytesVal, NativeFunctionContext, UnsafeVal, ValBuilder, ValId},
+};
+
+/// Create bytes containing all the args. Each arg must be an integer between 0 and 255.
+pub fn bytes(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let bytes = ctx
+        .args()
+        .map(|v| byte_arg(&ctx, "bytes", v.as_unsafe_val()))
+        .collect::<VmResult<BytesVal>>()?;
+    Ok(ctx.new_bytes(bytes))
+}
+
+/// Create bytes of a given length. Every byte is set to the optional fill value or 0.
+/// `(make-bytes len fill)`
+pub fn make_bytes(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let (len, fill) = match ctx.args().map(|x| x.as_unsafe_val()).collect::<Vec<_>>()[..] {
+        [len] => (len, 0),
+        [len, fill] => (len, byte_arg(&ctx, "make-bytes", fill)?),
+        _ => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "make-bytes".into(),
+                expected: if ctx.arg_count() == 0 { 1 } else { 2 },
+                actual: ctx.arg_count(),
+            })
+        }
+    };
+    let len = int_arg(&ctx, "make-bytes", len)?;
+    let len = usize::try_from(len).map_err(|_| VmError::CustomError {
+        src: None,
+        message: format!("make-bytes length must not be negative but got {len}"),
+    })?;
+    let bytes = filled_vec("make-bytes", len, fill)?;
+    Ok(ctx.new_bytes(bytes.into_boxed_slice()))
+}
+
+/// Get the byte at an index. `(bytes-ref b idx)`
+pub fn bytes_ref(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bytes-ref", 2)?;
+    let (id, idx) = bytes_and_index_args(&ctx, "bytes-ref")?;
+    let b = ctx.vm().objects.get_bytes(id)[idx];
+    Ok(ValBuilder::new((b as i64).into()))
+}
+
+/// Replace the byte at an index and return the bytes. `(bytes-set! b idx byte)`
+pub fn bytes_set(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bytes-set!", 3)?;
+    let (id, idx) = bytes_and_index_args(&ctx, "bytes-set!")?;
+    let b = byte_arg(&ctx, "bytes-set!", ctx.arg(2).unwrap().as_unsafe_val())?;
+    // Unsafe OK: Garbage collection does not run while the bytes are modified.
+    unsafe { ctx.vm_mut() }.objects.get_bytes_mut(id)[idx] = b;
+    // Unsafe OK: The bytes are an argument.
+    Ok(unsafe { ctx.with_unsafe_val(id.into()) })
+}
+
+/// Get the number of bytes.
+pub fn bytes_length(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bytes-length", 1)?;
+    let id = bytes_arg(&ctx, "bytes-length")?;
+    let len = ctx.vm().objects.get_bytes(id).len() as i64;
+    Ok(ValBuilder::new(len.into()))
+}
+
+/// Get the UTF-8 encoding of a string.
+pub fn string_to_bytes(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "string->bytes", 1)?;
+    let bytes: BytesVal = match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::String(id) => ctx.vm().objects.get_str(id).as_bytes().into(),
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "string->bytes",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    Ok(ctx.new_bytes(bytes))
+}
+
+/// Decode bytes as a UTF-8 string. Returns an error if the bytes are not valid UTF-8.
+pub fn bytes_to_string(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "bytes->string", 1)?;
+    let id = bytes_arg(&ctx, "bytes->string")?;
+    let s = match std::str::from_utf8(ctx.vm().objects.get_bytes(id)) {
+        Ok(s) => s.into(),
+        Err(err) => {
+            return Err(VmError::CustomError {
+                src: None,
+                message: format!("bytes->string got bytes that are not valid UTF-8: {err}"),
+            })
+        }
+    };
+    Ok(ctx.new_string(s))
+}
+
+fn int_arg(ctx: &NativeFunctionContext, function: &'static str, v: UnsafeVal) -> VmResult<i64> {
+    match v {
+        UnsafeVal::Int(x) => Ok(x),
+        v => Err(VmError::TypeError {
+            src: None,
+            context: function,
+            expected: UnsafeVal::INT_TYPE_NAME,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        }),
+    }
+}
+
+fn byte_arg(ctx: &NativeFunctionContext, function: &'static str, v: UnsafeVal) -> VmResult<u8> {
+    let x = int_arg(ctx, function, v)?;
+    u8::try_from(x).map_err(|_| VmError::CustomError {
+        src: None,
+        message: format!("{function} expected a byte between 0 and 255 but got {x}"),
+    })
+}
+
+fn bytes_arg(ctx: &NativeFunctionContext, function: &'static str) -> VmResult<ValId<BytesVal>> {
+    match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::Bytes(id) => Ok(id),
+        v => Err(VmError::TypeError {
+            src: None,
+            context: function,
+            expected: UnsafeVal::BYTES_TYPE_NAME,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        }),
+    }
+}
+
+/// Get the bytes and a bounds checked index for `bytes-ref` and `bytes-set!`.
+fn bytes_and_index_args(
+    ctx: &NativeFunctionContext,
+    function: &'static str,
+) -> VmResult<(ValId<BytesVal>, usize)> {
+    let id = bytes_arg(ctx, function)?;
+    let idx = int_arg(ctx, function, ctx.arg(1).unwrap().as_unsafe_val())?;
+    let len = ctx.vm().objects.get_bytes(id).len();
+    match usize::try_from(idx) {
+        Ok(idx) if idx < len => Ok((id, idx)),
+        _ => Err(VmError::CustomError {
+            src: None,
+            message: format!("{function} index {idx} is out of bounds for bytes of length {len}"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    #[test]
+    fn bytes_contains_args() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(bytes 0 1 255)").unwrap().to_string(),
+            "(bytes 0 1 255)"
+        );
+        assert_eq!(vm.eval_str("(bytes)").unwrap().to_string(), "(bytes)");
+        assert_eq!(
+            vm.eval_str("(type-of (bytes 1))").unwrap().to_string(),
+            "'bytes"
+        );
+        assert_eq!(
+            vm.eval_str("(bytes-length (bytes 1 2 3))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn make_bytes_fills_bytes() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(make-bytes 3 7)").unwrap().to_string(),
+            "(bytes 7 7 7)"
+        );
+        assert_eq!(
+            vm.eval_str("(make-bytes 2)").unwrap().to_string(),
+            "(bytes 0 0)"
+        );
+        let src = "(make-bytes -1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 15).with_src(src.into())),
+                message: "make-bytes length must not be negative but got -1".into(),
+            }
+        );
+        let err = vm
+            .eval_str("(make-bytes 1000000000000000000)")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("make-bytes could not allocate 1000000000000000000 elements"),
+            "{err}"
+        );
+        for (src, expected, actual) in [("(make-bytes)", 1, 0), ("(make-bytes 1 2 3)", 2, 3)] {
+            assert_eq!(
+                vm.eval_str(src).unwrap_err(),
+                VmError::ArityError {
+                    src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                    function: "make-bytes".into(),
+                    expected,
+                    actual,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn bytes_ref_and_set_access_bytes_at_index() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (make-bytes 3))").unwrap();
+        vm.eval_str("(define other b)").unwrap();
+        vm.eval_str("(bytes-set! other 1 200)").unwrap();
+        assert_eq!(
+            vm.eval_str("(bytes-ref b 1)").unwrap().try_int().unwrap(),
+            200
+        );
+        assert_eq!(vm.eval_str("b").unwrap().to_string(), "(bytes 0 200 0)");
+        assert_eq!(
+            vm.eval_str("(bytes-set! (bytes 1 2) 0 3)")
+                .unwrap()
+                .to_string(),
+            "(bytes 3 2)"
+        );
+    }
+
+    #[test]
+    fn bytes_index_out_of_bounds_returns_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (bytes 10 20 30))").unwrap();
+        for idx in [3, -1] {
+            for (function, extra) in [("bytes-ref", ""), ("bytes-set!", " 0")] {
+                let src = format!("({function} b {idx}{extra})");
+                assert_eq!(
+                    vm.eval_str(&src).unwrap_err(),
+                    VmError::CustomError {
+                        src: Some(Span::new(0, src.len() as u32).with_src(src.as_str().into())),
+                        message: format!(
+                            "{function} index {idx} is out of bounds for bytes of length 3"
+                        ),
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn non_byte_values_return_error() {
+        let mut vm = Vm::default();
+        for (function, src) in [
+            ("bytes", "(bytes 1 256)"),
+            ("make-bytes", "(make-bytes 1 256)"),
+            ("bytes-set!", "(bytes-set! (bytes 1) 0 256)"),
+        ] {
+            assert_eq!(
+                vm.eval_str(src).unwrap_err(),
+                VmError::CustomError {
+                    src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                    message: format!("{function} expected a byte between 0 and 255 but got 256"),
+                }
+            );
+        }
+        let src = "(bytes \"a\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 11).with_src(src.into())),
+                context: "bytes",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"a\"".into(),
+            }
+        );
+        let src = "(bytes-length (list 1))";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 23).with_src(src.into())),
+                context: "bytes-length",
+                expected: UnsafeVal::BYTES_TYPE_NAME,
+                actual: UnsafeVal::LIST_TYPE_NAME,
+                value: "(1)".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn strings_round_trip_through_bytes() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string->bytes \"hi\")").unwrap().to_string(),
+            "(bytes 104 105)"
+        );
+        assert_eq!(
+            vm.eval_str("(string->bytes \"é\")").unwrap().to_string(),
+            "(bytes 195 169)"
+        );
+        assert_eq!(
+            vm.eval_str("(bytes->string (string->bytes \"hello wörld\"))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "hello wörld"
+        );
+        let src = "(bytes->string (bytes 255))";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 27).with_src(src.into())),
+                message: "bytes->string got bytes that are not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 0".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn bytes_are_equal_if_contents_are_equal() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str("(= (bytes 1 2) (string->bytes (bytes->string (bytes 1 2))))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(= (bytes 1 2) (bytes 1 3))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn unreachable_bytes_are_garbage_collected() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define b (bytes 1 2 3))").unwrap();
+        let b = vm.val_by_name("b").unwrap().as_unsafe_val();
+        let temp = vm.eval_str("(bytes 4 5 6)").unwrap().as_unsafe_val();
+        vm.collect_garbage();
+        assert!(vm.objects.is_alive(b));
+        assert!(!vm.objects.is_alive(temp));
+        assert_eq!(
+            vm.val_by_name("b").unwrap().try_bytes(&vm).unwrap(),
+            &[1, 2, 3]
+        );
+    }
+}
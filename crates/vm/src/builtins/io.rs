@@ -0,0 +1,118 @@
+use std::fmt::Write;
+
+use crate::{
+    error::{VmError, VmResult},
+    val::{NativeFunctionContext, Val, ValBuilder},
+};
+
+/// Write all arguments to the output. Strings are written without quotes.
+pub fn print(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let output = format_args_to_string(&ctx);
+    // Unsafe OK: No values are created or garbage collected.
+    unsafe { ctx.vm_mut() }.write_output(&output);
+    Ok(Val::new_void().into())
+}
+
+/// Write all arguments to the output followed by a newline.
+pub fn println(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let mut output = format_args_to_string(&ctx);
+    output.push('\n');
+    // Unsafe OK: No values are created or garbage collected.
+    unsafe { ctx.vm_mut() }.write_output(&output);
+    Ok(Val::new_void().into())
+}
+
+fn format_args_to_string(ctx: &NativeFunctionContext<'_>) -> String {
+    let mut output = String::new();
+    for arg in ctx.args() {
+        // Unwrap OK: Writing to a string does not fail.
+        write!(output, "{}", arg.formatted(ctx.vm())).unwrap();
+    }
+    output
+}
+
+/// Call a function with no arguments and return everything it printed as a string.
+pub fn with_output_string(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "with-output-string".into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let func = ctx.arg(0).unwrap().as_unsafe_val();
+    // Unsafe OK: Garbage collection does not run while calling `func`.
+    let vm = unsafe { ctx.vm_mut() };
+    vm.output_captures.push(String::new());
+    let res = vm.call_reentrant(func, &[]);
+    // Unwrap OK: The capture was pushed above and any captures pushed by `func` have been popped.
+    let output = vm.output_captures.pop().unwrap();
+    res?;
+    Ok(ctx.new_string(output.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    #[test]
+    fn with_output_string_captures_print_from_lambda() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(with-output-string (lambda () (print \"hi\")))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "hi"
+        );
+        vm.eval_str(
+            "(define (greet name) (print \"hello \" name) (println \"!\") (println 1 2.5))",
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(with-output-string (lambda () (greet \"world\")))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "hello world!\n12.5\n"
+        );
+    }
+
+    #[test]
+    fn nested_with_output_string_captures_separately() {
+        let mut vm = Vm::default();
+        let src = r#"
+(with-output-string (lambda ()
+  (print "outer ")
+  (print (with-output-string (lambda () (print "inner"))))))
+"#;
+        assert_eq!(vm.eval_str(src).unwrap().try_str().unwrap(), "outer inner");
+        assert!(vm.output_captures.is_empty());
+    }
+
+    #[test]
+    fn with_output_string_restores_output_on_error() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str("(with-output-string (lambda () (print \"hi\") (+ 1 true)))")
+            .is_err());
+        assert!(vm.output_captures.is_empty());
+    }
+
+    #[test]
+    fn with_output_string_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(with-output-string)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 20).with_src("(with-output-string)".into())),
+                function: "with-output-string".into(),
+                expected: 1,
+                actual: 0
+            }
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use std::cmp::Ordering;
+
 use crate::{
+    builtins::{equal_impl, filled_vec},
     error::{VmError, VmResult},
-    val::{NativeFunctionContext, UnsafeVal, ValBuilder},
+    val::{ListVal, NativeFunctionContext, UnsafeVal, ValBuilder, ValId},
+    Vm,
 };
 
 pub fn list(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
@@ -21,6 +25,7 @@ pub fn list_length(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
             }),
         },
         n => Err(VmError::ArityError {
+            src: None,
             function: "list-length".into(),
             expected: 1,
             actual: n,
@@ -28,8 +33,512 @@ pub fn list_length(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     }
 }
 
+pub fn flatten(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let list_arg = match ctx.arg_count() {
+        1 | 2 => ctx.arg(0).unwrap(),
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "flatten".into(),
+                expected: if n == 0 { 1 } else { 2 },
+                actual: n,
+            })
+        }
+    };
+    let list_id = match list_arg.as_unsafe_val() {
+        UnsafeVal::List(id) => id,
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "flatten arg(idx=0)",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let depth = match ctx.arg(1) {
+        None => None,
+        Some(v) => match v.try_int() {
+            Ok(depth) if depth >= 0 => Some(depth as usize),
+            _ => {
+                return Err(VmError::TypeError {
+                    src: None,
+                    context: "flatten arg(idx=1)",
+                    expected: "non-negative int",
+                    actual: v.type_name(),
+                    value: v.format_quoted(ctx.vm()).to_string(),
+                })
+            }
+        },
+    };
+    let mut flattened = ListVal::new();
+    flatten_impl(ctx.vm(), list_id, depth, &mut Vec::new(), &mut flattened)?;
+    // Unsafe OK: All values come from a list that is currently an argument.
+    Ok(unsafe { ctx.new_list(flattened) })
+}
+
+/// Push the elements of the list with `id` into `out`, splicing in the contents of sub-lists until
+/// `depth` levels have been flattened. A `depth` of `None` flattens all levels.
+///
+/// `ancestors` contains the lists that are currently being flattened and is used to detect cycles.
+fn flatten_impl(
+    vm: &Vm,
+    id: ValId<ListVal>,
+    depth: Option<usize>,
+    ancestors: &mut Vec<ValId<ListVal>>,
+    out: &mut ListVal,
+) -> VmResult<()> {
+    if ancestors.contains(&id) {
+        return Err(VmError::CustomError {
+            src: None,
+            message: "flatten encountered a list that contains itself".into(),
+        });
+    }
+    ancestors.push(id);
+    for val in vm.objects.get_list(id).iter() {
+        match (val, depth) {
+            (UnsafeVal::List(sub_id), None) => flatten_impl(vm, *sub_id, None, ancestors, out)?,
+            (UnsafeVal::List(sub_id), Some(depth)) if depth > 0 => {
+                flatten_impl(vm, *sub_id, Some(depth - 1), ancestors, out)?
+            }
+            (val, _) => out.push(*val),
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+pub fn distinct(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let list = match ctx.arg_count() {
+        1 => match ctx.arg(0).unwrap().try_list(ctx.vm()) {
+            Ok(list) => list,
+            Err(v) => {
+                return Err(VmError::TypeError {
+                    src: None,
+                    context: "distinct",
+                    expected: UnsafeVal::LIST_TYPE_NAME,
+                    actual: v.type_name(),
+                    value: v.format_quoted(ctx.vm()).to_string(),
+                })
+            }
+        },
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "distinct".into(),
+                expected: 1,
+                actual: n,
+            })
+        }
+    };
+    // TODO: Each element is compared against all previously kept elements making this O(n^2). This
+    // can be made O(n) once values support hashing through a `hash` builtin.
+    let mut ret = ListVal::new();
+    for val in list.iter().map(|v| v.as_unsafe_val()) {
+        if !ret.iter().any(|kept| equal_impl(ctx.vm(), *kept, val)) {
+            ret.push(val);
+        }
+    }
+    // Unsafe OK: All values come from a list that is currently an argument.
+    Ok(unsafe { ctx.new_list(ret) })
+}
+
+/// Create a list containing `value` repeated `n` times. `(repeat n value)`.
+///
+/// The value is not copied so reference values like lists and structs are shared by every element
+/// of the returned list. Mutating one element is visible through all of them.
+pub fn repeat(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "repeat".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let n_val = ctx.arg(0).unwrap();
+    let n = match n_val.try_int() {
+        Ok(n) if n >= 0 => n as usize,
+        _ => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "repeat arg(idx=0)",
+                expected: "non-negative int",
+                actual: n_val.type_name(),
+                value: n_val.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let value = ctx.arg(1).unwrap().as_unsafe_val();
+    let list = filled_vec("repeat", n, value)?;
+    // Unsafe OK: The value is an argument.
+    Ok(unsafe { ctx.new_list(list) })
+}
+
+/// Returns a new list with the element at the given index replaced. The original list is not
+/// modified.
+pub fn list_set(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let (id, idx, val) = list_set_args(&ctx, "list-set")?;
+    let mut list = ctx.vm().objects.get_list(id).clone();
+    list[idx] = val;
+    // Unsafe OK: All values come from the arguments.
+    Ok(unsafe { ctx.new_list(list) })
+}
+
+/// Replace the element at the given index of a list in place. Returns the list.
+pub fn list_set_mut(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let (id, idx, val) = list_set_args(&ctx, "list-set!")?;
+    // Unsafe OK: Garbage collection does not run while the list is modified.
+    unsafe { ctx.vm_mut() }.objects.get_list_mut(id)[idx] = val;
+    // Unsafe OK: The list is an argument.
+    Ok(unsafe { ctx.with_unsafe_val(id.into()) })
+}
+
+/// Get the list, a bounds checked index, and the new value for `list-set` and `list-set!`.
+fn list_set_args(
+    ctx: &NativeFunctionContext<'_>,
+    function: &'static str,
+) -> VmResult<(ValId<ListVal>, usize, UnsafeVal)> {
+    if ctx.arg_count() != 3 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 3,
+            actual: ctx.arg_count(),
+        });
+    }
+    let id = match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::List(id) => id,
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: function,
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let idx = match ctx.arg(1).unwrap().as_unsafe_val() {
+        UnsafeVal::Int(idx) => idx,
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: function,
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let len = ctx.vm().objects.get_list(id).len();
+    match usize::try_from(idx) {
+        Ok(idx) if idx < len => Ok((id, idx, ctx.arg(2).unwrap().as_unsafe_val())),
+        _ => Err(VmError::CustomError {
+            src: None,
+            message: format!("{function} index {idx} is out of bounds for list of length {len}"),
+        }),
+    }
+}
+
+/// Get the list argument at `idx` as a vector of values that may be held while calling back into the
+/// VM.
+fn list_arg_values(
+    ctx: &NativeFunctionContext<'_>,
+    idx: usize,
+    context: &'static str,
+) -> VmResult<ListVal> {
+    match ctx.arg(idx).unwrap().try_list(ctx.vm()) {
+        Ok(list) => Ok(list.iter().map(|v| v.as_unsafe_val()).collect()),
+        Err(v) => Err(VmError::TypeError {
+            src: None,
+            context,
+            expected: UnsafeVal::LIST_TYPE_NAME,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        }),
+    }
+}
+
+/// Split a list into the elements that satisfy a predicate and those that do not.
+/// `(partition pred lst)` returns `(matching non-matching)`.
+pub fn partition(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "partition".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let pred = ctx.arg(0).unwrap().as_unsafe_val();
+    let list = list_arg_values(&ctx, 1, "partition arg(idx=1)")?;
+    let (mut matching, mut non_matching) = (ListVal::new(), ListVal::new());
+    // Unsafe OK: Garbage collection does not run while calling `pred`. The elements are also
+    // reachable through the list argument.
+    let vm = unsafe { ctx.vm_mut() };
+    for val in list {
+        if vm.call_reentrant(pred, &[val])?.is_truthy() {
+            matching.push(val);
+        } else {
+            non_matching.push(val);
+        }
+    }
+    let matching = vm.objects.insert_list(matching);
+    let non_matching = vm.objects.insert_list(non_matching);
+    // Unsafe OK: The lists were just created and contain values from the list argument.
+    Ok(unsafe { ctx.new_list(vec![matching.into(), non_matching.into()]) })
+}
+
+/// Count the number of elements in a list for which `pred` returns a truthy value.
+/// `(count pred list)`.
+pub fn count(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "count".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let pred = ctx.arg(0).unwrap().as_unsafe_val();
+    let list = list_arg_values(&ctx, 1, "count arg(idx=1)")?;
+    // Unsafe OK: Garbage collection does not run while calling `pred`. The elements are also
+    // reachable through the list argument.
+    let vm = unsafe { ctx.vm_mut() };
+    let mut count: i64 = 0;
+    for val in list {
+        if vm.call_reentrant(pred, &[val])?.is_truthy() {
+            count += 1;
+        }
+    }
+    Ok(ValBuilder::new(count.into()))
+}
+
+/// Returns `true` if `pred` returns a truthy value for any element in the list. Elements after the
+/// first match are not checked. `(any? pred list)`.
+pub fn any(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    short_circuit_impl(ctx, "any?", "any? arg(idx=1)", true)
+}
+
+/// Returns `true` if `pred` returns a truthy value for all elements in the list. Elements after the
+/// first non-match are not checked. `(all? pred list)`.
+pub fn all(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    short_circuit_impl(ctx, "all?", "all? arg(idx=1)", false)
+}
+
+/// Call `pred` on each element until it returns a value whose truthiness is `stop_on`. Returns
+/// `stop_on` if this happens or `!stop_on` if all elements were checked.
+fn short_circuit_impl<'a>(
+    mut ctx: NativeFunctionContext<'a>,
+    function: &'static str,
+    list_context: &'static str,
+    stop_on: bool,
+) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let pred = ctx.arg(0).unwrap().as_unsafe_val();
+    let list = list_arg_values(&ctx, 1, list_context)?;
+    // Unsafe OK: Garbage collection does not run while calling `pred`. The elements are also
+    // reachable through the list argument.
+    let vm = unsafe { ctx.vm_mut() };
+    for val in list {
+        if vm.call_reentrant(pred, &[val])?.is_truthy() == stop_on {
+            return Ok(ValBuilder::new(stop_on.into()));
+        }
+    }
+    Ok(ValBuilder::new((!stop_on).into()))
+}
+
+/// Group the elements of a list by the result of a key function. `(group-by key-fn lst)`
+///
+/// The groups are returned as an association list of `(key (elements...))` entries instead of a
+/// struct. Struct fields must be symbols, but keys may be any value such as strings or bools. Keys
+/// are compared with `=` and entries are ordered by the first element of each group. Elements keep
+/// their order within a group.
+pub fn group_by(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "group-by".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let key_fn = ctx.arg(0).unwrap().as_unsafe_val();
+    let list = list_arg_values(&ctx, 1, "group-by arg(idx=1)")?;
+    // Unsafe OK: Keys are kept reachable until they are inserted into the returned list. The
+    // elements are reachable through the list argument.
+    let vm = unsafe { ctx.vm_mut() };
+    let mut groups: Vec<(UnsafeVal, ListVal)> = Vec::new();
+    let mut res = Ok(());
+    for val in list {
+        let key = match vm.call_reentrant(key_fn, &[val]) {
+            Ok(k) => k,
+            Err(err) => {
+                res = Err(err);
+                break;
+            }
+        };
+        match groups.iter_mut().find(|(k, _)| equal_impl(vm, *k, key)) {
+            Some((_, group)) => group.push(val),
+            None => {
+                vm.objects.keep_reachable(key);
+                groups.push((key, vec![val]));
+            }
+        }
+    }
+    let mut ret = ListVal::with_capacity(groups.len());
+    for (key, group) in groups {
+        if res.is_ok() {
+            let group = vm.objects.insert_list(group);
+            let entry = vm.objects.insert_list(vec![key, group.into()]);
+            ret.push(entry.into());
+        }
+        vm.objects.allow_unreachable(key);
+    }
+    res?;
+    // Unsafe OK: The entries were just created.
+    Ok(unsafe { ctx.new_list(ret) })
+}
+
+/// Sort a list by the result of calling a key function on each element. The key function is called
+/// once per element. Keys may be numbers or strings.
+pub fn sort_by(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "sort-by".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let key_fn = ctx.arg(0).unwrap().as_unsafe_val();
+    let list = list_arg_values(&ctx, 1, "sort-by arg(idx=1)")?;
+    // Unsafe OK: Garbage collection does not run while calling `key_fn` or sorting. The elements
+    // are reachable through the list argument.
+    let vm = unsafe { ctx.vm_mut() };
+    let mut keyed = Vec::with_capacity(list.len());
+    for val in list {
+        keyed.push((vm.call_reentrant(key_fn, &[val])?, val));
+    }
+    let mut res = Ok(());
+    keyed.sort_by(
+        |(a, _), (b, _)| match compare_keys(vm, "sort-by key", *a, *b) {
+            Ok(ordering) => ordering,
+            Err(err) => {
+                if res.is_ok() {
+                    res = Err(err);
+                }
+                Ordering::Equal
+            }
+        },
+    );
+    res?;
+    let sorted = keyed.into_iter().map(|(_, val)| val).collect();
+    // Unsafe OK: All values come from a list that is currently an argument.
+    Ok(unsafe { ctx.new_list(sorted) })
+}
+
+/// Get the element of a list with the smallest key. `(min-by key-fn list)` calls `key-fn` on each
+/// element and returns the first element with the smallest key, or `void` if the list is empty.
+pub fn min_by(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    extremum_by_impl(
+        ctx,
+        "min-by",
+        "min-by arg(idx=1)",
+        "min-by key",
+        Ordering::Less,
+    )
+}
+
+/// Get the element of a list with the largest key. `(max-by key-fn list)` calls `key-fn` on each
+/// element and returns the first element with the largest key, or `void` if the list is empty.
+pub fn max_by(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    extremum_by_impl(
+        ctx,
+        "max-by",
+        "max-by arg(idx=1)",
+        "max-by key",
+        Ordering::Greater,
+    )
+}
+
+/// Get the first element whose key compares as `replace_when` against the keys of all the elements
+/// before it.
+fn extremum_by_impl<'a>(
+    mut ctx: NativeFunctionContext<'a>,
+    function: &'static str,
+    list_context: &'static str,
+    key_context: &'static str,
+    replace_when: Ordering,
+) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let key_fn = ctx.arg(0).unwrap().as_unsafe_val();
+    let list = list_arg_values(&ctx, 1, list_context)?;
+    // Unsafe OK: Garbage collection does not run while calling `key_fn`. The elements are
+    // reachable through the list argument.
+    let vm = unsafe { ctx.vm_mut() };
+    let mut best: Option<(UnsafeVal, UnsafeVal)> = None;
+    for val in list {
+        let key = vm.call_reentrant(key_fn, &[val])?;
+        best = match best {
+            Some((best_key, _))
+                if compare_keys(vm, key_context, key, best_key)? == replace_when =>
+            {
+                Some((key, val))
+            }
+            None => Some((key, val)),
+            keep => keep,
+        };
+    }
+    let res = best.map(|(_, val)| val).unwrap_or(UnsafeVal::Void);
+    // Unsafe OK: The value is either void or comes from a list that is currently an argument.
+    Ok(unsafe { ctx.with_unsafe_val(res) })
+}
+
+/// Compare the keys produced by the key function of `sort-by`, `min-by`, or `max-by`. Numbers
+/// are compared like `<` and strings are compared lexicographically.
+fn compare_keys(vm: &Vm, context: &'static str, a: UnsafeVal, b: UnsafeVal) -> VmResult<Ordering> {
+    let ordering = match (a, b) {
+        (UnsafeVal::Int(a), UnsafeVal::Int(b)) => a.cmp(&b),
+        (UnsafeVal::Float(a), UnsafeVal::Float(b)) => a.total_cmp(&b),
+        (UnsafeVal::Float(a), UnsafeVal::Int(b)) => a.total_cmp(&(b as f64)),
+        (UnsafeVal::Int(a), UnsafeVal::Float(b)) => (a as f64).total_cmp(&b),
+        (UnsafeVal::String(a), UnsafeVal::String(b)) => {
+            vm.objects.get_str(a).cmp(vm.objects.get_str(b))
+        }
+        (UnsafeVal::Int(_) | UnsafeVal::Float(_) | UnsafeVal::String(_), v) | (v, _) => {
+            return Err(VmError::TypeError {
+                src: None,
+                context,
+                expected: "int, float, or string",
+                actual: v.type_name(),
+                value: v.format_quoted(vm).to_string(),
+            })
+        }
+    };
+    Ok(ordering)
+}
+
 #[cfg(test)]
 mod tests {
+    use compact_str::CompactString;
+
     use crate::{parser::span::Span, Vm};
 
     use super::*;
@@ -40,6 +549,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(list-length)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 13).with_src("(list-length)".into())),
                 function: "list-length".into(),
                 expected: 1,
                 actual: 0
@@ -48,6 +558,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(list-length (list) 0)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 22).with_src("(list-length (list) 0)".into())),
                 function: "list-length".into(),
                 expected: 1,
                 actual: 2
@@ -89,4 +600,590 @@ mod tests {
             5
         );
     }
+
+    #[test]
+    fn flatten_flattens_all_nested_lists() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(flatten (list 1 (list 2 (list 3))))")
+                .unwrap()
+                .to_string(),
+            "(1 2 3)"
+        );
+        assert_eq!(
+            vm.eval_str("(flatten (list (list) 1 (list (list) \"two\")))")
+                .unwrap()
+                .to_string(),
+            "(1 \"two\")"
+        );
+    }
+
+    #[test]
+    fn flatten_with_depth_only_flattens_up_to_depth() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(flatten (list 1 (list 2 (list 3))) 0)")
+                .unwrap()
+                .to_string(),
+            "(1 (2 (3)))"
+        );
+        assert_eq!(
+            vm.eval_str("(flatten (list 1 (list 2 (list 3))) 1)")
+                .unwrap()
+                .to_string(),
+            "(1 2 (3))"
+        );
+        assert_eq!(
+            vm.eval_str("(flatten (list 1 (list 2 (list 3))) 2)")
+                .unwrap()
+                .to_string(),
+            "(1 2 3)"
+        );
+    }
+
+    #[test]
+    fn flatten_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(flatten)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 9).with_src("(flatten)".into())),
+                function: "flatten".into(),
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(flatten (list) 1 2)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 20).with_src("(flatten (list) 1 2)".into())),
+                function: "flatten".into(),
+                expected: 2,
+                actual: 3
+            }
+        );
+        let src = "(flatten 1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 11).with_src(src.into())),
+                context: "flatten arg(idx=0)",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+        let src = "(flatten (list) -1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 19).with_src(src.into())),
+                context: "flatten arg(idx=1)",
+                expected: "non-negative int",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "-1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn flatten_with_cyclic_list_returns_error() {
+        let mut vm = Vm::default();
+        let list_id = vm.objects.insert_list(vec![1.into()]);
+        vm.objects
+            .get_list_mut(list_id)
+            .push(UnsafeVal::List(list_id));
+        unsafe { vm.register_value("cyclic", list_id) };
+        assert_eq!(
+            vm.eval_str("(flatten cyclic)").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 16).with_src("(flatten cyclic)".into())),
+                message: "flatten encountered a list that contains itself".into()
+            }
+        );
+    }
+
+    #[test]
+    fn distinct_removes_duplicates_and_preserves_order() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(distinct (list 3 1 3 2 1))")
+                .unwrap()
+                .to_string(),
+            "(3 1 2)"
+        );
+        assert_eq!(
+            vm.eval_str("(distinct (list \"a\" \"b\" \"a\"))")
+                .unwrap()
+                .to_string(),
+            "(\"a\" \"b\")"
+        );
+        assert_eq!(
+            vm.eval_str("(distinct (list (list 1 2) (list 1) (list 1 2)))")
+                .unwrap()
+                .to_string(),
+            "((1 2) (1))"
+        );
+        assert_eq!(vm.eval_str("(distinct (list))").unwrap().to_string(), "()");
+    }
+
+    #[test]
+    fn distinct_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(distinct)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 10).with_src("(distinct)".into())),
+                function: "distinct".into(),
+                expected: 1,
+                actual: 0
+            }
+        );
+        let src = "(distinct \"a\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 14).with_src(src.into())),
+                context: "distinct",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"a\"".into(),
+            }
+        );
+    }
+
+    fn first_char(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+        let s = ctx.arg(0).unwrap().try_str(ctx.vm()).unwrap();
+        let first: CompactString = s.chars().take(1).collect();
+        Ok(ctx.new_string(first))
+    }
+
+    #[test]
+    fn partition_splits_list_by_predicate() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(partition even? (list 1 2 3 4 5))")
+                .unwrap()
+                .to_string(),
+            "((2 4) (1 3 5))"
+        );
+        assert_eq!(
+            vm.eval_str("(partition (lambda (x) (< x 3)) (list 1 2 3 4 5))")
+                .unwrap()
+                .to_string(),
+            "((1 2) (3 4 5))"
+        );
+        assert_eq!(
+            vm.eval_str("(partition even? (list))").unwrap().to_string(),
+            "(() ())"
+        );
+    }
+
+    #[test]
+    fn group_by_groups_by_key() {
+        let mut vm = Vm::default().with_native_function("first-char", first_char);
+        assert_eq!(
+            vm.eval_str("(group-by first-char (list \"apple\" \"banana\" \"avocado\" \"blueberry\" \"cherry\"))")
+                .unwrap()
+                .to_string(),
+            "((\"a\" (\"apple\" \"avocado\")) (\"b\" (\"banana\" \"blueberry\")) (\"c\" (\"cherry\")))"
+        );
+        assert_eq!(
+            vm.eval_str("(group-by (lambda (x) (< x 3)) (list 1 5 2 4))")
+                .unwrap()
+                .to_string(),
+            "((true (1 2)) (false (5 4)))"
+        );
+    }
+
+    #[test]
+    fn partition_and_group_by_propagate_errors() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(partition (lambda () true) (list 1))")
+                .unwrap_err(),
+            VmError::ArityError {
+                src: Some(
+                    Span::new(0, 37).with_src("(partition (lambda () true) (list 1))".into())
+                ),
+                function: "".into(),
+                expected: 0,
+                actual: 1
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(group-by list)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src("(group-by list)".into())),
+                function: "group-by".into(),
+                expected: 2,
+                actual: 1
+            }
+        );
+        let src = "(group-by list 1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 17).with_src(src.into())),
+                context: "group-by arg(idx=1)",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn list_set_returns_updated_copy() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define lst (list 1 2 3))").unwrap();
+        assert_eq!(
+            vm.eval_str("(list-set lst 1 \"two\")").unwrap().to_string(),
+            "(1 \"two\" 3)"
+        );
+        assert_eq!(vm.eval_str("lst").unwrap().to_string(), "(1 2 3)");
+    }
+
+    #[test]
+    fn list_set_mut_updates_list_in_place() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define lst (list 1 2 3))").unwrap();
+        assert_eq!(
+            vm.eval_str("(list-set! lst 2 30)").unwrap().to_string(),
+            "(1 2 30)"
+        );
+        assert_eq!(vm.eval_str("lst").unwrap().to_string(), "(1 2 30)");
+    }
+
+    #[test]
+    fn list_set_with_out_of_bounds_index_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(list-set (list 1 2 3) 3 0)").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 27).with_src("(list-set (list 1 2 3) 3 0)".into())),
+                message: "list-set index 3 is out of bounds for list of length 3".into()
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(list-set! (list 1 2 3) -1 0)").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 29).with_src("(list-set! (list 1 2 3) -1 0)".into())),
+                message: "list-set! index -1 is out of bounds for list of length 3".into()
+            }
+        );
+    }
+
+    #[test]
+    fn list_set_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(list-set (list 1) 0)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 21).with_src("(list-set (list 1) 0)".into())),
+                function: "list-set".into(),
+                expected: 3,
+                actual: 2
+            }
+        );
+        let src = "(list-set 1 0 0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 16).with_src(src.into())),
+                context: "list-set",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+        let src = "(list-set! (list 1) 0.0 0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 26).with_src(src.into())),
+                context: "list-set!",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::FLOAT_TYPE_NAME,
+                value: "0.0".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn sort_by_sorts_structs_by_numeric_field() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define people (list (struct 'age 30) (struct 'age 2.5) (struct 'age 12)))")
+            .unwrap();
+        assert_eq!(
+            vm.eval_str("(sort-by (lambda (p) (struct-get p 'age)) people)")
+                .unwrap()
+                .to_string(),
+            "((struct 'age 2.5) (struct 'age 12) (struct 'age 30))"
+        );
+    }
+
+    #[test]
+    fn sort_by_sorts_structs_by_string_field() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define people (list (struct 'name \"bob\") (struct 'name \"carol\") (struct 'name \"alice\")))")
+            .unwrap();
+        assert_eq!(
+            vm.eval_str("(sort-by (lambda (p) (struct-get p 'name)) people)")
+                .unwrap()
+                .to_string(),
+            "((struct 'name \"alice\") (struct 'name \"bob\") (struct 'name \"carol\"))"
+        );
+    }
+
+    #[test]
+    fn sort_by_is_stable_and_calls_key_fn_once_per_element() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define calls (new-box 0))").unwrap();
+        assert_eq!(
+            vm.eval_str(
+                "(sort-by (lambda (x) (box-update! calls inc) (bit-and x 1)) (list 5 2 3 4 1 6))"
+            )
+            .unwrap()
+            .to_string(),
+            "(2 4 6 5 3 1)"
+        );
+        assert_eq!(vm.eval_str("(unbox calls)").unwrap().try_int().unwrap(), 6);
+    }
+
+    #[test]
+    fn sort_by_with_incomparable_keys_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(sort-by (lambda (x) x) (list 1 true))";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 38).with_src(src.into())),
+                context: "sort-by key",
+                expected: "int, float, or string",
+                actual: UnsafeVal::BOOL_TYPE_NAME,
+                value: "true".into(),
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(sort-by list)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 14).with_src("(sort-by list)".into())),
+                function: "sort-by".into(),
+                expected: 2,
+                actual: 1
+            }
+        );
+    }
+
+    #[test]
+    fn max_by_and_min_by_select_by_key() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(max-by string-length (list \"a\" \"abc\" \"xyz\" \"ab\"))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            vm.eval_str("(min-by string-length (list \"ab\" \"a\" \"abc\" \"b\"))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "a"
+        );
+        vm.eval_str(
+            "(define items (list (struct 'name \"a\" 'price 3) (struct 'name \"b\" 'price 7.5) (struct 'name \"c\" 'price 5)))",
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(struct-get (max-by (lambda (s) (struct-get s 'price)) items) 'name)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "b"
+        );
+        assert_eq!(
+            vm.eval_str("(struct-get (min-by (lambda (s) (struct-get s 'price)) items) 'name)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "a"
+        );
+    }
+
+    #[test]
+    fn max_by_and_min_by_with_empty_list_return_void() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str("(max-by string-length (list))")
+            .unwrap()
+            .is_void());
+        assert!(vm
+            .eval_str("(min-by string-length (list))")
+            .unwrap()
+            .is_void());
+    }
+
+    #[test]
+    fn max_by_with_bad_args_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(max-by (lambda (x) x) (list 1 true))";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "max-by key",
+                expected: "int, float, or string",
+                actual: UnsafeVal::BOOL_TYPE_NAME,
+                value: "true".into(),
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(min-by list)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 13).with_src("(min-by list)".into())),
+                function: "min-by".into(),
+                expected: 2,
+                actual: 1
+            }
+        );
+    }
+
+    #[test]
+    fn count_counts_matching_elements() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(count (lambda (x) (= (bit-and x 1) 0)) (list 1 2 3 4 6))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            vm.eval_str("(count (lambda (x) true) (list))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn count_with_bad_args_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(count (lambda (x) true) 1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "count arg(idx=1)",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(count)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 7).with_src("(count)".into())),
+                function: "count".into(),
+                expected: 2,
+                actual: 0
+            }
+        );
+    }
+
+    #[test]
+    fn any_and_all_check_predicate() {
+        let mut vm = Vm::default();
+        for (src, want) in [
+            ("(any? (lambda (x) (< x 0)) (list 1 -2 3))", true),
+            ("(any? (lambda (x) (< x 0)) (list 1 2 3))", false),
+            ("(all? (lambda (x) (< x 4)) (list 1 2 3))", true),
+            ("(all? (lambda (x) (< x 3)) (list 1 2 3))", false),
+        ] {
+            assert_eq!(vm.eval_str(src).unwrap().try_bool().unwrap(), want, "{src}");
+        }
+    }
+
+    #[test]
+    fn any_and_all_with_empty_list_return_identity() {
+        let mut vm = Vm::default();
+        assert!(!vm
+            .eval_str("(any? (lambda (x) true) (list))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(vm
+            .eval_str("(all? (lambda (x) false) (list))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn any_and_all_short_circuit() {
+        let mut vm = Vm::default();
+        // `(< x 0)` is a type error for the string so it must not be reached.
+        assert!(vm
+            .eval_str("(any? (lambda (x) (< x 0)) (list 1 -1 \"not-a-number\"))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(all? (lambda (x) (< x 0)) (list -1 1 \"not-a-number\"))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(vm
+            .eval_str("(all? (lambda (x) (< x 0)) (list -1 \"not-a-number\"))")
+            .is_err());
+    }
+
+    #[test]
+    fn repeat_builds_list_of_copies() {
+        let mut vm = Vm::default();
+        assert_eq!(vm.eval_str("(repeat 0 1)").unwrap().to_string(), "()");
+        assert_eq!(vm.eval_str("(repeat 3 7)").unwrap().to_string(), "(7 7 7)");
+    }
+
+    #[test]
+    fn repeat_with_reference_value_shares_value() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define inner (list 1 2))").unwrap();
+        vm.eval_str("(define lists (repeat 2 inner))").unwrap();
+        vm.eval_str("(list-set! inner 0 10)").unwrap();
+        assert_eq!(vm.eval_str("lists").unwrap().to_string(), "((10 2) (10 2))");
+    }
+
+    #[test]
+    fn repeat_with_negative_count_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(repeat -1 1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "repeat arg(idx=0)",
+                expected: "non-negative int",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "-1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn repeat_with_too_large_count_returns_error() {
+        let mut vm = Vm::default();
+        let err = vm
+            .eval_str("(repeat 1000000000000000000 1)")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("repeat could not allocate 1000000000000000000 elements"),
+            "{err}"
+        );
+    }
 }
@@ -0,0 +1,256 @@
+use crate::{
+    compiler::{node_to_val, write_val_as_source},
+    error::{CompileError, VmError, VmResult},
+    parser::ast::Node,
+    val::{NativeFunctionContext, UnsafeVal, ValBuilder},
+};
+
+/// Evaluate a data structure as code. Symbols are treated as identifiers and lists are treated as
+/// function calls or special forms.
+pub fn eval(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "eval".into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let expression = ctx.arg(0).unwrap().as_unsafe_val();
+    let mut source = String::new();
+    write_val_as_source(ctx.vm(), expression, &mut source).map_err(|err| VmError::CustomError {
+        src: None,
+        message: format!("eval got {err}"),
+    })?;
+    // Unsafe OK: Garbage collection does not run while evaluating `source`.
+    let vm = unsafe { ctx.vm_mut() };
+    let v = vm.eval_reentrant(&source)?;
+    // Unsafe OK: `v` has just been returned so the VM does not have a chance to garbage collect
+    // it.
+    Ok(unsafe { ctx.with_unsafe_val(v) })
+}
+
+/// Parse a string into data without evaluating it. Identifiers become symbols and expressions
+/// become lists.
+pub fn read_string(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "read-string".into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let src = match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::String(id) => ctx.vm().objects.get_str(id).to_string(),
+        arg => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "read-string",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: arg.type_name(),
+                value: arg.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let node = Node::parse_one(&src).map_err(CompileError::from)?;
+    // Unsafe OK: Garbage collection does not run until the value is returned.
+    let v = node_to_val(unsafe { ctx.vm_mut() }, &src, &node);
+    Ok(unsafe { ctx.with_unsafe_val(v) })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{ast::AstParseError, span::Span},
+        Vm,
+    };
+
+    use super::*;
+
+    #[test]
+    fn eval_quoted_arithmetic_returns_result() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(eval (list '+ 1 2 (list '+ 3 4)))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            10
+        );
+    }
+
+    #[test]
+    fn eval_quoted_if_evaluates_taken_branch() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(eval (list 'if (list '< 1 2) \"yes\" \"no\"))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "yes"
+        );
+        assert_eq!(
+            vm.eval_str("(eval (list 'if false \"yes\" \"no\"))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "no"
+        );
+    }
+
+    #[test]
+    fn eval_atom_returns_atom() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define x 42)").unwrap();
+        assert_eq!(vm.eval_str("(eval 'x)").unwrap().try_int().unwrap(), 42);
+        assert_eq!(vm.eval_str("(eval 1.5)").unwrap().try_float().unwrap(), 1.5);
+    }
+
+    #[test]
+    fn eval_can_define_values() {
+        let mut vm = Vm::default();
+        vm.eval_str("(eval (list 'define 'x 10))").unwrap();
+        assert_eq!(vm.eval_str("(+ x 1)").unwrap().try_int().unwrap(), 11);
+    }
+
+    #[test]
+    fn eval_expands_macros_without_disturbing_caller() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (twice x) (list '+ x x))").unwrap();
+        assert_eq!(
+            vm.eval_str("(+ 1 (eval (list 'twice 20)) 1)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            42
+        );
+    }
+
+    #[test]
+    fn eval_with_runaway_recursion_returns_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define (recurse) (eval (list 'recurse)))")
+            .unwrap();
+        assert!(matches!(
+            vm.eval_str("(recurse)").unwrap_err(),
+            VmError::MaximumFunctionCallDepth { .. }
+        ));
+        assert_eq!(vm.eval_str("(eval 1)").unwrap().try_int().unwrap(), 1);
+    }
+
+    #[test]
+    fn eval_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(eval)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 6).with_src("(eval)".into())),
+                function: "eval".into(),
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(eval (new-box 1))").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 18).with_src("(eval (new-box 1))".into())),
+                message: "eval got box<1> of type mutable-box can not be converted to source code"
+                    .into()
+            }
+        );
+    }
+
+    #[test]
+    fn read_string_returns_data_structure() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str(
+                r#"(= (read-string "(+ 1 (foo \"bar\" 2.5 true))")
+                      (list '+ 1 (list 'foo "bar" 2.5 true)))"#
+            )
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(vm
+            .eval_str(r#"(= (read-string "foo") 'foo)"#)
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn read_string_does_not_evaluate() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str(r#"(list-length (read-string "(undefined-function 1 2)"))"#)
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            vm.eval_str(r#"(eval (read-string "(+ 1 2)"))"#)
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn read_string_with_malformed_input_returns_parse_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str(r#"(read-string "(a b]")"#).unwrap_err(),
+            VmError::CompileError {
+                src: Some(Span::new(0, 21).with_src("(read-string \"(a b]\")".into())),
+                error: CompileError::AstError(AstParseError::MismatchedDelimiter {
+                    open: Span::new(0, 1),
+                    close: Span::new(4, 5),
+                })
+            }
+        );
+        assert_eq!(
+            vm.eval_str(r#"(read-string "a b")"#).unwrap_err(),
+            VmError::CompileError {
+                src: Some(Span::new(0, 19).with_src("(read-string \"a b\")".into())),
+                error: CompileError::AstError(AstParseError::UnexpectedTrailingToken(Span::new(
+                    2, 3
+                )))
+            }
+        );
+        assert_eq!(
+            vm.eval_str(r#"(read-string "")"#).unwrap_err(),
+            VmError::CompileError {
+                src: Some(Span::new(0, 16).with_src("(read-string \"\")".into())),
+                error: CompileError::AstError(AstParseError::MissingDatum)
+            }
+        );
+    }
+
+    #[test]
+    fn read_string_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(read-string)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 13).with_src("(read-string)".into())),
+                function: "read-string".into(),
+                expected: 1,
+                actual: 0
+            }
+        );
+        let src = "(read-string 1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 15).with_src(src.into())),
+                context: "read-string",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".to_string(),
+            }
+        );
+    }
+}
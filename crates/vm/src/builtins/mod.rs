@@ -1,41 +1,182 @@
 use crate::{
     error::{VmError, VmResult},
-    val::{NativeFunction, NativeFunctionContext, UnsafeVal, Val, ValBuilder},
+    val::{NativeFunction, NativeFunctionContext, Symbol, UnsafeVal, Val, ValBuilder},
     Vm,
 };
 
+pub mod arrays;
+pub mod bits;
 pub mod boxes;
+pub mod buffers;
+pub mod bytes;
+pub mod io;
 pub mod lists;
+pub mod meta;
 pub mod numbers;
+pub mod seqs;
 pub mod strings;
 pub mod structs;
 pub mod system;
+pub mod vectors;
 
-pub const BUILTINS: &[(&str, NativeFunction)] = &[
-    ("global-values", global_values),
-    ("not", not),
-    ("=", equal),
-    ("+", numbers::add),
-    ("-", numbers::subtract),
-    ("<", numbers::less),
-    ("string-length", strings::string_length),
-    ("string-split", strings::string_split),
-    ("string-join", strings::string_join),
-    ("list", lists::list),
-    ("list-length", lists::list_length),
-    ("struct", structs::strct),
-    ("struct-get", structs::struct_get),
-    ("struct-set!", structs::struct_set),
-    ("new-box", boxes::new_box),
-    ("set-box!", boxes::set_box),
-    ("unbox", boxes::unbox),
-    ("working-directory", system::working_directory),
-    ("command", system::command),
+/// All builtin functions registered by [Vm::new]. Each entry contains the name, the function, and
+/// the number of args if the builtin only accepts an exact number of args. The number of args is
+/// used by the compiler to report arity errors at compile time when builtins are inlined.
+pub const BUILTINS: &[(&str, NativeFunction, Option<usize>)] = &[
+    ("global-values", global_values, Some(0)),
+    ("not", not, Some(1)),
+    ("=", equal, Some(2)),
+    ("!=", not_equal, Some(2)),
+    ("check-equal", check_equal, Some(2)),
+    ("type-of", type_of, Some(1)),
+    ("assert-type", assert_type, Some(2)),
+    ("eval", meta::eval, Some(1)),
+    ("read-string", meta::read_string, Some(1)),
+    ("+", numbers::add, None),
+    ("-", numbers::subtract, None),
+    ("<", numbers::less, None),
+    (">", numbers::greater, None),
+    ("<=", numbers::less_eq, None),
+    (">=", numbers::greater_eq, None),
+    ("inc", numbers::inc, Some(1)),
+    ("dec", numbers::dec, Some(1)),
+    ("float->int", numbers::float_to_int, Some(1)),
+    ("even?", numbers::is_even, Some(1)),
+    ("odd?", numbers::is_odd, Some(1)),
+    ("zero?", numbers::is_zero, Some(1)),
+    ("positive?", numbers::is_positive, Some(1)),
+    ("negative?", numbers::is_negative, Some(1)),
+    ("bit-and", bits::bit_and, Some(2)),
+    ("bit-or", bits::bit_or, Some(2)),
+    ("bit-xor", bits::bit_xor, Some(2)),
+    ("bit-not", bits::bit_not, Some(1)),
+    ("shift-left", bits::shift_left, Some(2)),
+    ("shift-right", bits::shift_right, Some(2)),
+    ("string-length", strings::string_length, Some(1)),
+    ("string-char-length", strings::string_char_length, Some(1)),
+    ("string-split", strings::string_split, None),
+    ("string-join", strings::string_join, None),
+    ("string-lines", strings::string_lines, Some(1)),
+    ("string-words", strings::string_words, Some(1)),
+    ("string-starts-with?", strings::string_starts_with, Some(2)),
+    ("string-ends-with?", strings::string_ends_with, Some(2)),
+    ("->string", strings::to_string, None),
+    ("format-number", strings::format_number, None),
+    ("parse-int", strings::parse_int, Some(2)),
+    ("string->symbol", strings::string_to_symbol, Some(1)),
+    ("symbol->string", strings::symbol_to_string, Some(1)),
+    ("list", lists::list, None),
+    ("list-length", lists::list_length, Some(1)),
+    ("list-set", lists::list_set, Some(3)),
+    ("list-set!", lists::list_set_mut, Some(3)),
+    ("repeat", lists::repeat, Some(2)),
+    ("flatten", lists::flatten, None),
+    ("distinct", lists::distinct, Some(1)),
+    ("vector", vectors::vector, None),
+    ("vector-ref", vectors::vector_ref, Some(2)),
+    ("vector-length", vectors::vector_length, Some(1)),
+    ("list->vector", vectors::list_to_vector, Some(1)),
+    ("vector->list", vectors::vector_to_list, Some(1)),
+    ("array", arrays::array, None),
+    ("make-array", arrays::make_array, None),
+    ("array-ref", arrays::array_ref, Some(2)),
+    ("array-set!", arrays::array_set, Some(3)),
+    ("array-length", arrays::array_length, Some(1)),
+    ("bytes", bytes::bytes, None),
+    ("make-bytes", bytes::make_bytes, None),
+    ("bytes-ref", bytes::bytes_ref, Some(2)),
+    ("bytes-set!", bytes::bytes_set, Some(3)),
+    ("bytes-length", bytes::bytes_length, Some(1)),
+    ("string->bytes", bytes::string_to_bytes, Some(1)),
+    ("bytes->string", bytes::bytes_to_string, Some(1)),
+    ("range-seq", seqs::range_seq, None),
+    ("seq-next", seqs::seq_next, Some(1)),
+    ("seq-done?", seqs::seq_done, Some(1)),
+    ("for-each", seqs::for_each, Some(2)),
+    ("make-buffer", buffers::make_buffer, None),
+    ("buffer-insert!", buffers::buffer_insert, Some(3)),
+    ("buffer-delete!", buffers::buffer_delete, Some(3)),
+    ("buffer->string", buffers::buffer_to_string, Some(1)),
+    ("buffer-length", buffers::buffer_length, Some(1)),
+    ("buffer-line-count", buffers::buffer_line_count, Some(1)),
+    ("partition", lists::partition, Some(2)),
+    ("count", lists::count, Some(2)),
+    ("any?", lists::any, Some(2)),
+    ("all?", lists::all, Some(2)),
+    ("group-by", lists::group_by, Some(2)),
+    ("sort-by", lists::sort_by, Some(2)),
+    ("min-by", lists::min_by, Some(2)),
+    ("max-by", lists::max_by, Some(2)),
+    ("struct", structs::strct, None),
+    ("struct-get", structs::struct_get, Some(2)),
+    ("struct-set!", structs::struct_set, Some(3)),
+    ("new-box", boxes::new_box, Some(1)),
+    ("set-box!", boxes::set_box, Some(2)),
+    ("unbox", boxes::unbox, Some(1)),
+    ("box-update!", boxes::box_update, Some(2)),
+    ("print", io::print, None),
+    ("println", io::println, None),
+    ("with-output-string", io::with_output_string, Some(1)),
+    ("working-directory", system::working_directory, Some(0)),
+    ("command", system::command, None),
 ];
 
+/// Returns the name and number of args of `func` if it is the builtin registered under `name` and
+/// only accepts an exact number of args.
+pub(crate) fn fixed_arity(name: &str, func: NativeFunction) -> Option<(&'static str, usize)> {
+    BUILTINS
+        .iter()
+        .find(|(builtin_name, builtin, _)| {
+            *builtin_name == name && std::ptr::fn_addr_eq(*builtin, func)
+        })
+        .and_then(|(name, _, arity)| Some((*name, (*arity)?)))
+}
+
+/// Returns `true` if `val` is the builtin registered under `name`.
+pub(crate) fn is_builtin(name: &str, val: UnsafeVal) -> bool {
+    let func = match val {
+        UnsafeVal::NativeFunction(func) => func,
+        _ => return false,
+    };
+    BUILTINS.iter().any(|(builtin_name, builtin, _)| {
+        *builtin_name == name && std::ptr::fn_addr_eq(*builtin, func)
+    })
+}
+
+/// Returns an arity error for `function` if `ctx` does not have exactly `expected` args.
+pub(crate) fn check_arity(
+    ctx: &NativeFunctionContext,
+    function: &str,
+    expected: usize,
+) -> VmResult<()> {
+    if ctx.arg_count() != expected {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected,
+            actual: ctx.arg_count(),
+        });
+    }
+    Ok(())
+}
+
+/// Create a `Vec` with `len` copies of `fill` for `function`. Returns an error instead of aborting
+/// if the memory can not be allocated.
+pub(crate) fn filled_vec<T: Clone>(function: &str, len: usize, fill: T) -> VmResult<Vec<T>> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(len)
+        .map_err(|err| VmError::CustomError {
+            src: None,
+            message: format!("{function} could not allocate {len} elements: {err}"),
+        })?;
+    v.resize(len, fill);
+    Ok(v)
+}
+
 pub fn global_values<'a>(mut ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
     if ctx.arg_count() > 0 {
         return Err(VmError::ArityError {
+            src: None,
             function: "global-values".into(),
             expected: 0,
             actual: ctx.arg_count(),
@@ -56,6 +197,7 @@ pub fn global_values<'a>(mut ctx: NativeFunctionContext) -> VmResult<ValBuilder<
 pub fn not<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
     if ctx.arg_count() != 1 {
         return Err(VmError::ArityError {
+            src: None,
             function: "not".into(),
             expected: 1,
             actual: ctx.arg_count(),
@@ -65,6 +207,74 @@ pub fn not<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
     Ok(ValBuilder::new(Val::new_bool(!v.is_truthy())))
 }
 
+/// Returns the name of the type of the argument as a symbol.
+pub fn type_of<'a>(mut ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "type-of".into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let type_name = ctx.arg(0).unwrap().type_name();
+    let symbol = unsafe { ctx.vm_mut() }.get_or_create_symbol(type_name);
+    Ok(ValBuilder::new(unsafe {
+        Val::from_unsafe_val(UnsafeVal::Symbol(symbol))
+    }))
+}
+
+/// Returns the first argument if its type matches the type symbol in the second argument. The type
+/// symbol is the same as what is returned by `type-of`.
+pub fn assert_type(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "assert-type".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let (v, type_arg) = (ctx.arg(0).unwrap(), ctx.arg(1).unwrap());
+    let type_name = type_arg
+        .try_symbol()
+        .ok()
+        .and_then(|sym| ctx.vm().symbol_to_str(sym))
+        .ok_or_else(|| VmError::TypeError {
+            src: None,
+            context: "assert-type arg(idx=1)",
+            expected: UnsafeVal::SYMBOL_TYPE_NAME,
+            actual: type_arg.type_name(),
+            value: type_arg.format_quoted(ctx.vm()).to_string(),
+        })?;
+    let expected = UnsafeVal::TYPE_NAMES
+        .iter()
+        .find(|t| **t == type_name)
+        .ok_or_else(|| VmError::CustomError {
+            src: None,
+            message: format!("assert-type got unknown type {type_name}"),
+        })?;
+    if v.type_name() != *expected {
+        return Err(VmError::TypeError {
+            src: None,
+            context: "assert-type",
+            expected,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        });
+    }
+    let v = v.as_unsafe_val();
+    // Unsafe OK: `v` is an argument so it will not be garbage collected.
+    Ok(unsafe { ctx.with_unsafe_val(v) })
+}
+
+/// Returns `true` if the two args are equal. Lists and structs are compared by their contents and
+/// functions are compared by identity.
+///
+/// Lambdas compiled from the same source text are deduplicated into the same function, so
+/// evaluating the same lambda literal again, like redefining it in a REPL, produces a value that
+/// is `=` to the previous one. Separate lambda literals within a source are not `=` even if their
+/// bodies are identical.
 pub fn equal<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
     match ctx.arg_count() {
         2 => {
@@ -73,6 +283,7 @@ pub fn equal<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
             Ok(Val::new_bool(equal_impl(ctx.vm(), a, b)).into())
         }
         _ => Err(VmError::ArityError {
+            src: None,
             function: "=".into(),
             expected: 2,
             actual: ctx.arg_count(),
@@ -80,55 +291,264 @@ pub fn equal<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
     }
 }
 
+/// Returns `true` if the two args are not equal. This is the opposite of `=`.
+pub fn not_equal<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    match ctx.arg_count() {
+        2 => {
+            let (a, b) = (ctx.arg(0).unwrap(), ctx.arg(1).unwrap());
+            let (a, b) = (a.as_unsafe_val(), b.as_unsafe_val());
+            Ok(Val::new_bool(!equal_impl(ctx.vm(), a, b)).into())
+        }
+        _ => Err(VmError::ArityError {
+            src: None,
+            function: "!=".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        }),
+    }
+}
+
+pub fn check_equal<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    let (a, b) = match ctx.arg_count() {
+        2 => (ctx.arg(0).unwrap(), ctx.arg(1).unwrap()),
+        _ => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "check-equal".into(),
+                expected: 2,
+                actual: ctx.arg_count(),
+            })
+        }
+    };
+    let (a, b) = (a.as_unsafe_val(), b.as_unsafe_val());
+    match find_difference(ctx.vm(), a, b) {
+        None => Ok(Val::new_void().into()),
+        Some(diff) => Err(VmError::CustomError {
+            src: None,
+            message: format!("check-equal failed, {diff}", diff = diff.display(ctx.vm())),
+        }),
+    }
+}
+
 pub fn equal_impl(vm: &Vm, a: UnsafeVal, b: UnsafeVal) -> bool {
+    find_difference(vm, a, b).is_none()
+}
+
+/// A location within a nested value.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PathSegment {
+    Index(usize),
+    Key(Symbol),
+}
+
+/// The first location where two values differ.
+#[derive(Debug, PartialEq)]
+struct Difference {
+    /// The location of the difference, starting from the outermost value.
+    path: Vec<PathSegment>,
+    /// The value on the left side or `None` if it is missing.
+    left: Option<UnsafeVal>,
+    /// The value on the right side or `None` if it is missing.
+    right: Option<UnsafeVal>,
+}
+
+impl Difference {
+    fn new(left: Option<UnsafeVal>, right: Option<UnsafeVal>) -> Difference {
+        Difference {
+            path: Vec::new(),
+            left,
+            right,
+        }
+    }
+
+    /// Nest `self` within `segment`.
+    fn within(mut self, segment: PathSegment) -> Difference {
+        self.path.insert(0, segment);
+        self
+    }
+
+    fn display<'a>(&'a self, vm: &'a Vm) -> impl 'a + std::fmt::Display {
+        DifferenceFormatter { vm, diff: self }
+    }
+}
+
+struct DifferenceFormatter<'a> {
+    vm: &'a Vm,
+    diff: &'a Difference,
+}
+
+impl<'a> std::fmt::Display for DifferenceFormatter<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.diff.path.is_empty() {
+            write!(f, "values differ: ")?;
+        } else {
+            write!(f, "values differ at")?;
+            for (idx, segment) in self.diff.path.iter().enumerate() {
+                let sep = if idx == 0 { " " } else { " -> " };
+                match segment {
+                    PathSegment::Index(i) => write!(f, "{sep}index {i}")?,
+                    PathSegment::Key(k) => write!(
+                        f,
+                        "{sep}key '{name}",
+                        name = self.vm.symbol_to_str(*k).unwrap_or("*unknown-symbol-name*")
+                    )?,
+                }
+            }
+            write!(f, ": ")?;
+        }
+        let write_side = |f: &mut std::fmt::Formatter<'_>, v: Option<UnsafeVal>| match v {
+            Some(v) => write!(f, "{}", v.format_quoted(self.vm)),
+            None => write!(f, "<missing>"),
+        };
+        write_side(f, self.diff.left)?;
+        write!(f, " != ")?;
+        write_side(f, self.diff.right)
+    }
+}
+
+/// Find the first location where `a` and `b` differ or `None` if they are equal.
+fn find_difference(vm: &Vm, a: UnsafeVal, b: UnsafeVal) -> Option<Difference> {
+    find_difference_impl(vm, a, b, &mut Vec::new())
+}
+
+/// Find the first location where `a` and `b` differ or `None` if they are equal.
+///
+/// `ancestors` contains the pairs of containers that are currently being compared and is used to
+/// detect cycles. A pair that is reached again is considered equal since any difference is found
+/// by the outer comparison.
+fn find_difference_impl(
+    vm: &Vm,
+    a: UnsafeVal,
+    b: UnsafeVal,
+    ancestors: &mut Vec<(UnsafeVal, UnsafeVal)>,
+) -> Option<Difference> {
     use crate::val::UnsafeVal::*;
-    match (a, b) {
+    if matches!(
+        (a, b),
+        (List(_), List(_)) | (Vector(_), Vector(_)) | (Array(_), Array(_)) | (Struct(_), Struct(_))
+    ) {
+        if a == b || ancestors.contains(&(a, b)) {
+            return None;
+        }
+        ancestors.push((a, b));
+        let diff = find_container_difference(vm, a, b, ancestors);
+        ancestors.pop();
+        return diff;
+    }
+    let is_equal = match (a, b) {
         (Void, Void) => true,
         (Bool(a), Bool(b)) => a == b,
         (Int(a), Int(b)) => a == b,
         (Float(a), Float(b)) => a == b,
+        (Symbol(a), Symbol(b)) => a == b,
         (String(a), String(b)) => vm.objects.get_str(a) == vm.objects.get_str(b),
-        (List(a), List(b)) => {
-            let a = vm.objects.get_list(a);
-            let b = vm.objects.get_list(b);
-            if a == b {
-                return true;
-            }
-            if a.len() != b.len() {
-                return false;
-            }
-            a.iter().zip(b.iter()).all(|(a, b)| equal_impl(vm, *a, *b))
-        }
+        (Bytes(a), Bytes(b)) => vm.objects.get_bytes(a) == vm.objects.get_bytes(b),
+        (ByteCodeFunction(a), ByteCodeFunction(b)) => a == b,
+        (NativeFunction(a), NativeFunction(b)) => std::ptr::fn_addr_eq(a, b),
+        _ => false,
+    };
+    if is_equal {
+        None
+    } else {
+        Some(Difference::new(Some(a), Some(b)))
+    }
+}
+
+/// Find the first location where the containers `a` and `b`, which have the same type, differ or
+/// `None` if they are equal.
+fn find_container_difference(
+    vm: &Vm,
+    a: UnsafeVal,
+    b: UnsafeVal,
+    ancestors: &mut Vec<(UnsafeVal, UnsafeVal)>,
+) -> Option<Difference> {
+    use crate::val::UnsafeVal::*;
+    match (a, b) {
+        (List(a), List(b)) => find_elements_difference(
+            vm,
+            vm.objects.get_list(a),
+            vm.objects.get_list(b),
+            ancestors,
+        ),
+        (Vector(a), Vector(b)) => find_elements_difference(
+            vm,
+            vm.objects.get_vector(a),
+            vm.objects.get_vector(b),
+            ancestors,
+        ),
+        (Array(a), Array(b)) => find_elements_difference(
+            vm,
+            vm.objects.get_array(a),
+            vm.objects.get_array(b),
+            ancestors,
+        ),
         (Struct(a), Struct(b)) => {
-            if a == b {
-                return true;
-            }
             let a = vm.objects.get_struct(a);
             let b = vm.objects.get_struct(b);
-            if a.len() != b.len() {
-                return false;
-            }
             for (k, v) in a.iter() {
-                let other = match b.get(k) {
-                    Some(other) => other,
-                    None => return false,
+                let diff = match b.get(k) {
+                    Some(other) => find_difference_impl(vm, v, other, ancestors),
+                    None => Some(Difference::new(Some(v), None)),
                 };
-                if !equal_impl(vm, v, other) {
-                    return false;
+                if let Some(diff) = diff {
+                    return Some(diff.within(PathSegment::Key(k)));
                 }
             }
-            true
+            let (k, v) = b.iter().find(|(k, _)| a.get(*k).is_none())?;
+            Some(Difference::new(None, Some(v)).within(PathSegment::Key(k)))
         }
-        (ByteCodeFunction(a), ByteCodeFunction(b)) => a == b,
-        (NativeFunction(a), NativeFunction(b)) => a == b,
-        _ => false,
+        _ => unreachable!(),
+    }
+}
+
+/// Find the first index where the elements of `a` and `b` differ or `None` if they are equal.
+fn find_elements_difference(
+    vm: &Vm,
+    a: &[UnsafeVal],
+    b: &[UnsafeVal],
+    ancestors: &mut Vec<(UnsafeVal, UnsafeVal)>,
+) -> Option<Difference> {
+    if a == b {
+        return None;
+    }
+    for (idx, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+        if let Some(diff) = find_difference_impl(vm, *a, *b, ancestors) {
+            return Some(diff.within(PathSegment::Index(idx)));
+        }
+    }
+    if a.len() == b.len() {
+        return None;
     }
+    let idx = a.len().min(b.len());
+    let diff = Difference::new(a.get(idx).copied(), b.get(idx).copied());
+    Some(diff.within(PathSegment::Index(idx)))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::parser::span::Span;
+
     use super::*;
 
+    #[test]
+    fn fixed_arity_builtins_match_runtime_arity_errors() {
+        let mut vm = Vm::default();
+        for (name, func, arity) in BUILTINS.iter().copied() {
+            assert_eq!(fixed_arity(name, func), arity.map(|arity| (name, arity)));
+            let expected = match arity {
+                Some(arity) => arity,
+                None => continue,
+            };
+            let args = " 1".repeat(expected + 1);
+            match vm.eval_str(&format!("({name}{args})")).unwrap_err() {
+                VmError::ArityError {
+                    expected: actual, ..
+                } => assert_eq!(actual, expected, "{name}"),
+                err => panic!("{name} returned {err:?}"),
+            }
+        }
+    }
+
     #[test]
     fn global_values_returns_list() {
         let mut vm = Vm::default();
@@ -147,6 +567,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(global-values 0)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 17).with_src("(global-values 0)".into())),
                 function: "global-values".into(),
                 expected: 0,
                 actual: 1
@@ -160,6 +581,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(=)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 3).with_src("(=)".into())),
                 function: "=".into(),
                 expected: 2,
                 actual: 0,
@@ -168,6 +590,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(= 1)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 5).with_src("(= 1)".into())),
                 function: "=".into(),
                 expected: 2,
                 actual: 1,
@@ -176,6 +599,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(= 1 2 3)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 9).with_src("(= 1 2 3)".into())),
                 function: "=".into(),
                 expected: 2,
                 actual: 3,
@@ -211,6 +635,22 @@ mod tests {
         assert!(vm.eval_str("(= void void)").unwrap().try_bool().unwrap());
     }
 
+    #[test]
+    fn equal_with_lambdas_compares_lambdas_compiled_from_same_source() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define f (lambda () 1))").unwrap();
+        vm.eval_str("(define g f)").unwrap();
+        vm.eval_str("(define f (lambda () 1))").unwrap();
+        assert!(vm.eval_str("(= f g)").unwrap().try_bool().unwrap());
+        assert!(!vm
+            .eval_str("(= (lambda () 1) (lambda () 1))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        vm.eval_str("(define h (lambda () 1))").unwrap();
+        assert!(!vm.eval_str("(= f h)").unwrap().try_bool().unwrap());
+    }
+
     #[test]
     fn equal_with_same_struct_ref_returns_true() {
         let mut vm = Vm::default();
@@ -234,6 +674,24 @@ mod tests {
             .unwrap());
     }
 
+    #[test]
+    fn equal_with_self_referential_values_does_not_overflow() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define a (list 1)) (define b (list 1)) (list-set! a 0 a) (list-set! b 0 b)")
+            .unwrap();
+        assert!(vm.eval_str("(= a b)").unwrap().try_bool().unwrap());
+        vm.eval_str("(define c (list 1 2)) (list-set! c 0 c)")
+            .unwrap();
+        assert!(!vm.eval_str("(= a c)").unwrap().try_bool().unwrap());
+        vm.eval_str("(define s (struct 'a 1)) (define t (struct 'a 1))")
+            .unwrap();
+        vm.eval_str("(struct-set! s 'self s) (struct-set! t 'self t)")
+            .unwrap();
+        assert!(vm.eval_str("(= s t)").unwrap().try_bool().unwrap());
+        vm.eval_str("(struct-set! t 'a 2)").unwrap();
+        assert!(!vm.eval_str("(= s t)").unwrap().try_bool().unwrap());
+    }
+
     #[test]
     fn equal_with_different_items_returns_false() {
         let mut vm = Vm::default();
@@ -266,6 +724,65 @@ mod tests {
         assert!(!vm.eval_str("(= + <)").unwrap().try_bool().unwrap());
     }
 
+    #[test]
+    fn not_equal_with_wrong_number_of_args_returns_arity_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(!= 1)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 6).with_src("(!= 1)".into())),
+                function: "!=".into(),
+                expected: 2,
+                actual: 1,
+            },
+        );
+        assert_eq!(
+            vm.eval_str("(!= 1 2 3)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 10).with_src("(!= 1 2 3)".into())),
+                function: "!=".into(),
+                expected: 2,
+                actual: 3,
+            },
+        );
+    }
+
+    #[test]
+    fn not_equal_with_equal_items_returns_false() {
+        let mut vm = Vm::default();
+        assert!(!vm.eval_str("(!= false false)").unwrap().try_bool().unwrap());
+        assert!(!vm.eval_str("(!= 1 1)").unwrap().try_bool().unwrap());
+        assert!(!vm
+            .eval_str("(!= (list \"list\") (list \"list\"))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(!= (struct 'field 1) (struct 'field 1))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm.eval_str("(!= + +)").unwrap().try_bool().unwrap());
+    }
+
+    #[test]
+    fn not_equal_with_different_items_returns_true() {
+        let mut vm = Vm::default();
+        assert!(vm.eval_str("(!= 1 1.0)").unwrap().try_bool().unwrap());
+        assert!(vm.eval_str("(!= 1 2)").unwrap().try_bool().unwrap());
+        assert!(vm
+            .eval_str("(!= \"string\" \"other\")")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(vm
+            .eval_str("(!= (list \"list\" 1) (list \"list\" 2))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(vm.eval_str("(!= + <)").unwrap().try_bool().unwrap());
+    }
+
     #[test]
     fn not_inverts_bool() {
         let mut vm = Vm::default();
@@ -279,6 +796,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(not)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 5).with_src("(not)".into())),
                 function: "not".into(),
                 expected: 1,
                 actual: 0,
@@ -287,6 +805,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(not true false)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 16).with_src("(not true false)".into())),
                 function: "not".into(),
                 expected: 1,
                 actual: 2,
@@ -310,4 +829,164 @@ mod tests {
         assert!(!vm.eval_str("(not not)").unwrap().try_bool().unwrap());
         assert!(!vm.eval_str("(not (list))").unwrap().try_bool().unwrap());
     }
+
+    #[test]
+    fn type_of_returns_type_name_as_symbol() {
+        let mut vm = Vm::default();
+        for (src, want) in [
+            ("(type-of 1)", "'int"),
+            ("(type-of 1.5)", "'float"),
+            ("(type-of \"\")", "'string"),
+            ("(type-of (list))", "'list"),
+            ("(type-of type-of)", "'function"),
+            ("(type-of 'int)", "'symbol"),
+        ] {
+            assert_eq!(vm.eval_str(src).unwrap().to_string(), want, "{src}");
+        }
+        assert!(vm
+            .eval_str("(= (type-of 1) 'int)")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn assert_type_with_matching_type_returns_value() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(assert-type 10 'int)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            10
+        );
+        assert_eq!(
+            vm.eval_str("(assert-type \"hello\" (type-of \"\"))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn assert_type_with_mismatched_type_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(assert-type 10 'string)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 24).with_src(src.into())),
+                context: "assert-type",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "10".to_string(),
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(assert-type 10 'not-a-type)").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 28).with_src("(assert-type 10 'not-a-type)".into())),
+                message: "assert-type got unknown type not-a-type".into()
+            }
+        );
+        let src = "(assert-type 10 \"int\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 22).with_src(src.into())),
+                context: "assert-type arg(idx=1)",
+                expected: UnsafeVal::SYMBOL_TYPE_NAME,
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"int\"".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn check_equal_with_equal_values_returns_void() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str("(check-equal (list 1 \"two\" (struct 'x 3)) (list 1 \"two\" (struct 'x 3)))")
+            .unwrap()
+            .is_void());
+    }
+
+    #[test]
+    fn check_equal_with_different_lists_reports_differing_index() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(check-equal (list 1 2 3) (list 1 4 3))")
+                .unwrap_err(),
+            VmError::CustomError {
+                src: Some(
+                    Span::new(0, 39).with_src("(check-equal (list 1 2 3) (list 1 4 3))".into())
+                ),
+                message: "check-equal failed, values differ at index 1: 2 != 4".into()
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(check-equal (list 1 2) (list 1 2 3))")
+                .unwrap_err(),
+            VmError::CustomError {
+                src: Some(
+                    Span::new(0, 37).with_src("(check-equal (list 1 2) (list 1 2 3))".into())
+                ),
+                message: "check-equal failed, values differ at index 2: <missing> != 3".into()
+            }
+        );
+    }
+
+    #[test]
+    fn check_equal_with_different_structs_reports_differing_key() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str(
+                "(check-equal (struct 'field (list 1 \"a\")) (struct 'field (list 1 \"b\")))"
+            )
+            .unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 71).with_src("(check-equal (struct 'field (list 1 \"a\")) (struct 'field (list 1 \"b\")))".into())),
+                message:
+                    "check-equal failed, values differ at key 'field -> index 1: \"a\" != \"b\""
+                        .into()
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(check-equal (struct 'a 1) (struct 'b 1))")
+                .unwrap_err(),
+            VmError::CustomError {
+                src: Some(
+                    Span::new(0, 41).with_src("(check-equal (struct 'a 1) (struct 'b 1))".into())
+                ),
+                message: "check-equal failed, values differ at key 'a: 1 != <missing>".into()
+            }
+        );
+    }
+
+    #[test]
+    fn check_equal_with_different_atoms_reports_values() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(check-equal 1 \"1\")").unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 19).with_src("(check-equal 1 \"1\")".into())),
+                message: "check-equal failed, values differ: 1 != \"1\"".into()
+            }
+        );
+    }
+
+    #[test]
+    fn check_equal_with_wrong_number_of_args_returns_arity_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(check-equal 1)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src("(check-equal 1)".into())),
+                function: "check-equal".into(),
+                expected: 2,
+                actual: 1,
+            }
+        );
+    }
 }
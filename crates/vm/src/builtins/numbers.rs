@@ -18,6 +18,20 @@ impl From<Number> for Val<'static> {
     }
 }
 
+fn not_a_number_error(vm: &Vm, context: &'static str, v: Val) -> VmError {
+    VmError::TypeError {
+        src: None,
+        context,
+        expected: "int or float",
+        actual: v.type_name(),
+        value: v.format_quoted(vm).to_string(),
+    }
+}
+
+/// Sum all the args.
+///
+/// Integer arithmetic is checked. If an intermediate integer sum does not fit within an `i64`,
+/// then [VmError::Overflow] is returned instead of silently wrapping or promoting to a float.
 fn add_impl<'a>(
     vm: &Vm,
     context: &'static str,
@@ -29,19 +43,15 @@ fn add_impl<'a>(
         // Unsafe OK: Using field values right away without any garbage collection.
         // TODO: Consider getting the number through [Val] directly.
         match arg.as_unsafe_val() {
-            UnsafeVal::Int(x) => int_sum += x,
+            UnsafeVal::Int(x) => {
+                int_sum = int_sum
+                    .checked_add(x)
+                    .ok_or(VmError::Overflow { src: None, context })?
+            }
             UnsafeVal::Float(x) => {
                 float_sum += x;
             }
-            _ => {
-                return Err(VmError::TypeError {
-                    src: None,
-                    context,
-                    expected: "int or float",
-                    actual: arg.type_name(),
-                    value: arg.format_quoted(vm).to_string(),
-                })
-            }
+            _ => return Err(not_a_number_error(vm, context, arg)),
         }
     }
     if float_sum != 0.0 {
@@ -58,15 +68,45 @@ pub fn add(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
 
 fn negate(vm: &Vm, context: &'static str, v: Val) -> VmResult<Number> {
     match v.as_unsafe_val() {
-        UnsafeVal::Int(x) => Ok(Number::Int(-x)),
+        UnsafeVal::Int(x) => x
+            .checked_neg()
+            .map(Number::Int)
+            .ok_or(VmError::Overflow { src: None, context }),
         UnsafeVal::Float(x) => Ok(Number::Float(-x)),
-        _ => Err(VmError::TypeError {
-            src: None,
-            context,
-            expected: "int or float",
-            actual: v.type_name(),
-            value: v.format_quoted(vm).to_string(),
-        }),
+        _ => Err(not_a_number_error(vm, context, v)),
+    }
+}
+
+/// Subtract all values in `rest` from `first`.
+///
+/// Similar to [add_impl], integer arithmetic is checked and returns [VmError::Overflow] if the
+/// result does not fit within an `i64`.
+fn subtract_impl<'a>(
+    vm: &Vm,
+    context: &'static str,
+    first: Val<'a>,
+    rest: impl 'a + Iterator<Item = Val<'a>>,
+) -> VmResult<Number> {
+    let (mut int_diff, mut float_diff) = match first.as_unsafe_val() {
+        UnsafeVal::Int(x) => (x, 0.0),
+        UnsafeVal::Float(x) => (0, x),
+        _ => return Err(not_a_number_error(vm, context, first)),
+    };
+    for arg in rest {
+        match arg.as_unsafe_val() {
+            UnsafeVal::Int(x) => {
+                int_diff = int_diff
+                    .checked_sub(x)
+                    .ok_or(VmError::Overflow { src: None, context })?
+            }
+            UnsafeVal::Float(x) => float_diff -= x,
+            _ => return Err(not_a_number_error(vm, context, arg)),
+        }
+    }
+    if float_diff != 0.0 {
+        Ok(Number::Float(float_diff + int_diff as f64))
+    } else {
+        Ok(Number::Int(int_diff))
     }
 }
 
@@ -74,6 +114,7 @@ pub fn subtract<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
     let vm = ctx.vm();
     match ctx.arg_count() {
         0 => Err(VmError::ArityError {
+            src: None,
             function: "-".into(),
             expected: 1,
             actual: 0,
@@ -82,32 +123,37 @@ pub fn subtract<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
         _ => {
             let mut args = ctx.args();
             let first = args.next().unwrap();
-            let rest_sum = add_impl(vm, "-", args)?;
-            let negated_rest = negate(vm, "-", rest_sum.into())?;
-            let ans = add_impl(vm, "-", [first, negated_rest.into()].into_iter())?;
+            let ans = subtract_impl(vm, "-", first, args)?;
             Ok(ValBuilder::new(ans.into()))
         }
     }
 }
 
-fn less_two_impl(vm: &Vm, a: Val, b: Val) -> VmResult<bool> {
+/// Compare two numbers. Returns `None` if the numbers can not be ordered, like when comparing
+/// against `NaN`.
+fn compare_two_impl(
+    vm: &Vm,
+    context: &'static str,
+    a: Val,
+    b: Val,
+) -> VmResult<Option<std::cmp::Ordering>> {
     // Unsafe OK: Only gets basic types like int and float.
     let (a, b) = (a.as_unsafe_val(), b.as_unsafe_val());
     match (a, b) {
-        (UnsafeVal::Int(a), UnsafeVal::Int(b)) => Ok(a < b),
-        (UnsafeVal::Float(a), UnsafeVal::Float(b)) => Ok(a < b),
-        (UnsafeVal::Float(a), UnsafeVal::Int(b)) => Ok(a < (b as f64)),
-        (UnsafeVal::Int(a), UnsafeVal::Float(b)) => Ok((a as f64) < b),
+        (UnsafeVal::Int(a), UnsafeVal::Int(b)) => Ok(Some(a.cmp(&b))),
+        (UnsafeVal::Float(a), UnsafeVal::Float(b)) => Ok(a.partial_cmp(&b)),
+        (UnsafeVal::Float(a), UnsafeVal::Int(b)) => Ok(a.partial_cmp(&(b as f64))),
+        (UnsafeVal::Int(a), UnsafeVal::Float(b)) => Ok((a as f64).partial_cmp(&b)),
         (_, UnsafeVal::Int(_)) | (_, UnsafeVal::Float(_)) => Err(VmError::TypeError {
             src: None,
-            context: "<",
+            context,
             expected: "int or float",
             actual: a.type_name(),
             value: a.format_quoted(vm).to_string(),
         }),
         (_, _) => Err(VmError::TypeError {
             src: None,
-            context: "<",
+            context,
             expected: "int or float",
             actual: b.type_name(),
             value: b.format_quoted(vm).to_string(),
@@ -115,31 +161,179 @@ fn less_two_impl(vm: &Vm, a: Val, b: Val) -> VmResult<bool> {
     }
 }
 
-pub fn less_impl<'a>(
-    vm: &Vm,
-    next: Val<'a>,
-    mut rest: impl 'a + Iterator<Item = Val<'a>>,
-) -> VmResult<bool> {
-    let x = match rest.next() {
+/// Returns `true` if `holds` is `true` for the ordering of each adjacent pair of args. Calls with
+/// fewer than 2 args are trivially `true`.
+fn compare_impl<'a>(
+    ctx: NativeFunctionContext,
+    context: &'static str,
+    holds: impl Fn(std::cmp::Ordering) -> bool,
+) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() == 2 {
+        let (a, b) = (ctx.arg(0).unwrap(), ctx.arg(1).unwrap());
+        let res = compare_two_impl(ctx.vm(), context, a, b)?.is_some_and(holds);
+        return Ok(Val::new_bool(res).into());
+    }
+    let mut args = ctx.args();
+    let mut prev = match args.next() {
         Some(x) => x,
-        None => return Ok(true),
+        None => return Ok(Val::new_bool(true).into()),
     };
-    if !less_two_impl(vm, next, x)? {
-        return Ok(false);
+    for next in args {
+        if !compare_two_impl(ctx.vm(), context, prev, next)?.is_some_and(&holds) {
+            return Ok(Val::new_bool(false).into());
+        }
+        prev = next;
     }
-    less_impl(vm, x, rest)
+    Ok(Val::new_bool(true).into())
 }
 
+/// Returns `true` if the args are strictly increasing.
 pub fn less<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
-    let mut args = ctx.args();
-    let first = match args.next() {
-        Some(x) => x,
-        None => return Ok(Val::new_bool(true).into()),
+    compare_impl(ctx, "<", std::cmp::Ordering::is_lt)
+}
+
+/// Returns `true` if the args are strictly decreasing.
+pub fn greater<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    compare_impl(ctx, ">", std::cmp::Ordering::is_gt)
+}
+
+/// Returns `true` if the args are non-decreasing.
+pub fn less_eq<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    compare_impl(ctx, "<=", std::cmp::Ordering::is_le)
+}
+
+/// Returns `true` if the args are non-increasing.
+pub fn greater_eq<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    compare_impl(ctx, ">=", std::cmp::Ordering::is_ge)
+}
+
+fn add_one_impl<'a>(
+    ctx: NativeFunctionContext<'a>,
+    function: &'static str,
+    delta: i64,
+) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let v = ctx.arg(0).unwrap();
+    let res = match v.as_unsafe_val() {
+        UnsafeVal::Int(x) => x
+            .checked_add(delta)
+            .map(Number::Int)
+            .ok_or(VmError::Overflow {
+                src: None,
+                context: function,
+            })?,
+        UnsafeVal::Float(x) => Number::Float(x + delta as f64),
+        _ => return Err(not_a_number_error(ctx.vm(), function, v)),
+    };
+    Ok(ValBuilder::new(res.into()))
+}
+
+pub fn inc(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    add_one_impl(ctx, "inc", 1)
+}
+
+pub fn dec(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    add_one_impl(ctx, "dec", -1)
+}
+
+/// Evaluate a predicate on the single argument of `ctx`. If `float_pred` is `None`, then only ints
+/// are accepted.
+fn number_predicate_impl<'a>(
+    ctx: NativeFunctionContext<'a>,
+    function: &'static str,
+    int_pred: impl Fn(i64) -> bool,
+    float_pred: Option<fn(f64) -> bool>,
+) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let v = ctx.arg(0).unwrap();
+    let res = match (v.as_unsafe_val(), float_pred) {
+        (UnsafeVal::Int(x), _) => int_pred(x),
+        (UnsafeVal::Float(x), Some(float_pred)) => float_pred(x),
+        (_, None) => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: function,
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+        (_, Some(_)) => return Err(not_a_number_error(ctx.vm(), function, v)),
     };
-    let res = less_impl(ctx.vm(), first, args)?;
     Ok(Val::new_bool(res).into())
 }
 
+pub fn is_even(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    number_predicate_impl(ctx, "even?", |x| x % 2 == 0, None)
+}
+
+pub fn is_odd(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    number_predicate_impl(ctx, "odd?", |x| x % 2 != 0, None)
+}
+
+pub fn is_zero(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    number_predicate_impl(ctx, "zero?", |x| x == 0, Some(|x| x == 0.0))
+}
+
+pub fn is_positive(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    number_predicate_impl(ctx, "positive?", |x| x > 0, Some(|x| x > 0.0))
+}
+
+pub fn is_negative(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    number_predicate_impl(ctx, "negative?", |x| x < 0, Some(|x| x < 0.0))
+}
+
+/// Convert a float with no fractional part into an int. Floats with a fractional part are an error
+/// instead of being truncated. Ints are returned as is.
+pub fn float_to_int(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 1 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "float->int".into(),
+            expected: 1,
+            actual: ctx.arg_count(),
+        });
+    }
+    let v = ctx.arg(0).unwrap();
+    let res = match v.as_unsafe_val() {
+        UnsafeVal::Int(x) => x,
+        // `i64::MAX as f64` rounds up to 2^63 so the upper bound is exclusive.
+        UnsafeVal::Float(x) if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 => {
+            x as i64
+        }
+        UnsafeVal::Float(x) if x.fract() == 0.0 => {
+            return Err(VmError::Overflow {
+                src: None,
+                context: "float->int",
+            })
+        }
+        _ => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "float->int",
+                expected: "whole number",
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    Ok(Val::new_int(res).into())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::parser::span::Span;
@@ -201,6 +395,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(-)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 3).with_src("(-)".into())),
                 function: "-".into(),
                 expected: 1,
                 actual: 0,
@@ -321,4 +516,255 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn comparisons_hold_pairwise_across_all_args() {
+        let mut vm = Vm::default();
+        for (src, want) in [
+            ("(< 1 2 3)", true),
+            ("(< 1 3 2)", false),
+            ("(< 1 1 2)", false),
+            ("(> 3 2 1)", true),
+            ("(> 3 1 2)", false),
+            ("(> 3 3)", false),
+            ("(<= 1 1 2.0 2)", true),
+            ("(<= 1 2 1)", false),
+            ("(>= 3 3 2.5 2)", true),
+            ("(>= 3 4)", false),
+            ("(> 1)", true),
+            ("(>=)", true),
+        ] {
+            assert_eq!(vm.eval_str(src).unwrap().try_bool().unwrap(), want, "{src}");
+        }
+    }
+
+    #[test]
+    fn comparisons_with_nonumber_args_is_type_error() {
+        let mut vm = Vm::default();
+        let src = "(>= 2 \"blue\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 13).with_src(src.into())),
+                context: ">=",
+                expected: "int or float",
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"blue\"".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn add_with_integer_overflow_returns_error() {
+        let mut vm = Vm::default();
+        let src = format!("(+ {} 1)", i64::MAX);
+        assert_eq!(
+            vm.eval_str(&src).unwrap_err(),
+            VmError::Overflow {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.as_str().into())),
+                context: "+",
+            }
+        );
+        let src = format!("(+ {} {})", i64::MAX - 1, 1);
+        assert_eq!(vm.eval_str(&src).unwrap().try_int().unwrap(), i64::MAX);
+    }
+
+    #[test]
+    fn subtract_with_integer_overflow_returns_error() {
+        let mut vm = Vm::default();
+        let src = format!("(- {} 2)", i64::MIN + 1);
+        assert_eq!(
+            vm.eval_str(&src).unwrap_err(),
+            VmError::Overflow {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.as_str().into())),
+                context: "-",
+            }
+        );
+        let src = format!("(- 0 {} 1)", i64::MAX);
+        assert_eq!(vm.eval_str(&src).unwrap().try_int().unwrap(), i64::MIN);
+        let src = format!("(- (- 0 {} 1))", i64::MAX);
+        assert!(matches!(
+            vm.eval_str(&src).unwrap_err(),
+            VmError::Overflow { context: "-", .. }
+        ));
+    }
+
+    #[test]
+    fn inc_and_dec_preserve_number_type() {
+        let mut vm = Vm::default();
+        assert_eq!(vm.eval_str("(inc 1)").unwrap().try_int().unwrap(), 2);
+        assert_eq!(vm.eval_str("(dec 1)").unwrap().try_int().unwrap(), 0);
+        assert_eq!(vm.eval_str("(inc 1.5)").unwrap().try_float().unwrap(), 2.5);
+        assert_eq!(vm.eval_str("(dec 1.5)").unwrap().try_float().unwrap(), 0.5);
+    }
+
+    #[test]
+    fn inc_and_dec_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(inc)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 5).with_src("(inc)".into())),
+                function: "inc".into(),
+                expected: 1,
+                actual: 0,
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(dec 1 2)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 9).with_src("(dec 1 2)".into())),
+                function: "dec".into(),
+                expected: 1,
+                actual: 2,
+            }
+        );
+        let src = "(inc \"1\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 9).with_src(src.into())),
+                context: "inc",
+                expected: "int or float",
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"1\"".into(),
+            }
+        );
+        let src = format!("(dec {})", i64::MIN);
+        assert!(matches!(
+            vm.eval_str(&src).unwrap_err(),
+            VmError::Overflow { context: "dec", .. }
+        ));
+    }
+
+    #[test]
+    fn number_predicates_return_expected_values() {
+        let mut vm = Vm::default();
+        // (input, even?, odd?, zero?, positive?, negative?)
+        let ints = [
+            ("-3", false, true, false, false, true),
+            ("-2", true, false, false, false, true),
+            ("0", true, false, true, false, false),
+            ("1", false, true, false, true, false),
+            ("4", true, false, false, true, false),
+        ];
+        for (input, even, odd, zero, positive, negative) in ints {
+            for (predicate, want) in [
+                ("even?", even),
+                ("odd?", odd),
+                ("zero?", zero),
+                ("positive?", positive),
+                ("negative?", negative),
+            ] {
+                let src = format!("({predicate} {input})");
+                assert_eq!(
+                    vm.eval_str(&src).unwrap().try_bool().unwrap(),
+                    want,
+                    "{src}"
+                );
+            }
+        }
+        // (input, zero?, positive?, negative?)
+        let floats = [
+            ("-1.5", false, false, true),
+            ("0.0", true, false, false),
+            ("-0.0", true, false, false),
+            ("0.5", false, true, false),
+        ];
+        for (input, zero, positive, negative) in floats {
+            for (predicate, want) in [
+                ("zero?", zero),
+                ("positive?", positive),
+                ("negative?", negative),
+            ] {
+                let src = format!("({predicate} {input})");
+                assert_eq!(
+                    vm.eval_str(&src).unwrap().try_bool().unwrap(),
+                    want,
+                    "{src}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn number_predicates_with_wrong_args_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(even? 2.0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 11).with_src(src.into())),
+                context: "even?",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::FLOAT_TYPE_NAME,
+                value: "2.0".into(),
+            }
+        );
+        let src = "(zero? \"0\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 11).with_src(src.into())),
+                context: "zero?",
+                expected: "int or float",
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"0\"".into(),
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(odd?)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 6).with_src("(odd?)".into())),
+                function: "odd?".into(),
+                expected: 1,
+                actual: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn float_to_int_converts_whole_floats() {
+        let mut vm = Vm::default();
+        let v = vm.eval_str("(float->int 2.0)").unwrap();
+        assert_eq!(v.try_int().unwrap(), 2);
+        drop(v);
+        assert_eq!(
+            vm.eval_str("(float->int -3)").unwrap().try_int().unwrap(),
+            -3
+        );
+    }
+
+    #[test]
+    fn float_to_int_with_fractional_part_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(float->int 2.5)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 16).with_src(src.into())),
+                context: "float->int",
+                expected: "whole number",
+                actual: UnsafeVal::FLOAT_TYPE_NAME,
+                value: "2.5".to_string(),
+            }
+        );
+        let src = "(float->int 1e300)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::Overflow {
+                src: Some(Span::new(0, 18).with_src(src.into())),
+                context: "float->int",
+            }
+        );
+        assert_eq!(
+            vm.eval_str("(float->int)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 12).with_src("(float->int)".into())),
+                function: "float->int".into(),
+                expected: 1,
+                actual: 0
+            }
+        );
+    }
 }
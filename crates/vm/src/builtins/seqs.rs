@@ -0,0 +1,281 @@
+use crate::{
+    builtins::check_arity,
+    error::{VmError, VmResult},
+    val::{custom::CustomValMut, CustomType, NativeFunctionContext, UnsafeVal, Val, ValBuilder},
+};
+
+/// A lazy sequence of ints. Values are produced one at a time so iterating over a range does not
+/// require building the full list.
+#[derive(Debug)]
+pub struct RangeSeq {
+    next: i64,
+    end: i64,
+    step: i64,
+}
+
+impl CustomType for RangeSeq {}
+
+impl std::fmt::Display for RangeSeq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<range-seq next={} end={} step={}>",
+            self.next, self.end, self.step
+        )
+    }
+}
+
+impl RangeSeq {
+    /// Returns `true` if there are no more values in the sequence.
+    fn is_done(&self) -> bool {
+        if self.step > 0 {
+            self.next >= self.end
+        } else {
+            self.next <= self.end
+        }
+    }
+}
+
+impl Iterator for RangeSeq {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        if self.is_done() {
+            return None;
+        }
+        let ret = self.next;
+        // Saturating OK: Saturating at the bounds still ends the sequence as `end` is within i64.
+        self.next = self.next.saturating_add(self.step);
+        Some(ret)
+    }
+}
+
+fn int_arg(ctx: &NativeFunctionContext, idx: usize) -> VmResult<i64> {
+    let v = ctx.arg(idx).unwrap();
+    v.try_int().map_err(|v| VmError::TypeError {
+        src: None,
+        context: "range-seq",
+        expected: UnsafeVal::INT_TYPE_NAME,
+        actual: v.type_name(),
+        value: v.format_quoted(ctx.vm()).to_string(),
+    })
+}
+
+fn range_seq_arg<'a>(
+    ctx: &'a NativeFunctionContext,
+    idx: usize,
+    context: &'static str,
+) -> VmResult<CustomValMut<'a, RangeSeq>> {
+    let v = ctx.arg(idx).unwrap();
+    v.try_custom_mut(ctx.vm()).map_err(|_| VmError::TypeError {
+        src: None,
+        context,
+        expected: "range-seq",
+        actual: v.type_name(),
+        value: v.format_quoted(ctx.vm()).to_string(),
+    })
+}
+
+/// Create a lazy sequence of ints.
+///
+/// - `(range-seq end)` produces `0` up to, but not including, `end`.
+/// - `(range-seq start end)` produces `start` up to, but not including, `end`.
+/// - `(range-seq start end step)` is similar but increments by `step` on each iteration.
+pub fn range_seq(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let (start, end, step) = match ctx.arg_count() {
+        1 => (0, int_arg(&ctx, 0)?, 1),
+        2 => (int_arg(&ctx, 0)?, int_arg(&ctx, 1)?, 1),
+        3 => (int_arg(&ctx, 0)?, int_arg(&ctx, 1)?, int_arg(&ctx, 2)?),
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "range-seq".into(),
+                expected: if n == 0 { 1 } else { 3 },
+                actual: n,
+            })
+        }
+    };
+    if step == 0 {
+        return Err(VmError::TypeError {
+            src: None,
+            context: "range-seq step",
+            expected: "non-zero int",
+            actual: UnsafeVal::INT_TYPE_NAME,
+            value: step.to_string(),
+        });
+    }
+    Ok(ctx.new_custom(RangeSeq {
+        next: start,
+        end,
+        step,
+    }))
+}
+
+/// Returns `true` if the sequence has no more values.
+pub fn seq_done(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "seq-done?", 1)?;
+    let is_done = range_seq_arg(&ctx, 0, "seq-done?")?.is_done();
+    Ok(Val::new_bool(is_done).into())
+}
+
+/// Advance the sequence and return the value it was pointing to. If the sequence is done, then
+/// `void` is returned.
+pub fn seq_next(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "seq-next", 1)?;
+    let next = range_seq_arg(&ctx, 0, "seq-next")?.next();
+    match next {
+        Some(x) => Ok(Val::new_int(x).into()),
+        None => Ok(Val::new_void().into()),
+    }
+}
+
+/// Returns `true` if a list or sequence has no more values after `idx` values have been taken with
+/// [iter_next]. Used to implement `dolist` and `dotimes`.
+pub fn iter_done(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "dolist", 2)?;
+    let idx = ctx.arg_as::<i64>(1)? as usize;
+    if let Ok(list) = ctx.arg(0).unwrap().try_list(ctx.vm()) {
+        return Ok(Val::new_bool(list.len() <= idx).into());
+    }
+    let is_done = range_seq_arg(&ctx, 0, "dolist")?.is_done();
+    Ok(Val::new_bool(is_done).into())
+}
+
+/// Take the next value of a list or sequence. `idx` is the number of values that have already been
+/// taken. Used to implement `dolist` and `dotimes`.
+pub fn iter_next(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "dolist", 2)?;
+    let idx = ctx.arg_as::<i64>(1)? as usize;
+    if let Ok(list) = ctx.arg(0).unwrap().try_list(ctx.vm()) {
+        let v = list
+            .get(idx)
+            .map(|v| v.as_unsafe_val())
+            .unwrap_or(UnsafeVal::Void);
+        // Unsafe OK: The value comes from a list that is currently an argument.
+        return Ok(unsafe { ctx.with_unsafe_val(v) });
+    }
+    let next = range_seq_arg(&ctx, 0, "dolist")?.next();
+    match next {
+        Some(x) => Ok(Val::new_int(x).into()),
+        None => Ok(Val::new_void().into()),
+    }
+}
+
+/// Call a function on each element of a list or sequence. Sequences are consumed lazily.
+pub fn for_each(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "for-each", 2)?;
+    let func = ctx.arg(0).unwrap().as_unsafe_val();
+    let items = ctx.arg(1).unwrap();
+    if let Ok(list) = items.try_list(ctx.vm()) {
+        let list: Vec<UnsafeVal> = list.iter().map(|v| v.as_unsafe_val()).collect();
+        // Unsafe OK: Garbage collection does not run while calling `func`. The elements are also
+        // reachable through the list argument.
+        let vm = unsafe { ctx.vm_mut() };
+        for v in list {
+            vm.call_reentrant(func, &[v])?;
+        }
+        return Ok(Val::new_void().into());
+    }
+    loop {
+        // The lock on the sequence is released before calling `func` in case `func` also uses the
+        // sequence.
+        let next = range_seq_arg(&ctx, 1, "for-each arg(idx=1)")?.next();
+        let v = match next {
+            Some(v) => v,
+            None => return Ok(Val::new_void().into()),
+        };
+        // Unsafe OK: Garbage collection does not run while calling `func`.
+        unsafe { ctx.vm_mut() }.call_reentrant(func, &[v.into()])?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    #[test]
+    fn seq_next_iterates_through_range() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define s (range-seq 1 7 3))").unwrap();
+        assert!(!vm.eval_str("(seq-done? s)").unwrap().try_bool().unwrap());
+        assert_eq!(vm.eval_str("(seq-next s)").unwrap().try_int().unwrap(), 1);
+        assert_eq!(vm.eval_str("(seq-next s)").unwrap().try_int().unwrap(), 4);
+        assert!(vm.eval_str("(seq-done? s)").unwrap().try_bool().unwrap());
+        assert!(vm.eval_str("(seq-next s)").unwrap().is_void());
+    }
+
+    #[test]
+    fn range_seq_with_negative_step_counts_down() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define total (new-box (list)))").unwrap();
+        vm.eval_str(
+            "(for-each (lambda (x) (set-box! total (list (unbox total) x))) (range-seq 3 0 -1))",
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(flatten (unbox total))").unwrap().to_string(),
+            "(3 2 1)"
+        );
+    }
+
+    #[test]
+    fn for_each_sums_large_range_seq() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define total (new-box 0))").unwrap();
+        vm.eval_str(
+            "(for-each (lambda (x) (set-box! total (+ (unbox total) x))) (range-seq 100000))",
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(unbox total)").unwrap().try_int().unwrap(),
+            4999950000
+        );
+    }
+
+    #[test]
+    fn for_each_iterates_over_list() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define total (new-box 0))").unwrap();
+        vm.eval_str("(for-each (lambda (x) (set-box! total (+ (unbox total) x))) (list 1 2 3))")
+            .unwrap();
+        assert_eq!(vm.eval_str("(unbox total)").unwrap().try_int().unwrap(), 6);
+    }
+
+    #[test]
+    fn seq_functions_with_wrong_args_return_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(range-seq)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 11).with_src("(range-seq)".into())),
+                function: "range-seq".into(),
+                expected: 1,
+                actual: 0,
+            }
+        );
+        let src = "(range-seq 0 10 0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 18).with_src(src.into())),
+                context: "range-seq step",
+                expected: "non-zero int",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "0".into(),
+            }
+        );
+        let src = "(seq-next (list))";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 17).with_src(src.into())),
+                context: "seq-next",
+                expected: "range-seq",
+                actual: UnsafeVal::LIST_TYPE_NAME,
+                value: "()".into(),
+            }
+        );
+    }
+}
@@ -1,12 +1,29 @@
-use compact_str::CompactString;
+use std::fmt::Write;
+
+use compact_str::{CompactString, ToCompactString};
 
 use crate::{
     error::{VmError, VmResult},
-    val::{NativeFunctionContext, UnsafeVal, ValBuilder},
+    val::{NativeFunctionContext, UnsafeVal, Val, ValBuilder},
     Vm,
 };
 
+/// Get the number of bytes in a string.
 pub fn string_length<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    string_length_impl(ctx, "string-length", str::len)
+}
+
+/// Get the number of chars in a string. Unlike `string-length`, this takes time proportional to
+/// the length of the string. Text buffer positions are counted in chars.
+pub fn string_char_length<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>> {
+    string_length_impl(ctx, "string-char-length", |s| s.chars().count())
+}
+
+fn string_length_impl<'a>(
+    ctx: NativeFunctionContext,
+    function: &'static str,
+    length: impl Fn(&str) -> usize,
+) -> VmResult<ValBuilder<'a>> {
     match ctx.arg_count() {
         1 => ctx
             .arg(0)
@@ -14,14 +31,15 @@ pub fn string_length<'a>(ctx: NativeFunctionContext) -> VmResult<ValBuilder<'a>>
             .try_str(ctx.vm())
             .map_err(|v| VmError::TypeError {
                 src: None,
-                context: "string-length",
+                context: function,
                 expected: UnsafeVal::STRING_TYPE_NAME,
                 actual: v.type_name(),
                 value: v.formatted(ctx.vm()).to_string(),
             })
-            .map(|s| ValBuilder::new((s.len() as i64).into())),
+            .map(|s| ValBuilder::new((length(s) as i64).into())),
         n => Err(VmError::ArityError {
-            function: "string-length".into(),
+            src: None,
+            function: function.into(),
             expected: 1,
             actual: n,
         }),
@@ -84,6 +102,7 @@ pub fn string_split(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'
             Ok(string_split_impl(ctx, string.split(separator)))
         }
         n => Err(VmError::ArityError {
+            src: None,
             function: "string-split".into(),
             expected: if n == 0 { 1 } else { 2 },
             actual: n,
@@ -91,6 +110,100 @@ pub fn string_split(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'
     }
 }
 
+pub fn string_lines(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let vm: *mut Vm = unsafe { ctx.vm_mut() };
+    let string = single_string_arg(&ctx, "string-lines", unsafe { &*vm })?;
+    Ok(string_split_impl(ctx, string.lines()))
+}
+
+pub fn string_words(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let vm: *mut Vm = unsafe { ctx.vm_mut() };
+    let string = single_string_arg(&ctx, "string-words", unsafe { &*vm })?;
+    Ok(string_split_impl(ctx, string.split_whitespace()))
+}
+
+/// Get the string from a function that takes a single string argument.
+fn single_string_arg<'a>(
+    ctx: &NativeFunctionContext,
+    function: &'static str,
+    vm: &'a Vm,
+) -> VmResult<&'a str> {
+    match ctx.arg_count() {
+        1 => ctx
+            .arg(0)
+            .unwrap()
+            .try_str(vm)
+            .map_err(|v| VmError::TypeError {
+                src: None,
+                context: function,
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            }),
+        n => Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 1,
+            actual: n,
+        }),
+    }
+}
+
+pub fn string_starts_with(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    string_affix_impl(
+        ctx,
+        "string-starts-with?",
+        [
+            "string-starts-with? arg(idx = 0)",
+            "string-starts-with? arg(idx = 1)",
+        ],
+        |s, prefix| s.starts_with(prefix),
+    )
+}
+
+pub fn string_ends_with(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    string_affix_impl(
+        ctx,
+        "string-ends-with?",
+        [
+            "string-ends-with? arg(idx = 0)",
+            "string-ends-with? arg(idx = 1)",
+        ],
+        |s, suffix| s.ends_with(suffix),
+    )
+}
+
+/// Check if the first string argument has the second string argument as an affix.
+fn string_affix_impl<'a>(
+    ctx: NativeFunctionContext<'a>,
+    function: &'static str,
+    contexts: [&'static str; 2],
+    has_affix: impl Fn(&str, &str) -> bool,
+) -> VmResult<ValBuilder<'a>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: function.into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let arg = |idx: usize| {
+        ctx.arg(idx)
+            .unwrap()
+            .try_str(ctx.vm())
+            .map_err(|v| VmError::TypeError {
+                src: None,
+                context: contexts[idx],
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+    };
+    let (string, affix) = (arg(0)?, arg(1)?);
+    Ok(ValBuilder::new(has_affix(string, affix).into()))
+}
+
 pub fn string_join(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     let (list, separator) = match ctx.arg_count() {
         1 => {
@@ -134,6 +247,7 @@ pub fn string_join(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
         }
         0 => {
             return Err(VmError::ArityError {
+                src: None,
                 function: "string-join".into(),
                 expected: 1,
                 actual: 0,
@@ -141,6 +255,7 @@ pub fn string_join(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
         }
         n => {
             return Err(VmError::ArityError {
+                src: None,
                 function: "string-join".into(),
                 expected: 2,
                 actual: n,
@@ -152,17 +267,181 @@ pub fn string_join(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
         if idx > 0 {
             result.push_str(separator);
         }
-        result.push_str(
-            list_element
-                .try_str(ctx.vm())
-                .map_err(|v| VmError::TypeError {
-                    src: None,
-                    context: "string-join arg(idx=0) list subelement",
-                    expected: UnsafeVal::STRING_TYPE_NAME,
-                    actual: v.type_name(),
-                    value: v.format_quoted(ctx.vm()).to_string(),
-                })?,
-        );
+        // Strings are formatted without quotes so they are joined as is.
+        write!(result, "{}", list_element.formatted(ctx.vm())).unwrap();
+    }
+    Ok(ctx.new_string(result))
+}
+
+pub fn string_to_symbol(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let arg = match ctx.arg_count() {
+        1 => ctx.arg(0).unwrap(),
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "string->symbol".into(),
+                expected: 1,
+                actual: n,
+            })
+        }
+    };
+    let name = arg
+        .try_str(ctx.vm())
+        .map_err(|v| VmError::TypeError {
+            src: None,
+            context: "string->symbol",
+            expected: UnsafeVal::STRING_TYPE_NAME,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        })?
+        .to_compact_string();
+    let symbol = unsafe { ctx.vm_mut() }.get_or_create_symbol(&name);
+    Ok(unsafe { ctx.with_unsafe_val(UnsafeVal::Symbol(symbol)) })
+}
+
+pub fn symbol_to_string(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let arg = match ctx.arg_count() {
+        1 => ctx.arg(0).unwrap(),
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "symbol->string".into(),
+                expected: 1,
+                actual: n,
+            })
+        }
+    };
+    let name = arg
+        .try_symbol()
+        .ok()
+        .and_then(|symbol| ctx.vm().symbol_to_str(symbol))
+        .ok_or_else(|| VmError::TypeError {
+            src: None,
+            context: "symbol->string",
+            expected: UnsafeVal::SYMBOL_TYPE_NAME,
+            actual: arg.type_name(),
+            value: arg.format_quoted(ctx.vm()).to_string(),
+        })?
+        .to_compact_string();
+    Ok(ctx.new_string(name))
+}
+
+/// Parse a string as an int. `(parse-int s radix)` parses `s` in base `radix` which must be
+/// between 2 and 36. If `s` is not a valid int, then `void` is returned.
+pub fn parse_int(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    if ctx.arg_count() != 2 {
+        return Err(VmError::ArityError {
+            src: None,
+            function: "parse-int".into(),
+            expected: 2,
+            actual: ctx.arg_count(),
+        });
+    }
+    let s = ctx.arg(0).unwrap();
+    let s = s.try_str(ctx.vm()).map_err(|v| VmError::TypeError {
+        src: None,
+        context: "parse-int arg(idx = 0)",
+        expected: UnsafeVal::STRING_TYPE_NAME,
+        actual: v.type_name(),
+        value: v.format_quoted(ctx.vm()).to_string(),
+    })?;
+    let radix_val = ctx.arg(1).unwrap();
+    let radix = match radix_val.try_int() {
+        Ok(radix) if (2..=36).contains(&radix) => radix as u32,
+        _ => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "parse-int radix",
+                expected: "int between 2 and 36",
+                actual: radix_val.type_name(),
+                value: radix_val.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    match i64::from_str_radix(s, radix) {
+        Ok(x) => Ok(Val::new_int(x).into()),
+        Err(_) => Ok(Val::new_void().into()),
+    }
+}
+
+/// The maximum number of decimals for `format-number`. Floats do not have more significant digits.
+const MAX_FORMAT_NUMBER_DECIMALS: i64 = 17;
+
+/// Format a number with `,` separating each group of thousands.
+///
+/// - `(format-number x)` formats `x` without any decimals.
+/// - `(format-number x decimals)` formats `x` rounded to exactly `decimals` decimal places.
+///   `decimals` must be between 0 and 17.
+pub fn format_number(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let decimals = match ctx.arg_count() {
+        1 => 0,
+        2 => {
+            let v = ctx.arg(1).unwrap();
+            match v.try_int() {
+                Ok(n) if (0..=MAX_FORMAT_NUMBER_DECIMALS).contains(&n) => n as usize,
+                _ => {
+                    return Err(VmError::TypeError {
+                        src: None,
+                        context: "format-number arg(idx = 1)",
+                        expected: "int between 0 and 17",
+                        actual: v.type_name(),
+                        value: v.format_quoted(ctx.vm()).to_string(),
+                    })
+                }
+            }
+        }
+        n => {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "format-number".into(),
+                expected: if n == 0 { 1 } else { 2 },
+                actual: n,
+            })
+        }
+    };
+    let v = ctx.arg(0).unwrap();
+    let formatted = match (v.try_int(), v.try_float()) {
+        (Ok(x), _) if decimals == 0 => x.to_string(),
+        (Ok(x), _) => format!("{x}.{zeros}", zeros = "0".repeat(decimals)),
+        (_, Ok(x)) => format!("{x:.decimals$}"),
+        _ => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "format-number arg(idx = 0)",
+                expected: "int or float",
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    Ok(ctx.new_string(group_thousands(&formatted)))
+}
+
+/// Insert `,` between each group of 3 digits in the integer part of the formatted number `s`.
+fn group_thousands(s: &str) -> CompactString {
+    let (sign, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", s),
+    };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(idx) => unsigned.split_at(idx),
+        None => (unsigned, ""),
+    };
+    let mut result = CompactString::from(sign);
+    for (idx, digit) in integer.chars().enumerate() {
+        if idx > 0 && (integer.len() - idx) % 3 == 0 {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    result.push_str(fraction);
+    result
+}
+
+pub fn to_string(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let mut result = CompactString::default();
+    for arg in ctx.args() {
+        write!(result, "{}", arg.formatted(ctx.vm())).unwrap();
     }
     Ok(ctx.new_string(result))
 }
@@ -195,6 +474,20 @@ mod tests {
                 .unwrap(),
             4
         );
+        assert_eq!(
+            vm.eval_str("(string-length \"é😀\")")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            6
+        );
+        assert_eq!(
+            vm.eval_str("(string-char-length \"é😀\")")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            2
+        );
     }
 
     #[test]
@@ -203,6 +496,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(string-length)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src("(string-length)".into())),
                 function: "string-length".into(),
                 expected: 1,
                 actual: 0
@@ -211,6 +505,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(string-length \"\" \"\")").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 21).with_src("(string-length \"\" \"\")".into())),
                 function: "string-length".into(),
                 expected: 1,
                 actual: 2
@@ -258,6 +553,7 @@ mod tests {
         assert_eq!(
             vm.eval_str(src).unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 14).with_src(src.into())),
                 function: "string-split".into(),
                 expected: 1,
                 actual: 0
@@ -268,6 +564,7 @@ mod tests {
         assert_eq!(
             vm.eval_str(src).unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 23).with_src(src.into())),
                 function: "string-split".into(),
                 expected: 2,
                 actual: 3
@@ -324,6 +621,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(string-join)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 13).with_src("(string-join)".into())),
                 function: "string-join".into(),
                 expected: 1,
                 actual: 0,
@@ -332,6 +630,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(string-join (list) \"\" 3)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 25).with_src("(string-join (list) \"\" 3)".into())),
                 function: "string-join".into(),
                 expected: 2,
                 actual: 3,
@@ -364,17 +663,6 @@ mod tests {
                 value: "3".to_string(),
             },
         );
-        let src = "(string-join (list \"ok string\" 42))";
-        assert_eq!(
-            vm.eval_str(src).unwrap_err(),
-            VmError::TypeError {
-                src: Some(Span::new(0, 35).with_src(src.into())),
-                context: "string-join arg(idx=0) list subelement",
-                expected: UnsafeVal::STRING_TYPE_NAME,
-                actual: UnsafeVal::INT_TYPE_NAME,
-                value: "42".to_string(),
-            },
-        );
         let src = "(string-join (list) 3)";
         assert_eq!(
             vm.eval_str(src).unwrap_err(),
@@ -403,4 +691,358 @@ mod tests {
             .unwrap();
         assert_eq!(got.try_str().unwrap(), "one fish two");
     }
+
+    #[test]
+    fn string_join_formats_non_string_elements() {
+        let mut vm = Vm::default();
+        let got = vm
+            .eval_str("(string-join (list 1 \"two\" 3.5 (list 4 \"five\")) \", \")")
+            .unwrap();
+        assert_eq!(got.try_str().unwrap(), "1, two, 3.5, (4 \"five\")");
+        drop(got);
+        let got = vm.eval_str("(string-join (list) \", \")").unwrap();
+        assert_eq!(got.try_str().unwrap(), "");
+    }
+
+    #[test]
+    fn to_string_concatenates_formatted_args() {
+        let mut vm = Vm::default();
+        let got = vm.eval_str("(->string \"x=\" 5)").unwrap();
+        assert_eq!(got.try_str().unwrap(), "x=5");
+        drop(got);
+        let got = vm
+            .eval_str("(->string \"list: \" (list 1 \"two\") \" struct: \" (struct 'a 1))")
+            .unwrap();
+        assert_eq!(
+            got.try_str().unwrap(),
+            "list: (1 \"two\") struct: (struct 'a 1)"
+        );
+        drop(got);
+        let got = vm.eval_str("(->string)").unwrap();
+        assert_eq!(got.try_str().unwrap(), "");
+    }
+
+    #[test]
+    fn quoted_symbol_is_same_as_string_to_symbol() {
+        let mut vm = Vm::default();
+        assert!(vm.eval_str("'foo").unwrap().try_symbol().is_ok());
+        assert!(vm
+            .eval_str("(= 'foo (string->symbol \"foo\"))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(= 'foo (string->symbol \"bar\"))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn symbol_prints_as_name() {
+        let mut vm = Vm::default();
+        {
+            let v = vm.eval_str("'foo").unwrap();
+            assert_eq!(v.formatted(v.vm()).to_string(), "foo");
+            assert_eq!(v.format_quoted(v.vm()).to_string(), "'foo");
+        }
+        assert_eq!(
+            vm.eval_str("(->string 'foo)").unwrap().try_str().unwrap(),
+            "foo"
+        );
+        assert_eq!(
+            vm.eval_str("(symbol->string 'foo)")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "foo"
+        );
+    }
+
+    #[test]
+    fn string_to_symbol_with_non_string_returns_type_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string->symbol 'foo)").unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 21).with_src("(string->symbol 'foo)".into())),
+                context: "string->symbol",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: UnsafeVal::SYMBOL_TYPE_NAME,
+                value: "'foo".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn symbol_to_string_with_non_symbol_returns_type_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(symbol->string \"foo\")").unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 22).with_src("(symbol->string \"foo\")".into())),
+                context: "symbol->string",
+                expected: UnsafeVal::SYMBOL_TYPE_NAME,
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"foo\"".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn string_starts_with_returns_if_string_has_prefix() {
+        let mut vm = Vm::default();
+        let mut eval_bool = |src: &str| vm.eval_str(src).unwrap().try_bool().unwrap();
+        assert!(eval_bool("(string-starts-with? \"hello world\" \"hello\")"));
+        assert!(eval_bool("(string-starts-with? \"hello world\" \"\")"));
+        assert!(eval_bool("(string-starts-with? \"\" \"\")"));
+        assert!(!eval_bool(
+            "(string-starts-with? \"hello world\" \"world\")"
+        ));
+        assert!(!eval_bool("(string-starts-with? \"hi\" \"hello\")"));
+    }
+
+    #[test]
+    fn string_ends_with_returns_if_string_has_suffix() {
+        let mut vm = Vm::default();
+        let mut eval_bool = |src: &str| vm.eval_str(src).unwrap().try_bool().unwrap();
+        assert!(eval_bool("(string-ends-with? \"hello world\" \"world\")"));
+        assert!(eval_bool("(string-ends-with? \"hello world\" \"\")"));
+        assert!(eval_bool("(string-ends-with? \"\" \"\")"));
+        assert!(!eval_bool("(string-ends-with? \"hello world\" \"hello\")"));
+        assert!(!eval_bool("(string-ends-with? \"ld\" \"world\")"));
+    }
+
+    #[test]
+    fn string_starts_with_with_non_string_returns_type_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-starts-with? \"1\" 1)").unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 27).with_src("(string-starts-with? \"1\" 1)".into())),
+                context: "string-starts-with? arg(idx = 1)",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn string_ends_with_with_wrong_number_of_args_returns_arity_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-ends-with? \"1\")").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 23).with_src("(string-ends-with? \"1\")".into())),
+                function: "string-ends-with?".into(),
+                expected: 2,
+                actual: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn string_lines_splits_on_newlines() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-lines \"one\ntwo\n\nthree\")")
+                .unwrap()
+                .to_string(),
+            "(\"one\" \"two\" \"\" \"three\")"
+        );
+        assert_eq!(
+            vm.eval_str("(string-lines \"\")").unwrap().to_string(),
+            "()"
+        );
+    }
+
+    #[test]
+    fn string_lines_ignores_trailing_newline() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-lines \"one\ntwo\n\")")
+                .unwrap()
+                .to_string(),
+            "(\"one\" \"two\")"
+        );
+    }
+
+    #[test]
+    fn string_lines_with_crlf_line_endings_strips_carriage_return() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-lines \"one\r\ntwo\r\n\r\nthree\")")
+                .unwrap()
+                .to_string(),
+            "(\"one\" \"two\" \"\" \"three\")"
+        );
+    }
+
+    #[test]
+    fn string_words_splits_on_whitespace_runs() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-words \"  the   quick\tbrown\n fox  \")")
+                .unwrap()
+                .to_string(),
+            "(\"the\" \"quick\" \"brown\" \"fox\")"
+        );
+        assert_eq!(
+            vm.eval_str("(string-words \"   \")").unwrap().to_string(),
+            "()"
+        );
+    }
+
+    #[test]
+    fn string_words_with_non_string_returns_type_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(string-words 1)").unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 16).with_src("(string-words 1)".into())),
+                context: "string-words",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn format_number_groups_thousands() {
+        let mut vm = Vm::default();
+        for (src, want) in [
+            ("(format-number 1234567.891 2)", "1,234,567.89"),
+            ("(format-number -1234567.891 1)", "-1,234,567.9"),
+            ("(format-number -999 0)", "-999"),
+            ("(format-number 1000)", "1,000"),
+            ("(format-number 1234 2)", "1,234.00"),
+            ("(format-number 0.5 0)", "0"),
+            ("(format-number 12.0)", "12"),
+            ("(format-number 1 17)", "1.00000000000000000"),
+            (
+                "(format-number 9223372036854775807)",
+                "9,223,372,036,854,775,807",
+            ),
+            ("(format-number 1e21 0)", "1,000,000,000,000,000,000,000"),
+        ] {
+            assert_eq!(vm.eval_str(src).unwrap().try_str().unwrap(), want, "{src}");
+        }
+    }
+
+    #[test]
+    fn format_number_with_bad_args_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(format-number \"1\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "format-number arg(idx = 0)",
+                expected: "int or float",
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"1\"".into(),
+            }
+        );
+        let src = "(format-number 1 -1)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "format-number arg(idx = 1)",
+                expected: "int between 0 and 17",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "-1".into(),
+            }
+        );
+        for (src, decimals) in [
+            ("(format-number 1.5 1000000)", "1000000"),
+            ("(format-number 1 1000000000000000)", "1000000000000000"),
+        ] {
+            assert_eq!(
+                vm.eval_str(src).unwrap_err(),
+                VmError::TypeError {
+                    src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                    context: "format-number arg(idx = 1)",
+                    expected: "int between 0 and 17",
+                    actual: UnsafeVal::INT_TYPE_NAME,
+                    value: decimals.into(),
+                }
+            );
+        }
+
+        assert_eq!(
+            vm.eval_str("(format-number)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src("(format-number)".into())),
+                function: "format-number".into(),
+                expected: 1,
+                actual: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_int_parses_with_radix() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(parse-int \"ff\" 16)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            255
+        );
+        assert_eq!(
+            vm.eval_str("(parse-int \"-101\" 2)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            -5
+        );
+        assert_eq!(
+            vm.eval_str("(parse-int \"zz\" 36)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            1295
+        );
+    }
+
+    #[test]
+    fn parse_int_with_invalid_digit_returns_void() {
+        let mut vm = Vm::default();
+        assert!(vm.eval_str("(parse-int \"102\" 2)").unwrap().is_void());
+        assert!(vm.eval_str("(parse-int \"\" 10)").unwrap().is_void());
+        assert!(vm
+            .eval_str("(parse-int \"ffffffffffffffffff\" 16)")
+            .unwrap()
+            .is_void());
+    }
+
+    #[test]
+    fn parse_int_with_invalid_radix_returns_error() {
+        let mut vm = Vm::default();
+        let src = "(parse-int \"1\" 37)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "parse-int radix",
+                expected: "int between 2 and 36",
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "37".into(),
+            }
+        );
+        assert!(vm.eval_str("(parse-int \"1\" 1)").is_err());
+        assert_eq!(
+            vm.eval_str("(parse-int \"1\")").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src("(parse-int \"1\")".into())),
+                function: "parse-int".into(),
+                expected: 2,
+                actual: 1,
+            }
+        );
+    }
 }
@@ -8,8 +8,9 @@ pub fn strct(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     let mut strct = StructVal::with_capacity(ctx.arg_count() / 2);
 
     let mut args_iter = ctx.args();
-    if ctx.arg_count() % 2 != 0 {
+    if !ctx.arg_count().is_multiple_of(2) {
         return Err(VmError::ArityError {
+            src: None,
             function: "struct needs an even amount of args, ".into(),
             expected: ctx.arg_count() + 1,
             actual: ctx.arg_count(),
@@ -60,6 +61,7 @@ pub fn struct_get(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
             Ok(unsafe { ctx.with_unsafe_val(v) })
         }
         args => Err(VmError::ArityError {
+            src: None,
             function: "struct-get".into(),
             expected: 2,
             actual: args,
@@ -98,6 +100,7 @@ pub fn struct_set(mut ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>
             Ok(ValBuilder::new(().into()))
         }
         args => Err(VmError::ArityError {
+            src: None,
             function: "struct-set!".into(),
             expected: 3,
             actual: args,
@@ -127,6 +130,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(struct 'field)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src("(struct 'field)".into())),
                 function: "struct needs an even amount of args, ".into(),
                 expected: 2,
                 actual: 1
@@ -158,6 +162,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(struct-get 1 2 3)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 18).with_src("(struct-get 1 2 3)".into())),
                 function: "struct-get".into(),
                 expected: 2,
                 actual: 3
@@ -272,6 +277,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(struct-set! x 'field 2 3)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 26).with_src("(struct-set! x 'field 2 3)".into())),
                 function: "struct-set!".into(),
                 expected: 3,
                 actual: 4
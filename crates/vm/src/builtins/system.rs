@@ -14,11 +14,17 @@ pub fn working_directory(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<
             let working_directory: CompactString = match std::env::current_dir() {
                 Ok(path) => path.to_string_lossy().into(),
                 // Untested OK: It is hard to create a working directory error and is not common.
-                Err(err) => return Err(VmError::CustomError(err.to_string())),
+                Err(err) => {
+                    return Err(VmError::CustomError {
+                        src: None,
+                        message: err.to_string(),
+                    })
+                }
             };
             Ok(ctx.new_string(working_directory))
         }
         args => Err(VmError::ArityError {
+            src: None,
             function: "working-directory".into(),
             expected: 0,
             actual: args,
@@ -31,6 +37,7 @@ pub fn command(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
     let cmd_str = args
         .next()
         .ok_or_else(|| VmError::ArityError {
+            src: None,
             function: "command".into(),
             expected: 1,
             actual: 0,
@@ -54,18 +61,22 @@ pub fn command(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
         })?;
         cmd.arg(arg_str);
     }
-    let output = cmd
-        .output()
-        .map_err(|err| VmError::CustomError(format!("failed to run command {cmd_str}: {err}")))?;
+    let output = cmd.output().map_err(|err| VmError::CustomError {
+        src: None,
+        message: format!("failed to run command {cmd_str}: {err}"),
+    })?;
     if !output.stderr.is_empty() {
         let err = String::from_utf8_lossy(&output.stderr);
         error!("Command {cmd_str}: {err}");
     }
     if !output.status.success() {
-        return Err(VmError::CustomError(format!(
-            "command {cmd_str} exited with code {code:?}",
-            code = output.status.code()
-        )));
+        return Err(VmError::CustomError {
+            src: None,
+            message: format!(
+                "command {cmd_str} exited with code {code:?}",
+                code = output.status.code()
+            ),
+        });
     }
     let output = ctx.new_string(CompactString::from_utf8_lossy(&output.stdout));
     Ok(output)
@@ -83,6 +94,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(working-directory 1)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 21).with_src("(working-directory 1)".into())),
                 function: "working-directory".into(),
                 expected: 0,
                 actual: 1
@@ -117,6 +129,7 @@ mod tests {
         assert_eq!(
             vm.eval_str(src).unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 9).with_src(src.into())),
                 function: "command".into(),
                 expected: 1,
                 actual: 0
@@ -159,10 +172,10 @@ mod tests {
         let src = "(command \"does-not-exist-1234\")";
         assert_eq!(
             vm.eval_str(src).unwrap_err(),
-            VmError::CustomError(
+            VmError::CustomError { src: Some(Span::new(0, 31).with_src(src.into())), message:
                 "failed to run command does-not-exist-1234: No such file or directory (os error 2)"
                     .into()
-            ),
+             },
         );
     }
 }
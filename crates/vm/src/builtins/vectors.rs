@@ -0,0 +1,248 @@
+use crate::{
+    builtins::check_arity,
+    error::{VmError, VmResult},
+    val::{NativeFunctionContext, UnsafeVal, ValBuilder, ValId, VectorVal},
+};
+
+/// Create a vector containing all the args. Unlike lists, vectors have a fixed length.
+pub fn vector(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    let args: VectorVal = ctx.args().map(|x| x.as_unsafe_val()).collect();
+    // Unsafe OK: All values come from the arguments.
+    Ok(unsafe { ctx.new_vector(args) })
+}
+
+/// Get the element of a vector at an index. `(vector-ref v idx)`
+pub fn vector_ref(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "vector-ref", 2)?;
+    let id = vector_arg(&ctx, "vector-ref")?;
+    let idx = match ctx.arg(1).unwrap().as_unsafe_val() {
+        UnsafeVal::Int(idx) => idx,
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "vector-ref",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    let vector = ctx.vm().objects.get_vector(id);
+    match usize::try_from(idx)
+        .ok()
+        .and_then(|idx| vector.get(idx).copied())
+    {
+        // Unsafe OK: The element is kept alive by the vector which is an arg.
+        Some(v) => Ok(unsafe { ctx.with_unsafe_val(v) }),
+        None => Err(VmError::CustomError {
+            src: None,
+            message: format!(
+                "vector-ref index {idx} is out of bounds for vector of length {len}",
+                len = vector.len()
+            ),
+        }),
+    }
+}
+
+/// Get the number of elements in a vector.
+pub fn vector_length(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "vector-length", 1)?;
+    let id = vector_arg(&ctx, "vector-length")?;
+    let len = ctx.vm().objects.get_vector(id).len() as i64;
+    Ok(ValBuilder::new(len.into()))
+}
+
+/// Create a vector with the same elements as a list.
+pub fn list_to_vector(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "list->vector", 1)?;
+    let vector: VectorVal = match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::List(id) => ctx.vm().objects.get_list(id).as_slice().into(),
+        v => {
+            return Err(VmError::TypeError {
+                src: None,
+                context: "list->vector",
+                expected: UnsafeVal::LIST_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })
+        }
+    };
+    // Unsafe OK: The elements are kept alive by the list which is an arg.
+    Ok(unsafe { ctx.new_vector(vector) })
+}
+
+/// Create a list with the same elements as a vector.
+pub fn vector_to_list(ctx: NativeFunctionContext<'_>) -> VmResult<ValBuilder<'_>> {
+    check_arity(&ctx, "vector->list", 1)?;
+    let id = vector_arg(&ctx, "vector->list")?;
+    let list = ctx.vm().objects.get_vector(id).to_vec();
+    // Unsafe OK: The elements are kept alive by the vector which is an arg.
+    Ok(unsafe { ctx.new_list(list) })
+}
+
+fn vector_arg(ctx: &NativeFunctionContext, function: &'static str) -> VmResult<ValId<VectorVal>> {
+    match ctx.arg(0).unwrap().as_unsafe_val() {
+        UnsafeVal::Vector(id) => Ok(id),
+        v => Err(VmError::TypeError {
+            src: None,
+            context: function,
+            expected: UnsafeVal::VECTOR_TYPE_NAME,
+            actual: v.type_name(),
+            value: v.format_quoted(ctx.vm()).to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::span::Span, Vm};
+
+    use super::*;
+
+    #[test]
+    fn vector_contains_args() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(vector 1 \"two\" 'three)")
+                .unwrap()
+                .to_string(),
+            "(vector 1 \"two\" 'three)"
+        );
+        assert_eq!(vm.eval_str("(vector)").unwrap().to_string(), "(vector)");
+        assert_eq!(
+            vm.eval_str("(type-of (vector 1))").unwrap().to_string(),
+            "'vector"
+        );
+        assert_eq!(
+            vm.eval_str("(vector-length (vector 1 2 3))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn vector_ref_returns_element_at_index() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define v (vector 10 20 30))").unwrap();
+        for (idx, want) in [(0, 10), (1, 20), (2, 30)] {
+            assert_eq!(
+                vm.eval_str(&format!("(vector-ref v {idx})"))
+                    .unwrap()
+                    .try_int()
+                    .unwrap(),
+                want
+            );
+        }
+    }
+
+    #[test]
+    fn vector_ref_out_of_bounds_returns_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define v (vector 10 20 30))").unwrap();
+        for idx in [3, -1] {
+            let src = format!("(vector-ref v {idx})");
+            assert_eq!(
+                vm.eval_str(&src).unwrap_err(),
+                VmError::CustomError {
+                    src: Some(Span::new(0, src.len() as u32).with_src(src.as_str().into())),
+                    message: format!(
+                        "vector-ref index {idx} is out of bounds for vector of length 3"
+                    ),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn vector_functions_with_bad_args_return_error() {
+        let mut vm = Vm::default();
+        let src = "(vector-ref (list 1) 0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 23).with_src(src.into())),
+                context: "vector-ref",
+                expected: UnsafeVal::VECTOR_TYPE_NAME,
+                actual: UnsafeVal::LIST_TYPE_NAME,
+                value: "(1)".into(),
+            }
+        );
+        let src = "(vector-ref (vector 1) 0.0)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, 27).with_src(src.into())),
+                context: "vector-ref",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: UnsafeVal::FLOAT_TYPE_NAME,
+                value: "0.0".into(),
+            }
+        );
+        let src = "(vector-length)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 15).with_src(src.into())),
+                function: "vector-length".into(),
+                expected: 1,
+                actual: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn vectors_and_lists_convert_between_each_other() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(list->vector (list 1 2 3))")
+                .unwrap()
+                .to_string(),
+            "(vector 1 2 3)"
+        );
+        assert_eq!(
+            vm.eval_str("(vector->list (vector 1 2 3))")
+                .unwrap()
+                .to_string(),
+            "(1 2 3)"
+        );
+    }
+
+    #[test]
+    fn vectors_are_equal_if_elements_are_equal() {
+        let mut vm = Vm::default();
+        assert!(vm
+            .eval_str("(= (vector 1 (list 2)) (vector 1 (list 2)))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(= (vector 1 2) (vector 1 3))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(!vm
+            .eval_str("(= (vector 1 2) (list 1 2))")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn vector_elements_are_kept_alive_by_garbage_collector() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define v (vector \"string\" (list 1 2) (vector 3)))")
+            .unwrap();
+        vm.collect_garbage();
+        assert_eq!(
+            vm.eval_str("v").unwrap().to_string(),
+            "(vector \"string\" (1 2) (vector 3))"
+        );
+        let v = vm.val_by_name("v").unwrap();
+        let vector = v.try_vector(&vm).unwrap();
+        for element in vector.iter() {
+            assert!(vm.objects.is_alive(element.as_unsafe_val()));
+        }
+    }
+}
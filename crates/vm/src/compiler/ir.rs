@@ -2,8 +2,10 @@ use bumpalo::Bump;
 use compact_str::{CompactString, ToCompactString};
 
 use crate::{
+    builtins::{numbers, seqs},
     error::CompileError,
     parser::{ast::Node, span::Span},
+    val::NativeFunction,
 };
 
 type BumpVec<'a, T> = bumpalo::collections::Vec<'a, T>;
@@ -28,6 +30,9 @@ pub enum Constant {
     String(CompactString),
     /// A symbol constant.
     Symbol(CompactString),
+    /// A native function. Used by forms that are lowered into builtin calls that must not be
+    /// affected by user definitions.
+    NativeFunction(NativeFunction),
 }
 
 /// Contains the intermediate representation. This is a slightly more processed AST that is usefull
@@ -68,7 +73,8 @@ pub enum Ir<'a> {
         true_expr: &'a Self,
         false_expr: Option<&'a Self>,
     },
-    /// A lambda.
+    /// A lambda. Lambdas compiled from the same source text are deduplicated so evaluating the
+    /// same lambda literal again produces a function that is `=` to the previous one.
     Lambda {
         span: Span,
         name: Option<&'a str>,
@@ -83,6 +89,17 @@ pub enum Ir<'a> {
     },
     /// Return the result of the given expression.
     Return { expr: &'a Self },
+    /// A loop expression of the form: (loop ([<name> <expr>]...) <exprs>...)
+    ///
+    /// The bindings are set up like a let expression. A `recur` in tail position rebinds the
+    /// bindings and jumps back to the start of the loop.
+    Loop {
+        span: Span,
+        bindings: BumpVec<'a, (&'a str, Self)>,
+        expressions: BumpVec<'a, Self>,
+    },
+    /// Rebind the bindings of the enclosing loop and jump back to its start.
+    Recur { span: Span, args: BumpVec<'a, Self> },
 }
 
 impl<'a> Ir<'a> {
@@ -132,6 +149,8 @@ impl<'a> Ir<'a> {
             Ir::Lambda { .. } => IrReturnType::Value,
             Ir::Let { .. } => IrReturnType::Value,
             Ir::Return { .. } => IrReturnType::EarlyReturn,
+            Ir::Loop { .. } => IrReturnType::Value,
+            Ir::Recur { .. } => IrReturnType::Recur,
         }
     }
 
@@ -191,8 +210,72 @@ impl<'a> Ir<'a> {
                             })
                         }
                     },
+                    "if-let" => match rest {
+                        [binding, true_expr, false_expr @ ..] if false_expr.len() <= 1 => {
+                            let true_expr = Ir::new(arena, src, true_expr)?;
+                            let false_expr = match false_expr {
+                                [false_expr] => Some(Ir::new(arena, src, false_expr)?),
+                                _ => None,
+                            };
+                            Self::new_if_let(
+                                arena, src, span, "if-let", binding, true_expr, false_expr,
+                            )?
+                        }
+                        _ => {
+                            return Err(CompileError::ExpressionHasWrongArgs {
+                                expression: "if-let",
+                                expected: if rest.len() > 3 { 3 } else { 2 },
+                                actual: rest.len(),
+                            })
+                        }
+                    },
+                    "when-let" => match rest {
+                        [binding, exprs @ ..] => {
+                            // A let with no bindings is used to evaluate all the expressions.
+                            let body = Ir::Let {
+                                span,
+                                bindings: BumpVec::new_in(arena),
+                                expressions: Self::new_many(arena, src, exprs)?,
+                            };
+                            Self::new_if_let(arena, src, span, "when-let", binding, body, None)?
+                        }
+                        [] => {
+                            return Err(CompileError::ExpressionHasWrongArgs {
+                                expression: "when-let",
+                                expected: 1,
+                                actual: 0,
+                            })
+                        }
+                    },
+                    "loop" => match rest {
+                        [bindings, exprs @ ..] => {
+                            Self::new_recur_loop(arena, src, span, bindings, exprs)?
+                        }
+                        [] => {
+                            return Err(CompileError::ExpressionHasWrongArgs {
+                                expression: "loop",
+                                expected: 1,
+                                actual: 0,
+                            })
+                        }
+                    },
+                    "recur" => Ir::Recur {
+                        span,
+                        args: Self::new_many(arena, src, rest)?,
+                    },
+                    "dotimes" => Self::new_loop(arena, src, span, "dotimes", rest)?,
+                    "dolist" => Self::new_loop(arena, src, span, "dolist", rest)?,
+                    "->" => {
+                        Self::new_thread_expression(arena, src, "->", rest, ThreadPosition::First)?
+                    }
+                    "->>" => {
+                        Self::new_thread_expression(arena, src, "->>", rest, ThreadPosition::Last)?
+                    }
                     "or" => Self::new_or_expression(arena, src, span, rest)?,
                     "and" => Self::new_and_expression(arena, src, span, rest)?,
+                    // The contents of a comment have already been parsed into an AST so malformed
+                    // source is still rejected. The contents are otherwise ignored.
+                    "comment" => Ir::Constant(span, Constant::Void),
                     "return" => match rest {
                         [expr] => Ir::Return {
                             expr: arena.alloc(Ir::new(arena, src, expr)?),
@@ -254,7 +337,7 @@ impl<'a> Ir<'a> {
             },
             [Node::Tree(lambda_signature_span, lambda_signature), exprs @ ..] => {
                 let lambda_span = if let Some(expr) = exprs.last() {
-                    lambda_signature_span.extend_end(expr.span().end)
+                    lambda_signature_span.merge(expr.span())
                 } else {
                     *lambda_signature_span
                 };
@@ -307,7 +390,18 @@ impl<'a> Ir<'a> {
             let ident = node_to_ident(src, arg)?;
             args_vec.push(ident);
         }
-        let exprs_vec = Self::new_many(arena, src, exprs)?;
+        let mut exprs_vec = Self::new_many(arena, src, exprs)?;
+        // A `recur` that targets the function rebinds the arguments. This is done by wrapping the
+        // body in a loop that binds each argument to itself.
+        if check_recur_many(&exprs_vec, true)? {
+            let bindings = args_vec.iter().map(|arg| (*arg, Ir::Deref(span, arg)));
+            let loop_ir = Ir::Loop {
+                span,
+                bindings: BumpVec::from_iter_in(bindings, arena),
+                expressions: exprs_vec,
+            };
+            exprs_vec = BumpVec::from_iter_in(std::iter::once(loop_ir), arena);
+        }
         Ok(Ir::Lambda {
             span,
             name,
@@ -316,6 +410,35 @@ impl<'a> Ir<'a> {
         })
     }
 
+    /// Create a `loop` expression. `(loop ([x 0]) (if (< x 10) (recur (+ x 1)) x))` evaluates to
+    /// `10`.
+    fn new_recur_loop(
+        arena: &'a Bump,
+        src: &'a str,
+        span: Span,
+        bindings: &Node,
+        exprs: &[Node],
+    ) -> Result<Ir<'a>> {
+        let bindings_ast = match bindings {
+            Node::Tree(_, tree) => tree.as_slice(),
+            _ => return Err(CompileError::BadBinding { expression: "loop" }),
+        };
+        let mut bindings = BumpVec::with_capacity_in(bindings_ast.len(), arena);
+        for binding in bindings_ast {
+            let (ident_span, expr) = parse_binding("loop", binding)?;
+            let expr = Self::new(arena, src, expr)?;
+            check_recur(&expr, false)?;
+            bindings.push((ident_span.with_src(src).as_str(), expr));
+        }
+        let expressions = Self::new_many(arena, src, exprs)?;
+        check_recur_many(&expressions, true)?;
+        Ok(Ir::Loop {
+            span,
+            bindings,
+            expressions,
+        })
+    }
+
     fn new_let(
         arena: &'a Bump,
         src: &'a str,
@@ -356,6 +479,185 @@ impl<'a> Ir<'a> {
         Ok(ret)
     }
 
+    /// Create an expression that evaluates the expression in `binding`, binds it to a local
+    /// variable, and branches on its truthiness.
+    fn new_if_let(
+        arena: &'a Bump,
+        src: &'a str,
+        span: Span,
+        expression: &'static str,
+        binding: &Node,
+        true_expr: Ir<'a>,
+        false_expr: Option<Ir<'a>>,
+    ) -> Result<Ir<'a>> {
+        let (ident_span, expr) = parse_binding(expression, binding)?;
+        let ident = ident_span.with_src(src).as_str();
+        let expr = Self::new(arena, src, expr)?;
+        let branch_expr = Ir::If {
+            span,
+            predicate: arena.alloc(Ir::Deref(ident_span, ident)),
+            true_expr: arena.alloc(true_expr),
+            false_expr: false_expr.map(|e| &*arena.alloc(e)),
+        };
+        Ok(Ir::Let {
+            span,
+            bindings: BumpVec::from_iter_in(std::iter::once((ident, expr)), arena),
+            expressions: BumpVec::from_iter_in(std::iter::once(branch_expr), arena),
+        })
+    }
+
+    /// Lower `dotimes` and `dolist` into a `loop`. `(dolist (x items) body...)` becomes:
+    ///
+    /// ```lisp
+    /// (loop ([__dolist_items items] [__dolist_idx 0])
+    ///   (if (iter-done? __dolist_items __dolist_idx)
+    ///       void
+    ///       (let ([x (iter-next __dolist_items __dolist_idx)])
+    ///         body...
+    ///         (recur __dolist_items (+ __dolist_idx 1)))))
+    /// ```
+    ///
+    /// `(dotimes (i n) body...)` is the same but iterates over `(range-seq n)`.
+    fn new_loop(
+        arena: &'a Bump,
+        src: &'a str,
+        span: Span,
+        expression: &'static str,
+        exprs: &[Node],
+    ) -> Result<Ir<'a>> {
+        let (binding, body) = match exprs {
+            [binding, body @ ..] => (binding, body),
+            [] => {
+                return Err(CompileError::ExpressionHasWrongArgs {
+                    expression,
+                    expected: 1,
+                    actual: 0,
+                })
+            }
+        };
+        let (ident_span, expr_node) = parse_binding(expression, binding)?;
+        let expr = Self::new(arena, src, expr_node)?;
+        check_recur(&expr, false)?;
+        let items = if expression == "dotimes" {
+            Self::new_native_call(arena, expr_node.span(), seqs::range_seq, [expr])
+        } else {
+            expr
+        };
+        let items_ident = "__dolist_items";
+        let idx_ident = "__dolist_idx";
+        let iter_args = || [Ir::Deref(span, items_ident), Ir::Deref(span, idx_ident)];
+        let mut expressions = Self::new_many(arena, src, body)?;
+        check_recur_many(&expressions, false)?;
+        expressions.push(Ir::Recur {
+            span,
+            args: BumpVec::from_iter_in(
+                [
+                    Ir::Deref(span, items_ident),
+                    Self::new_native_call(
+                        arena,
+                        span,
+                        numbers::add,
+                        [
+                            Ir::Deref(span, idx_ident),
+                            Ir::Constant(span, Constant::Int(1)),
+                        ],
+                    ),
+                ],
+                arena,
+            ),
+        });
+        let next = Self::new_native_call(arena, span, seqs::iter_next, iter_args());
+        let iteration = Ir::If {
+            span,
+            predicate: arena.alloc(Self::new_native_call(
+                arena,
+                span,
+                seqs::iter_done,
+                iter_args(),
+            )),
+            true_expr: arena.alloc(Ir::Constant(span, Constant::Void)),
+            false_expr: Some(arena.alloc(Ir::Let {
+                span,
+                bindings: BumpVec::from_iter_in([(ident_span.with_src(src).as_str(), next)], arena),
+                expressions,
+            })),
+        };
+        Ok(Ir::Loop {
+            span,
+            bindings: BumpVec::from_iter_in(
+                [
+                    (items_ident, items),
+                    (idx_ident, Ir::Constant(span, Constant::Int(0))),
+                ],
+                arena,
+            ),
+            expressions: BumpVec::from_iter_in([iteration], arena),
+        })
+    }
+
+    fn new_native_call<const N: usize>(
+        arena: &'a Bump,
+        span: Span,
+        function: NativeFunction,
+        args: [Ir<'a>; N],
+    ) -> Ir<'a> {
+        Ir::FunctionCall {
+            span,
+            function: arena.alloc(Ir::Constant(span, Constant::NativeFunction(function))),
+            args: BumpVec::from_iter_in(args, arena),
+        }
+    }
+
+    /// Create a threading expression. `(-> x (f 1) g)` is equivalent to `(g (f x 1))` and
+    /// `(->> x (f 1) g)` is equivalent to `(g (f 1 x))`.
+    fn new_thread_expression(
+        arena: &'a Bump,
+        src: &'a str,
+        expression: &'static str,
+        exprs: &[Node],
+        position: ThreadPosition,
+    ) -> Result<Ir<'a>> {
+        let (initial, forms) = match exprs {
+            [initial, forms @ ..] => (initial, forms),
+            [] => {
+                return Err(CompileError::ExpressionHasWrongArgs {
+                    expression,
+                    expected: 1,
+                    actual: 0,
+                })
+            }
+        };
+        let mut threaded = Self::new(arena, src, initial)?;
+        for form in forms {
+            threaded = match form {
+                Node::Tree(span, tree) => match tree.as_slice() {
+                    [function, args @ ..] => {
+                        let mut args_vec = BumpVec::with_capacity_in(args.len() + 1, arena);
+                        for arg in args {
+                            args_vec.push(Self::new(arena, src, arg)?);
+                        }
+                        match position {
+                            ThreadPosition::First => args_vec.insert(0, threaded),
+                            ThreadPosition::Last => args_vec.push(threaded),
+                        }
+                        Ir::FunctionCall {
+                            span: *span,
+                            function: arena.alloc(Self::new(arena, src, function)?),
+                            args: args_vec,
+                        }
+                    }
+                    [] => return Err(CompileError::EmptyExpression),
+                },
+                function => Ir::FunctionCall {
+                    span: function.span(),
+                    function: arena.alloc(Self::new(arena, src, function)?),
+                    args: BumpVec::from_iter_in(std::iter::once(threaded), arena),
+                },
+            };
+        }
+        Ok(threaded)
+    }
+
     fn new_or_expression(
         arena: &'a Bump,
         src: &'a str,
@@ -429,12 +731,96 @@ impl<'a> Ir<'a> {
     }
 }
 
+/// Where the threaded value is placed within the arguments of a threading expression.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ThreadPosition {
+    /// Thread the value as the first argument. Used by `->`.
+    First,
+    /// Thread the value as the last argument. Used by `->>`.
+    Last,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum IrReturnType {
     /// A value is pushed to the top of the stack.
     Value,
     /// The current function is returned, exiting the current function call frame.
     EarlyReturn,
+    /// Execution jumps back to the start of the enclosing loop.
+    Recur,
+}
+
+/// Check that all `recur` expressions within `ir` that target the enclosing loop or function are in
+/// tail position. `is_tail` should be `true` if `ir` is in tail position.
+///
+/// Returns `true` if `ir` contains a `recur` that targets the enclosing loop or function.
+fn check_recur(ir: &Ir, is_tail: bool) -> Result<bool> {
+    match ir {
+        Ir::Constant(_, _) | Ir::Deref(_, _) => Ok(false),
+        // Loops and lambdas are checked when they are created. Any `recur` within them targets
+        // them instead of the enclosing loop or function.
+        Ir::Loop { .. } | Ir::Lambda { .. } => Ok(false),
+        Ir::Recur { args, .. } => {
+            if !is_tail {
+                return Err(CompileError::RecurNotInTailPosition);
+            }
+            check_recur_many(args, false)?;
+            Ok(true)
+        }
+        Ir::FunctionCall { function, args, .. } => {
+            check_recur(function, false)?;
+            check_recur_many(args, false)?;
+            Ok(false)
+        }
+        Ir::Define { expr, .. } | Ir::Return { expr } => {
+            check_recur(expr, false)?;
+            Ok(false)
+        }
+        Ir::If {
+            predicate,
+            true_expr,
+            false_expr,
+            ..
+        } => {
+            check_recur(predicate, false)?;
+            let true_has_recur = check_recur(true_expr, is_tail)?;
+            let false_has_recur = match false_expr {
+                Some(false_expr) => check_recur(false_expr, is_tail)?,
+                None => false,
+            };
+            Ok(true_has_recur || false_has_recur)
+        }
+        Ir::Let {
+            bindings,
+            expressions,
+            ..
+        } => {
+            for (_, expr) in bindings {
+                check_recur(expr, false)?;
+            }
+            check_recur_many(expressions, is_tail)
+        }
+    }
+}
+
+/// Similar to [check_recur] but only the last expression of `exprs` may be in tail position.
+fn check_recur_many(exprs: &[Ir], is_tail: bool) -> Result<bool> {
+    let mut has_recur = false;
+    for (idx, expr) in exprs.iter().enumerate() {
+        has_recur |= check_recur(expr, is_tail && idx + 1 == exprs.len())?;
+    }
+    Ok(has_recur)
+}
+
+/// Parse a binding of the form `(ident expr)` into the span of the identifier and the expression.
+fn parse_binding<'n>(expression: &'static str, binding: &'n Node) -> Result<(Span, &'n Node)> {
+    match binding {
+        Node::Tree(_, tree) => match tree.as_slice() {
+            [Node::Identifier(ident_span), expr] => Ok((*ident_span, expr)),
+            _ => Err(CompileError::BadBinding { expression }),
+        },
+        _ => Err(CompileError::BadBinding { expression }),
+    }
 }
 
 fn node_to_ident<'a>(src: &'a str, node: &Node) -> Result<&'a str> {
@@ -0,0 +1,431 @@
+//! Expands macros by rewriting the AST before it is compiled.
+//!
+//! Macros are defined at the top level with `(defmacro (name args...) body...)`. When a macro is
+//! called, its arguments are passed unevaluated as data. Identifiers become symbols, expressions
+//! become lists, and atoms are passed as is. The returned data is converted back into nodes and
+//! compiled in place of the macro call.
+//!
+//! Parts of the returned data that came from the macro arguments keep their original spans so
+//! errors point to the source that was written by the user. Other expressions take the span of the
+//! macro call. Identifiers and strings created by the macro have no source so their text is
+//! appended to the end of the source.
+use std::fmt::Write;
+
+use bumpalo::Bump;
+
+use crate::{
+    error::CompileError,
+    parser::{
+        ast::Node,
+        span::Span,
+        tokenizer::{Token, TokenType},
+    },
+    val::{FormatOptions, Instruction, ListVal, UnsafeVal, ValFormatter, ValId},
+    Vm,
+};
+
+use super::{Compiler, Result};
+
+/// The maximum number of times a macro call may expand into another macro call.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// How values are formatted within errors. Values are bounded as they may contain themselves.
+const ERROR_FORMAT_OPTIONS: FormatOptions = FormatOptions {
+    quote_strings: true,
+    max_depth: Some(4),
+    max_length: Some(8),
+};
+
+/// The result of expanding the macros within some source code.
+pub struct Expansion {
+    /// The original source followed by the text of identifiers and strings created by macros.
+    pub src: String,
+    /// The top level nodes with all macros expanded.
+    pub nodes: Vec<Node>,
+}
+
+/// Expand all macros within the top level expressions of `src` that are within `span` and register
+/// any macro definitions.
+///
+/// Returns `None` if the expressions do not define or use any macros.
+pub fn expand_macros(
+    vm: &mut Vm,
+    src: &str,
+    span: Span,
+    arena: &Bump,
+) -> Result<Option<Expansion>> {
+    let parse_src = &src[..span.end as usize];
+    if vm.macros.is_empty() && !has_defmacro_token(parse_src, span.start as usize) {
+        return Ok(None);
+    }
+    let mut expander = MacroExpander {
+        vm,
+        arena,
+        src: src.to_string(),
+    };
+    let mut nodes = Vec::new();
+    let mut changed = false;
+    for node_or_err in Node::parse_from(parse_src, span.start as usize) {
+        let node = node_or_err?;
+        if let Some(defmacro_args) = defmacro_args(src, &node) {
+            expander.define_macro(defmacro_args)?;
+            nodes.push(Node::Void(node.span()));
+            changed = true;
+            continue;
+        }
+        match expander.expand(&node, 0)? {
+            Some(expanded) => {
+                nodes.push(expanded);
+                changed = true;
+            }
+            None => nodes.push(node),
+        }
+    }
+    Ok(changed.then_some(Expansion {
+        src: expander.src,
+        nodes,
+    }))
+}
+
+/// Returns `true` if `src` contains the `defmacro` identifier after byte offset `start`. Unlike
+/// searching the text, strings and comments that contain "defmacro" are ignored.
+fn has_defmacro_token(src: &str, start: usize) -> bool {
+    Token::parse_tokens_from(src, start)
+        .any(|t| t.token_type == TokenType::Other && t.as_str(src) == "defmacro")
+}
+
+/// Returns the arguments to `node` if `node` is a `defmacro` expression.
+fn defmacro_args<'n>(src: &str, node: &'n Node) -> Option<&'n [Node]> {
+    match node {
+        Node::Tree(_, tree) => match tree.as_slice() {
+            [Node::Identifier(ident), rest @ ..] if ident.with_src(src).as_str() == "defmacro" => {
+                Some(rest)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct MacroExpander<'a> {
+    vm: &'a mut Vm,
+    arena: &'a Bump,
+    /// The source that all nodes point to. Text for nodes created by macros is appended.
+    src: String,
+}
+
+impl<'a> MacroExpander<'a> {
+    /// Compile the macro transformer defined by `args` and register it within the [Vm].
+    fn define_macro(&mut self, args: &[Node]) -> Result<()> {
+        let (signature, name, body) = match args {
+            [signature @ Node::Tree(_, signature_nodes), body @ ..] if !body.is_empty() => {
+                match signature_nodes.as_slice() {
+                    [Node::Identifier(name), ..] => (signature, *name, body),
+                    _ => {
+                        return Err(CompileError::ExpectedIdentifierList {
+                            context: "macro definition",
+                        })
+                    }
+                }
+            }
+            [_, _, ..] => {
+                return Err(CompileError::ExpectedIdentifierList {
+                    context: "macro definition",
+                })
+            }
+            _ => {
+                return Err(CompileError::ExpressionHasWrongArgs {
+                    expression: "defmacro",
+                    expected: 2,
+                    actual: args.len(),
+                })
+            }
+        };
+        // Unwrap OK: `body` was checked to not be empty above.
+        let body_span = body[0].span().merge(body.last().unwrap().span());
+        let transformer_src = format!(
+            "(define {signature} {body})",
+            signature = signature.span().with_src(self.src.as_str()).as_str(),
+            body = body_span.with_src(self.src.as_str()).as_str()
+        );
+        let bytecode = Compiler::compile(self.vm, &transformer_src, self.arena)?;
+        let transformer = match bytecode.instructions.first() {
+            Some(Instruction::PushConst(f @ UnsafeVal::ByteCodeFunction(_))) => *f,
+            // Unreachable OK: Defining a function always starts by pushing the function.
+            _ => unreachable!(),
+        };
+        let name = self
+            .vm
+            .get_or_create_symbol(name.with_src(self.src.as_str()).as_str());
+        self.vm.macros.insert(name, transformer);
+        Ok(())
+    }
+
+    /// Expand all macro calls within `node`. Returns the expanded node or `None` if `node` does not
+    /// contain any macro calls.
+    fn expand(&mut self, node: &Node, depth: usize) -> Result<Option<Node>> {
+        let (span, tree) = match node {
+            Node::Tree(span, tree) => (*span, tree),
+            _ => return Ok(None),
+        };
+        if let Some(Node::Identifier(ident)) = tree.first() {
+            let name = ident.with_src(self.src.as_str()).as_str();
+            if name == "defmacro" {
+                return Err(CompileError::DefmacroNotAllowed);
+            }
+            if name == "comment" {
+                return Ok(None);
+            }
+            if let Some(transformer) = self.macro_transformer(name) {
+                let name = name.to_string();
+                let expanded = self.call_transformer(&name, transformer, span, &tree[1..])?;
+                if depth >= MAX_EXPANSION_DEPTH {
+                    return Err(CompileError::MacroExpansionFailed {
+                        name: name.into(),
+                        reason: format!(
+                            "exceeded maximum expansion depth of {MAX_EXPANSION_DEPTH}"
+                        ),
+                    });
+                }
+                let fully_expanded = self.expand(&expanded, depth + 1)?;
+                return Ok(Some(fully_expanded.unwrap_or(expanded)));
+            }
+        }
+        let mut children = Vec::with_capacity(tree.len());
+        let mut changed = false;
+        for child in tree.iter() {
+            let expanded_child = self.expand(child, depth)?;
+            changed |= expanded_child.is_some();
+            children.push(expanded_child);
+        }
+        if !changed {
+            return Ok(None);
+        }
+        let children = tree
+            .iter()
+            .zip(children)
+            .map(|(child, expanded)| expanded.unwrap_or_else(|| child.clone()))
+            .collect();
+        Ok(Some(Node::Tree(span, children)))
+    }
+
+    fn macro_transformer(&self, name: &str) -> Option<UnsafeVal> {
+        let symbol = self.vm.get_symbol(name)?;
+        self.vm.macros.get(&symbol).copied()
+    }
+
+    /// Call the macro `transformer` with `args` and return the resulting node. `span` is the span of
+    /// the macro call.
+    fn call_transformer(
+        &mut self,
+        name: &str,
+        transformer: UnsafeVal,
+        span: Span,
+        args: &[Node],
+    ) -> Result<Node> {
+        let expansion_failed = |reason: String| CompileError::MacroExpansionFailed {
+            name: name.into(),
+            reason,
+        };
+        let mut origins = Vec::new();
+        let args: Vec<UnsafeVal> = args
+            .iter()
+            .map(|arg| {
+                let v = node_to_val(self.vm, &self.src, arg);
+                record_origins(self.vm, arg, v, &mut origins);
+                v
+            })
+            .collect();
+        let result = self
+            .vm
+            .call_at_compile_time(transformer, &args)
+            .map_err(|err| expansion_failed(err.to_string()))?;
+        self.val_to_node(result, span, &origins, &mut Vec::new())
+            .map_err(expansion_failed)
+    }
+
+    /// Convert `v` into a node. Values that were created from a node within `origins` are
+    /// converted back into that node. Other values use `span` or, if they require source text, have
+    /// their text appended to the source.
+    ///
+    /// `ancestors` contains the lists that are currently being converted and is used to detect
+    /// cycles.
+    fn val_to_node(
+        &mut self,
+        v: UnsafeVal,
+        span: Span,
+        origins: &[(UnsafeVal, &Node)],
+        ancestors: &mut Vec<ValId<ListVal>>,
+    ) -> std::result::Result<Node, String> {
+        if let Some((_, node)) = origins.iter().find(|(origin, _)| *origin == v) {
+            return Ok((*node).clone());
+        }
+        let node = match v {
+            UnsafeVal::Void => Node::Void(span),
+            UnsafeVal::Bool(x) => Node::Bool(span, x),
+            UnsafeVal::Int(x) => Node::Int(span, x),
+            UnsafeVal::Float(x) => Node::Float(span, x),
+            UnsafeVal::String(_) => {
+                let mut literal = String::new();
+                write_val_as_source(self.vm, v, &mut literal)?;
+                Node::String(self.append_src(&literal))
+            }
+            UnsafeVal::Symbol(symbol) => {
+                let name = self
+                    .vm
+                    .symbol_to_str(symbol)
+                    .unwrap_or_default()
+                    .to_string();
+                Node::Identifier(self.append_src(&name))
+            }
+            UnsafeVal::List(id) if ancestors.contains(&id) => {
+                return Err(unconvertible_to_source(self.vm, v))
+            }
+            UnsafeVal::List(id) => {
+                ancestors.push(id);
+                let list = self.vm.objects.get_list(id).to_vec();
+                let children = list
+                    .into_iter()
+                    .map(|v| self.val_to_node(v, span, origins, ancestors))
+                    .collect::<std::result::Result<_, _>>();
+                ancestors.pop();
+                Node::Tree(span, children?)
+            }
+            v => return Err(unconvertible_to_source(self.vm, v)),
+        };
+        Ok(node)
+    }
+
+    /// Append `text` to the source and return its span.
+    fn append_src(&mut self, text: &str) -> Span {
+        self.src.push('\n');
+        let start = self.src.len() as u32;
+        self.src.push_str(text);
+        Span::new(start, self.src.len() as u32)
+    }
+}
+
+/// Record the value that was created from each tree, string, and identifier within `node`. `v` must
+/// be the value returned by [node_to_val] for `node`.
+fn record_origins<'n>(
+    vm: &Vm,
+    node: &'n Node,
+    v: UnsafeVal,
+    origins: &mut Vec<(UnsafeVal, &'n Node)>,
+) {
+    match (node, v) {
+        (Node::Tree(_, tree), UnsafeVal::List(id)) => {
+            origins.push((v, node));
+            for (child, child_v) in tree.iter().zip(vm.objects.get_list(id).iter()) {
+                record_origins(vm, child, *child_v, origins);
+            }
+        }
+        (Node::String(_) | Node::Identifier(_), _) => origins.push((v, node)),
+        _ => {}
+    }
+}
+
+/// Convert `node` into data. Identifiers become symbols, trees become lists, and atoms are
+/// converted as is.
+pub fn node_to_val(vm: &mut Vm, src: &str, node: &Node) -> UnsafeVal {
+    match node {
+        Node::Void(_) => UnsafeVal::Void,
+        Node::Bool(_, x) => UnsafeVal::Bool(*x),
+        Node::Int(_, x) => UnsafeVal::Int(*x),
+        Node::Float(_, x) => UnsafeVal::Float(*x),
+        Node::String(_) => {
+            let s = node.to_string_literal(src).unwrap_or_default();
+            UnsafeVal::String(vm.objects.insert_string(s))
+        }
+        Node::Identifier(span) => {
+            UnsafeVal::Symbol(vm.get_or_create_symbol(span.with_src(src).as_str()))
+        }
+        Node::Tree(_, tree) => {
+            let list = tree.iter().map(|n| node_to_val(vm, src, n)).collect();
+            UnsafeVal::List(vm.objects.insert_list(list))
+        }
+    }
+}
+
+/// Write `v` as source code that evaluates to the expression represented by `v`.
+///
+/// Returns an error if `v` can not be read back as the same expression. This includes values like
+/// vectors, non-finite floats, symbols that would be read as a literal or as several tokens, and
+/// lists that contain themselves.
+pub fn write_val_as_source(
+    vm: &Vm,
+    v: UnsafeVal,
+    out: &mut String,
+) -> std::result::Result<(), String> {
+    write_val_as_source_impl(vm, v, out, &mut Vec::new())
+}
+
+/// Write `v` as source code. `ancestors` contains the lists that are currently being written and is
+/// used to detect cycles.
+fn write_val_as_source_impl(
+    vm: &Vm,
+    v: UnsafeVal,
+    out: &mut String,
+    ancestors: &mut Vec<ValId<ListVal>>,
+) -> std::result::Result<(), String> {
+    // Unwrap OK: Writing to a string does not fail.
+    match v {
+        UnsafeVal::Void => out.push_str("void"),
+        UnsafeVal::Bool(x) => write!(out, "{x}").unwrap(),
+        UnsafeVal::Int(x) => write!(out, "{x}").unwrap(),
+        UnsafeVal::Float(x) if x.is_finite() => write!(out, "{x:?}").unwrap(),
+        UnsafeVal::String(id) => {
+            out.push('"');
+            for ch in vm.objects.get_str(id).chars() {
+                match ch {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\t' => out.push_str("\\t"),
+                    ch => out.push(ch),
+                }
+            }
+            out.push('"');
+        }
+        UnsafeVal::Symbol(symbol) => {
+            let name = vm.symbol_to_str(symbol).unwrap_or_default();
+            if !reads_as_identifier(name) {
+                return Err(format!(
+                    "symbol {name:?} can not be converted to source code as it would not be read \
+                     back as the same symbol"
+                ));
+            }
+            out.push_str(name);
+        }
+        UnsafeVal::List(id) if ancestors.contains(&id) => {
+            return Err(unconvertible_to_source(vm, v))
+        }
+        UnsafeVal::List(id) => {
+            ancestors.push(id);
+            out.push('(');
+            for (idx, v) in vm.objects.get_list(id).iter().enumerate() {
+                if idx > 0 {
+                    out.push(' ');
+                }
+                write_val_as_source_impl(vm, *v, out, ancestors)?;
+            }
+            out.push(')');
+            ancestors.pop();
+        }
+        v => return Err(unconvertible_to_source(vm, v)),
+    }
+    Ok(())
+}
+
+/// Returns `true` if `name` is read back as a single identifier with the same name.
+fn reads_as_identifier(name: &str) -> bool {
+    matches!(Node::parse_one(name), Ok(Node::Identifier(span)) if span.with_src(name).as_str() == name)
+}
+
+fn unconvertible_to_source(vm: &Vm, v: UnsafeVal) -> String {
+    format!(
+        "{value} of type {type_name} can not be converted to source code",
+        value = ValFormatter::new_with_options(vm, v, ERROR_FORMAT_OPTIONS),
+        type_name = v.type_name(),
+    )
+}
@@ -5,6 +5,7 @@ use compact_str::{CompactString, ToCompactString};
 use ir::{Constant, Ir, IrReturnType};
 
 use crate::{
+    builtins,
     error::CompileError,
     parser::{ast::Node, span::Span},
     val::{ByteCode, Instruction, UnsafeVal},
@@ -12,6 +13,9 @@ use crate::{
 };
 
 mod ir;
+mod macros;
+
+pub(crate) use macros::{node_to_val, write_val_as_source};
 
 type BumpVec<'a, T> = bumpalo::collections::Vec<'a, T>;
 type Result<T> = std::result::Result<T, CompileError>;
@@ -27,6 +31,18 @@ pub struct Compiler<'a> {
     local_space_required: usize,
     instructions: BumpVec<'a, Instruction>,
     instruction_source: BumpVec<'a, Span>,
+    loops: BumpVec<'a, LoopTarget>,
+}
+
+/// The target of a `recur` expression.
+#[derive(Copy, Clone)]
+struct LoopTarget {
+    /// The index of the first instruction of the loop body.
+    start: usize,
+    /// The argument index of the first loop binding. The bindings are contiguous.
+    first_binding: usize,
+    /// The number of loop bindings.
+    binding_count: usize,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -46,6 +62,20 @@ enum CompileManyBehavior {
 
 impl<'a> Compiler<'a> {
     pub fn compile(vm: &'a mut Vm, input_source: &str, arena: &Bump) -> Result<ByteCode> {
+        let span = Span::new(0, input_source.len() as u32);
+        Self::compile_span(vm, input_source, span, arena)
+    }
+
+    /// Compile the top level expressions of `input_source` that are within `span`. Unlike compiling
+    /// a substring, the source map and error spans are relative to all of `input_source`.
+    pub fn compile_span(
+        vm: &'a mut Vm,
+        input_source: &str,
+        span: Span,
+        arena: &Bump,
+    ) -> Result<ByteCode> {
+        let expansion = macros::expand_macros(vm, input_source, span, arena)?;
+        let input_source = expansion.as_ref().map_or(input_source, |e| e.src.as_str());
         let settings = vm.settings;
         let source = settings.enable_source_maps.then(|| input_source.into());
         let mut compiler = Compiler {
@@ -59,27 +89,47 @@ impl<'a> Compiler<'a> {
             local_space_required: 0,
             instructions: BumpVec::new_in(arena),
             instruction_source: BumpVec::new_in(arena),
+            loops: BumpVec::new_in(arena),
         };
-        compiler.compile_impl(input_source, CompilerContext::Module)?;
-        Ok(ByteCode {
+        match &expansion {
+            Some(expansion) => {
+                for node in expansion.nodes.iter() {
+                    compiler.compile_node(input_source, node, CompilerContext::Module)?;
+                }
+            }
+            None => compiler.compile_impl(input_source, span, CompilerContext::Module)?,
+        }
+        let bytecode = ByteCode {
             name: "".into(),
             arg_count: 0,
             local_bindings: compiler.local_space_required,
             instructions: compiler.instructions.into_bump_slice().into(),
             source,
             instruction_source: compiler.instruction_source.into_bump_slice().into(),
-        })
+        };
+        bytecode.validate_jumps()?;
+        Ok(bytecode)
     }
 
-    fn compile_impl(&mut self, input_source: &str, ctx: CompilerContext) -> Result<()> {
-        for node_or_err in Node::parse(input_source) {
+    fn compile_impl(&mut self, input_source: &str, span: Span, ctx: CompilerContext) -> Result<()> {
+        let parse_source = &input_source[..span.end as usize];
+        for node_or_err in Node::parse_from(parse_source, span.start as usize) {
             let node = node_or_err.map_err(CompileError::AstError)?;
-            let ir = Ir::new(self.arena, input_source, &node)?;
-            self.compile_one(&ir, ctx)?;
+            self.compile_node(input_source, &node, ctx)?;
         }
         Ok(())
     }
 
+    fn compile_node(
+        &mut self,
+        input_source: &str,
+        node: &Node,
+        ctx: CompilerContext,
+    ) -> Result<()> {
+        let ir = Ir::new(self.arena, input_source, node)?;
+        self.compile_one(&ir, ctx)
+    }
+
     fn arg_idx(&self, symbol: &str) -> Option<usize> {
         for (idx, sym) in self.local_bindings.iter().enumerate().rev() {
             if sym == symbol {
@@ -126,6 +176,12 @@ impl<'a> Compiler<'a> {
                 expressions,
             } => self.compile_one_let(*span, bindings.as_slice(), expressions)?,
             Ir::Return { expr } => self.compile_one_return(expr)?,
+            Ir::Loop {
+                span,
+                bindings,
+                expressions,
+            } => self.compile_one_loop(*span, bindings.as_slice(), expressions)?,
+            Ir::Recur { span, args } => self.compile_one_recur(*span, args)?,
         };
         Ok(())
     }
@@ -158,7 +214,17 @@ impl<'a> Compiler<'a> {
         expressions: &[Ir],
     ) -> Result<()> {
         for (binding, expr) in bindings {
-            self.compile_one(expr, CompilerContext::Subexpression)?;
+            match expr {
+                // Unnamed lambdas take the name of their binding so that they may refer to
+                // themselves.
+                Ir::Lambda {
+                    span,
+                    name: None,
+                    args,
+                    expressions,
+                } => self.compile_one_lambda(*span, Some(binding), args, expressions)?,
+                expr => self.compile_one(expr, CompilerContext::Subexpression)?,
+            }
             self.local_bindings.push(binding.to_compact_string());
             // TODO: Add let binding span to `bindings`.
             self.instruction_source.push(span);
@@ -173,6 +239,59 @@ impl<'a> Compiler<'a> {
         Ok(())
     }
 
+    fn compile_one_loop(
+        &mut self,
+        span: Span,
+        bindings: &[(&str, Ir)],
+        expressions: &[Ir],
+    ) -> Result<()> {
+        let first_binding = self.arguments.len() + self.local_bindings.len();
+        for (binding, expr) in bindings {
+            self.compile_one(expr, CompilerContext::Subexpression)?;
+            self.local_bindings.push(binding.to_compact_string());
+            self.instruction_source.push(span);
+            self.instructions
+                .push(Instruction::BindArg(self.arg_idx(binding).unwrap()));
+        }
+        self.loops.push(LoopTarget {
+            start: self.instructions.len(),
+            first_binding,
+            binding_count: bindings.len(),
+        });
+        self.compile_many(expressions, CompileManyBehavior::KeepSingleReturn)?;
+        self.loops.pop();
+        self.local_space_required = self.local_space_required.max(self.local_bindings.len());
+        for _ in bindings {
+            self.local_bindings.pop().unwrap();
+        }
+        Ok(())
+    }
+
+    fn compile_one_recur(&mut self, span: Span, args: &[Ir]) -> Result<()> {
+        let target = *self
+            .loops
+            .last()
+            .ok_or(CompileError::RecurNotInTailPosition)?;
+        if args.len() != target.binding_count {
+            return Err(CompileError::ExpressionHasWrongArgs {
+                expression: "recur",
+                expected: target.binding_count,
+                actual: args.len(),
+            });
+        }
+        self.compile_many(args, CompileManyBehavior::KeepAll)?;
+        // The last argument is at the top of the stack so bind in reverse order.
+        for idx in (target.first_binding..target.first_binding + target.binding_count).rev() {
+            self.instruction_source.push(span);
+            self.instructions.push(Instruction::BindArg(idx));
+        }
+        self.instruction_source.push(span);
+        self.instructions.push(Instruction::JumpBack(
+            self.instructions.len() + 1 - target.start,
+        ));
+        Ok(())
+    }
+
     fn compile_one_constant(&mut self, span: Span, val: &Constant) -> Result<()> {
         let instruction = match val {
             Constant::Void => Instruction::PushConst(().into()),
@@ -186,6 +305,7 @@ impl<'a> Compiler<'a> {
             Constant::String(x) => {
                 Instruction::PushConst(UnsafeVal::String(self.vm.objects.insert_string(x.clone())))
             }
+            Constant::NativeFunction(f) => Instruction::PushConst(UnsafeVal::NativeFunction(*f)),
         };
         self.instruction_source.push(span);
         self.instructions.push(instruction);
@@ -234,15 +354,26 @@ impl<'a> Compiler<'a> {
                 Ir::Deref(_, ident) => {
                     let interned_ident = self.vm.get_or_create_symbol(ident);
                     match self.vm.values.get(&interned_ident) {
-                        Some(UnsafeVal::NativeFunction(func)) => Some(*func),
+                        Some(UnsafeVal::NativeFunction(func)) => Some((*ident, *func)),
                         _ => None,
                     }
                 }
                 _ => None,
             })
             .flatten();
+        if let Some((expression, expected)) = maybe_native_function
+            .and_then(|(ident, func)| builtins::fixed_arity(ident, func))
+            .filter(|(_, expected)| *expected != args.len())
+        {
+            return Err(CompileError::ExpressionHasWrongArgs {
+                expression,
+                expected,
+                actual: args.len(),
+            });
+        }
+        let maybe_native_function = maybe_native_function.map(|(_, func)| func);
         if maybe_native_function.is_none() {
-            self.compile_one(function, CompilerContext::Subexpression)?;
+            self.compile_one_call_target(function)?;
         }
         self.compile_many(args, CompileManyBehavior::KeepAll)?;
         match maybe_native_function {
@@ -261,6 +392,34 @@ impl<'a> Compiler<'a> {
         Ok(())
     }
 
+    /// Compile the function of a function call. Calls to global functions use
+    /// [Instruction::DerefCached] if inline caching is enabled.
+    fn compile_one_call_target(&mut self, function: &Ir) -> Result<()> {
+        if let Ir::Deref(span, ident) = function {
+            let is_global =
+                self.arg_idx(ident).is_none() && self.function_name.as_deref() != Some(*ident);
+            if is_global
+                && self.settings.enable_inline_cache
+                && !self.settings.enable_aggressive_inline
+            {
+                let symbol = self.vm.get_or_create_symbol(ident);
+                // Native functions are not cached so there is no benefit over a plain `Deref`.
+                if !matches!(
+                    self.vm.values.get(&symbol),
+                    Some(UnsafeVal::NativeFunction(_))
+                ) {
+                    self.instruction_source.push(*span);
+                    self.instructions.push(Instruction::DerefCached {
+                        symbol,
+                        cache: Box::default(),
+                    });
+                    return Ok(());
+                }
+            }
+        }
+        self.compile_one(function, CompilerContext::Subexpression)
+    }
+
     fn compile_one_define(
         &mut self,
         ctx: CompilerContext,
@@ -343,6 +502,7 @@ impl<'a> Compiler<'a> {
             local_space_required: 0,
             instructions: BumpVec::new_in(self.arena),
             instruction_source: BumpVec::new_in(self.arena),
+            loops: BumpVec::new_in(self.arena),
         };
         if let Some(dupe) = find_duplicate(&lambda_compiler.arguments) {
             return Err(CompileError::ArgumentDefinedMultipleTimes(dupe));
@@ -357,8 +517,12 @@ impl<'a> Compiler<'a> {
             source: lambda_compiler.source,
             instruction_source: lambda_compiler.instruction_source.into_bump_slice().into(),
         };
-        let lambda_val =
-            UnsafeVal::ByteCodeFunction(lambda_compiler.vm.objects.insert_bytecode(bytecode));
+        bytecode.validate_jumps()?;
+        // Lambdas are deduplicated as the same source, like a function in a REPL, may be compiled
+        // many times.
+        let lambda_val = UnsafeVal::ByteCodeFunction(
+            lambda_compiler.vm.objects.get_or_insert_bytecode(bytecode),
+        );
         self.instruction_source.push(span);
         self.instructions.push(Instruction::PushConst(lambda_val));
         Ok(())
@@ -396,7 +560,7 @@ fn find_duplicate(vec: &[CompactString]) -> Option<CompactString> {
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::ast::AstParseError;
+    use crate::{error::VmError, parser::ast::AstParseError};
 
     use super::*;
 
@@ -422,7 +586,30 @@ mod tests {
         let mut vm = Vm::default();
         assert_eq!(
             Compiler::compile(&mut vm, ")", &Bump::new()).unwrap_err(),
-            CompileError::AstError(AstParseError::UnexpectedCloseParen)
+            CompileError::AstError(AstParseError::UnexpectedCloseParen(Span::new(0, 1)))
+        );
+    }
+
+    #[test]
+    fn brackets_can_be_used_in_place_of_parens() {
+        let mut vm = Vm::default();
+        let bracket_bytecode = Compiler::compile(&mut vm, "[+ 1 2]", &Bump::new()).unwrap();
+        let paren_bytecode = Compiler::compile(&mut vm, "(+ 1 2)", &Bump::new()).unwrap();
+        assert_eq!(bracket_bytecode.instructions, paren_bytecode.instructions);
+        assert_eq!(vm.eval_str("[+ 1 2]").unwrap().try_int().unwrap(), 3);
+        assert_eq!(
+            vm.eval_str("[define [add-one x] [+ x 1]] (add-one [+ 1 2])")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            4
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "[+ 1 2)", &Bump::new()).unwrap_err(),
+            CompileError::AstError(AstParseError::MismatchedDelimiter {
+                open: Span::new(0, 1),
+                close: Span::new(6, 7)
+            })
         );
     }
 
@@ -517,6 +704,7 @@ mod tests {
         let mut vm = Vm::new(Settings {
             enable_aggressive_inline: true,
             enable_source_maps: false,
+            ..Default::default()
         });
         let actual = Compiler::compile(&mut vm, "+", &Bump::new()).unwrap();
         assert_eq!(
@@ -540,6 +728,7 @@ mod tests {
         let mut vm = Vm::new(Settings {
             enable_aggressive_inline: true,
             enable_source_maps: false,
+            ..Default::default()
         });
         let actual = Compiler::compile(&mut vm, "(+ 1 2)", &Bump::new()).unwrap();
         assert_eq!(
@@ -568,11 +757,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn aggressive_inline_with_wrong_number_of_args_to_builtin_is_compile_error() {
+        let mut vm = Vm::new(Settings {
+            enable_aggressive_inline: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            Compiler::compile(&mut vm, "(not 1 2)", &Bump::new()).unwrap_err(),
+            CompileError::ExpressionHasWrongArgs {
+                expression: "not",
+                expected: 1,
+                actual: 2
+            }
+        );
+        assert!(Compiler::compile(&mut vm, "(not 1)", &Bump::new()).is_ok());
+    }
+
+    #[test]
+    fn aggressive_inline_with_redefined_builtin_does_not_check_arity() {
+        let mut vm = Vm::new(Settings {
+            enable_aggressive_inline: true,
+            ..Default::default()
+        });
+        vm.eval_str("(define not list)").unwrap();
+        assert!(Compiler::compile(&mut vm, "(not 1 2)", &Bump::new()).is_ok());
+    }
+
     #[test]
     fn aggressive_inline_with_nonexistant_function_falls_back_to_deref() {
         let mut vm = Vm::new(Settings {
             enable_aggressive_inline: true,
             enable_source_maps: false,
+            ..Default::default()
         });
         let actual = Compiler::compile(&mut vm, "(does-not-exist 1 2)", &Bump::new()).unwrap();
         assert_eq!(
@@ -1260,6 +1477,30 @@ mod tests {
         assert_eq!(actual, CompileError::EmptyExpression);
     }
 
+    #[test]
+    fn let_bound_lambda_can_call_itself() {
+        let mut vm = Vm::default();
+        let src = r#"
+(let ([sum-to (lambda (n)
+                (if (< n 2)
+                    1
+                    (+ n (sum-to (- n 1)))))])
+  (sum-to 10))
+"#;
+        assert_eq!(vm.eval_str(src).unwrap().try_int().unwrap(), 55);
+    }
+
+    #[test]
+    fn let_bound_lambda_is_named_after_binding() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(let ([f (lambda () 1)]) f)")
+                .unwrap()
+                .to_string(),
+            "<function f>"
+        );
+    }
+
     ////////////////////////////////////////////////////////////////////////////////
     // BEGIN: Or
     ////////////////////////////////////////////////////////////////////////////////
@@ -1441,4 +1682,636 @@ mod tests {
             }
         );
     }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // BEGIN: Comment
+    ////////////////////////////////////////////////////////////////////////////////
+
+    #[test]
+    fn comment_compiles_to_void() {
+        let mut vm = Vm::default();
+        let src = "(comment (foo bar))";
+        assert_eq!(
+            Compiler::compile(&mut vm, src, &Bump::new()).unwrap(),
+            ByteCode {
+                name: "".into(),
+                arg_count: 0,
+                local_bindings: 0,
+                instructions: vec![Instruction::PushConst(UnsafeVal::Void)].into(),
+                source: Some(src.into()),
+                instruction_source: vec![Span::new(0, 19)].into(),
+            }
+        );
+        assert!(vm.eval_str(src).unwrap().is_void());
+        assert_eq!(
+            vm.eval_str("(define (f) 1 (comment (if)) 2) (f)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn comment_with_unparseable_contents_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            Compiler::compile(&mut vm, "(comment (unclosed", &Bump::new()).unwrap_err(),
+            CompileError::AstError(AstParseError::UnclosedParen)
+        );
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // BEGIN: If-Let and When-Let
+    ////////////////////////////////////////////////////////////////////////////////
+
+    #[test]
+    fn if_let_with_truthy_value_evaluates_true_branch_with_binding() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(if-let [x (+ 1 2)] (+ x 10) 0)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            13
+        );
+        assert_eq!(
+            vm.eval_str("(define (f y) (if-let (x y) x \"none\")) (f 0)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn if_let_with_falsey_value_evaluates_false_branch() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(if-let [x false] 1 2)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            2
+        );
+        assert!(vm
+            .eval_str("(if-let [x (if false 1)] 1)")
+            .unwrap()
+            .is_void());
+    }
+
+    #[test]
+    fn if_let_evaluates_expression_once() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define counter (new-box 0))").unwrap();
+        vm.eval_str("(define (next!) (set-box! counter (+ 1 (unbox counter))) (unbox counter))")
+            .unwrap();
+        assert_eq!(
+            vm.eval_str("(if-let [x (next!)] x)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            vm.eval_str("(unbox counter)").unwrap().try_int().unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn when_let_evaluates_body_only_when_truthy() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(when-let [x 2] (+ x 1) (+ x 2))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            4
+        );
+        assert!(vm.eval_str("(when-let [x false] 1)").unwrap().is_void());
+        assert!(vm.eval_str("(when-let [x 1])").unwrap().is_void());
+    }
+
+    #[test]
+    fn if_let_and_when_let_with_bad_form_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            Compiler::compile(&mut vm, "(if-let [x 1])", &Bump::new()).unwrap_err(),
+            CompileError::ExpressionHasWrongArgs {
+                expression: "if-let",
+                expected: 2,
+                actual: 1
+            }
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "(when-let)", &Bump::new()).unwrap_err(),
+            CompileError::ExpressionHasWrongArgs {
+                expression: "when-let",
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "(if-let [1 1] 1)", &Bump::new()).unwrap_err(),
+            CompileError::BadBinding {
+                expression: "if-let"
+            }
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "(when-let x 1)", &Bump::new()).unwrap_err(),
+            CompileError::BadBinding {
+                expression: "when-let"
+            }
+        );
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // BEGIN: Threading
+    ////////////////////////////////////////////////////////////////////////////////
+
+    #[test]
+    fn thread_first_is_same_as_nested_calls() {
+        let mut vm = Vm::default();
+        for (threaded, nested) in [
+            ("(-> 1 (- 10) (+ 100))", "(+ (- 1 10) 100)"),
+            ("(-> 1 inc list)", "(list (inc 1))"),
+            ("(-> 1)", "1"),
+        ] {
+            let threaded_bytecode = Compiler::compile(&mut vm, threaded, &Bump::new()).unwrap();
+            let nested_bytecode = Compiler::compile(&mut vm, nested, &Bump::new()).unwrap();
+            assert_eq!(threaded_bytecode.instructions, nested_bytecode.instructions);
+            let threaded_result = vm.eval_str(threaded).unwrap().to_string();
+            let nested_result = vm.eval_str(nested).unwrap().to_string();
+            assert_eq!(threaded_result, nested_result);
+        }
+        assert_eq!(
+            vm.eval_str("(-> 1 (- 10) (+ 100))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            91
+        );
+    }
+
+    #[test]
+    fn thread_last_is_same_as_nested_calls() {
+        let mut vm = Vm::default();
+        for (threaded, nested) in [
+            ("(->> 1 (- 10) (list 100))", "(list 100 (- 10 1))"),
+            ("(->> 1 inc list)", "(list (inc 1))"),
+        ] {
+            let threaded_bytecode = Compiler::compile(&mut vm, threaded, &Bump::new()).unwrap();
+            let nested_bytecode = Compiler::compile(&mut vm, nested, &Bump::new()).unwrap();
+            assert_eq!(threaded_bytecode.instructions, nested_bytecode.instructions);
+            let threaded_result = vm.eval_str(threaded).unwrap().to_string();
+            let nested_result = vm.eval_str(nested).unwrap().to_string();
+            assert_eq!(threaded_result, nested_result);
+        }
+        assert_eq!(
+            vm.eval_str("(->> 1 (- 10) (list 100))")
+                .unwrap()
+                .to_string(),
+            "(100 9)"
+        );
+    }
+
+    #[test]
+    fn thread_with_bad_form_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            Compiler::compile(&mut vm, "(->)", &Bump::new()).unwrap_err(),
+            CompileError::ExpressionHasWrongArgs {
+                expression: "->",
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "(->> 1 ())", &Bump::new()).unwrap_err(),
+            CompileError::EmptyExpression
+        );
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // BEGIN: Dotimes and Dolist
+    ////////////////////////////////////////////////////////////////////////////////
+
+    #[test]
+    fn dotimes_runs_body_with_each_index() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define total (new-box 0))").unwrap();
+        let got = vm
+            .eval_str("(dotimes (i 5) (set-box! total (+ (unbox total) i)))")
+            .unwrap();
+        assert!(got.is_void());
+        drop(got);
+        assert_eq!(vm.eval_str("(unbox total)").unwrap().try_int().unwrap(), 10);
+    }
+
+    #[test]
+    fn dotimes_with_zero_does_not_run_body() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define total (new-box 0))").unwrap();
+        vm.eval_str("(dotimes (i 0) (set-box! total 100))").unwrap();
+        assert_eq!(vm.eval_str("(unbox total)").unwrap().try_int().unwrap(), 0);
+    }
+
+    #[test]
+    fn dolist_runs_body_with_each_element() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define items (new-box (list)))").unwrap();
+        let got = vm
+            .eval_str("(dolist (x (list 1 2 3)) (set-box! items (list x (unbox items))))")
+            .unwrap();
+        assert!(got.is_void());
+        drop(got);
+        assert_eq!(
+            vm.eval_str("(unbox items)").unwrap().to_string(),
+            "(3 (2 (1 ())))"
+        );
+    }
+
+    #[test]
+    fn dotimes_and_dolist_can_use_enclosing_locals() {
+        let mut vm = Vm::default();
+        vm.eval_str(
+            r#"
+(define (sum-times n)
+  (let ([acc (new-box 0)])
+    (dotimes (i 3) (set-box! acc (+ (unbox acc) i n)))
+    (unbox acc)))
+(define (sum-list items scale)
+  (let ([acc (new-box 0)])
+    (dolist (x items) (set-box! acc (+ (unbox acc) x scale)))
+    (unbox acc)))
+"#,
+        )
+        .unwrap();
+        assert_eq!(vm.eval_str("(sum-times 4)").unwrap().try_int().unwrap(), 15);
+        assert_eq!(
+            vm.eval_str("(sum-list (list 1 2 3) 10)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            36
+        );
+    }
+
+    #[test]
+    fn nested_dotimes_and_dolist_see_outer_bindings() {
+        let mut vm = Vm::default();
+        let src = r#"
+(let ([pairs (new-box (list))])
+  (dolist (x (list 1 2))
+    (dotimes (i 2)
+      (set-box! pairs (list (list x i) (unbox pairs)))))
+  (unbox pairs))
+"#;
+        assert_eq!(
+            vm.eval_str(src).unwrap().to_string(),
+            "((2 1) ((2 0) ((1 1) ((1 0) ()))))"
+        );
+    }
+
+    #[test]
+    fn dotimes_and_dolist_do_not_depend_on_global_definitions() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define for-each 1) (define range-seq 2) (define + 3)")
+            .unwrap();
+        assert!(vm.eval_str("(dotimes (i 3) i)").unwrap().is_void());
+        assert!(vm.eval_str("(dolist (x (list 1 2)) x)").unwrap().is_void());
+    }
+
+    #[test]
+    fn dolist_iterates_over_range_seq() {
+        let mut vm = Vm::default();
+        let src = "(let ([acc (new-box 0)])
+                     (dolist (x (range-seq 1 4)) (set-box! acc (list x (unbox acc))))
+                     (unbox acc))";
+        assert_eq!(vm.eval_str(src).unwrap().to_string(), "(3 (2 (1 0)))");
+    }
+
+    #[test]
+    fn recur_within_dotimes_body_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            Compiler::compile(
+                &mut vm,
+                "(loop ([x 1]) (dotimes (i 2) (recur 2)))",
+                &Bump::new()
+            )
+            .unwrap_err(),
+            CompileError::RecurNotInTailPosition
+        );
+    }
+
+    #[test]
+    fn dotimes_and_dolist_with_bad_binding_return_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            Compiler::compile(&mut vm, "(dotimes)", &Bump::new()).unwrap_err(),
+            CompileError::ExpressionHasWrongArgs {
+                expression: "dotimes",
+                expected: 1,
+                actual: 0
+            }
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "(dotimes i 10)", &Bump::new()).unwrap_err(),
+            CompileError::BadBinding {
+                expression: "dotimes"
+            }
+        );
+        assert_eq!(
+            Compiler::compile(&mut vm, "(dolist (1 (list)) 1)", &Bump::new()).unwrap_err(),
+            CompileError::BadBinding {
+                expression: "dolist"
+            }
+        );
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////
+    // BEGIN: Macros
+    ////////////////////////////////////////////////////////////////////////////////
+
+    const SWAP_MACRO: &str = r#"
+(defmacro (swap! a b)
+  (list 'let (list (list 'tmp (list 'unbox a)))
+        (list 'set-box! a (list 'unbox b))
+        (list 'set-box! b 'tmp)))
+"#;
+
+    #[test]
+    fn macro_call_is_expanded_before_compiling() {
+        let mut vm = Vm::default();
+        vm.eval_str(SWAP_MACRO).unwrap();
+        let expanded = Compiler::compile(&mut vm, "(swap! x y)", &Bump::new()).unwrap();
+        let explicit = Compiler::compile(
+            &mut vm,
+            "(let ((tmp (unbox x))) (set-box! x (unbox y)) (set-box! y tmp))",
+            &Bump::new(),
+        )
+        .unwrap();
+        assert_eq!(expanded.instructions, explicit.instructions);
+    }
+
+    #[test]
+    fn macro_can_be_used_after_definition() {
+        let mut vm = Vm::default();
+        let src = format!(
+            "{SWAP_MACRO}
+(define x (new-box 1))
+(define y (new-box 2))
+(swap! x y)
+(list (unbox x) (unbox y))"
+        );
+        assert_eq!(vm.eval_str(&src).unwrap().to_string(), "(2 1)");
+    }
+
+    #[test]
+    fn macro_within_expression_and_macro_output_are_expanded() {
+        let mut vm = Vm::default();
+        vm.eval_str(
+            r#"
+(defmacro (unless pred expr) (list 'if pred void expr))
+(defmacro (when-not pred expr) (list 'unless pred expr))
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(list (when-not false \"yes\") (unless true 1))")
+                .unwrap()
+                .to_string(),
+            "(\"yes\" <void>)"
+        );
+    }
+
+    #[test]
+    fn defmacro_not_at_top_level_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            Compiler::compile(&mut vm, "(list (defmacro (m) 1))", &Bump::new()).unwrap_err(),
+            CompileError::DefmacroNotAllowed
+        );
+    }
+
+    #[test]
+    fn macro_that_fails_returns_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (bad-macro) (+ 1 true)) (defmacro (box-macro) (new-box 1))")
+            .unwrap();
+        assert!(matches!(
+            Compiler::compile(&mut vm, "(bad-macro)", &Bump::new()).unwrap_err(),
+            CompileError::MacroExpansionFailed { name, .. } if name == "bad-macro"
+        ));
+        assert!(matches!(
+            Compiler::compile(&mut vm, "(box-macro)", &Bump::new()).unwrap_err(),
+            CompileError::MacroExpansionFailed { name, .. } if name == "box-macro"
+        ));
+    }
+
+    #[test]
+    fn macro_that_fails_restores_stack_frames() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (bad-macro) (+ 1 true))").unwrap();
+        let depth = vm.stack_frames.depth();
+        let stack_len = vm.stack.len();
+        assert!(Compiler::compile(&mut vm, "(bad-macro)", &Bump::new()).is_err());
+        assert_eq!(vm.stack_frames.depth(), depth);
+        assert_eq!(vm.stack.len(), stack_len);
+    }
+
+    #[test]
+    fn recursive_macro_returns_error() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (forever) (list 'forever))").unwrap();
+        assert!(matches!(
+            Compiler::compile(&mut vm, "(forever)", &Bump::new()).unwrap_err(),
+            CompileError::MacroExpansionFailed { name, .. } if name == "forever"
+        ));
+    }
+
+    #[test]
+    fn errors_within_macro_args_point_to_original_source() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (identity x) x)").unwrap();
+        let src = "(identity (+ 1 \"x\"))";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(10, 19).with_src(src.into())),
+                context: "+",
+                expected: "int or float",
+                actual: UnsafeVal::STRING_TYPE_NAME,
+                value: "\"x\"".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn macro_args_are_not_converted_to_source() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (identity x) x)").unwrap();
+        assert_eq!(
+            vm.eval_str("(identity 1.0e400)")
+                .unwrap()
+                .try_float()
+                .unwrap(),
+            f64::INFINITY
+        );
+        assert_eq!(
+            vm.eval_str("(identity \"a \\\"quoted\\\" string\")")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "a \"quoted\" string"
+        );
+    }
+
+    #[test]
+    fn defmacro_within_string_does_not_expand_macros() {
+        let mut vm = Vm::default();
+        let src = "(string-length \"defmacro\") ; defmacro";
+        let span = Span::new(0, src.len() as u32);
+        assert!(macros::expand_macros(&mut vm, src, span, &Bump::new())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn values_that_can_not_be_read_back_are_not_written_as_source() {
+        let mut vm = Vm::default();
+        let mut out = String::new();
+        assert!(write_val_as_source(&vm, UnsafeVal::Float(f64::INFINITY), &mut out).is_err());
+        assert!(write_val_as_source(&vm, UnsafeVal::Float(f64::NAN), &mut out).is_err());
+        for name in ["1", "true", "a b", "(a)"] {
+            let symbol = UnsafeVal::Symbol(vm.get_or_create_symbol(name));
+            assert!(
+                write_val_as_source(&vm, symbol, &mut out).is_err(),
+                "{name}"
+            );
+        }
+        let symbol = UnsafeVal::Symbol(vm.get_or_create_symbol("a-b"));
+        write_val_as_source(&vm, symbol, &mut out).unwrap();
+        assert_eq!(out, "a-b");
+    }
+
+    #[test]
+    fn self_referential_lists_are_not_converted_to_source() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define l (list 1)) (list-set! l 0 l)")
+            .unwrap();
+        let err = vm.eval_str("(eval l)").unwrap_err().to_string();
+        assert!(err.contains("can not be converted to source code"), "{err}");
+        vm.eval_str("(defmacro (self-referential) l)").unwrap();
+        let err = vm.eval_str("(self-referential)").unwrap_err().to_string();
+        assert!(err.contains("can not be converted to source code"), "{err}");
+        vm.eval_str("(define shared (list '+ 1 2))").unwrap();
+        assert_eq!(
+            vm.eval_str("(eval (list '+ shared shared))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            6
+        );
+    }
+
+    #[test]
+    fn macros_survive_garbage_collection() {
+        let mut vm = Vm::default();
+        vm.eval_str("(defmacro (one) 1)").unwrap();
+        unsafe { vm.run_gc() };
+        assert_eq!(
+            vm.eval_str("(+ (one) (one))").unwrap().try_int().unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn loop_with_recur_sums_without_growing_stack() {
+        let mut vm = Vm::default();
+        let src = "(loop ([i 1] [acc 0]) (if (< 1000000 i) acc (recur (+ i 1) (+ acc i))))";
+        assert_eq!(vm.eval_str(src).unwrap().try_int().unwrap(), 500000500000);
+    }
+
+    #[test]
+    fn loop_without_recur_evaluates_body_once() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(loop ([x 1] [y (+ x 1)]) (+ x y))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+        assert!(vm.eval_str("(loop ())").unwrap().is_void());
+    }
+
+    #[test]
+    fn loop_body_with_multiple_expressions_keeps_last() {
+        let mut vm = Vm::default();
+        let src = "(loop ([i 0]) (+ i 100) (if (< i 10) (recur (+ i 1)) i))";
+        assert_eq!(vm.eval_str(src).unwrap().try_int().unwrap(), 10);
+    }
+
+    #[test]
+    fn recur_targets_innermost_loop() {
+        let mut vm = Vm::default();
+        let src = "(loop ([i 0] [total 0])
+                     (if (< i 3)
+                         (recur (+ i 1)
+                                (+ total (loop ([j 0]) (if (< j 10) (recur (+ j 1)) j))))
+                         total))";
+        assert_eq!(vm.eval_str(src).unwrap().try_int().unwrap(), 30);
+    }
+
+    #[test]
+    fn recur_in_function_tail_position_rebinds_arguments() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define (sum-to n acc) (if (< n 1) acc (recur (- n 1) (+ acc n))))")
+            .unwrap();
+        assert_eq!(
+            vm.eval_str("(sum-to 1000000 0)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            500000500000
+        );
+    }
+
+    #[test]
+    fn recur_with_wrong_number_of_args_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.eval_str("(loop ([i 0] [j 0]) (recur 1))").unwrap_err(),
+            CompileError::ExpressionHasWrongArgs {
+                expression: "recur",
+                expected: 2,
+                actual: 1,
+            }
+            .into()
+        );
+    }
+
+    #[test]
+    fn recur_outside_of_tail_position_returns_error() {
+        let mut vm = Vm::default();
+        for src in [
+            "(recur 1)",
+            "(let ([x 1]) (recur x))",
+            "(loop ([i 0]) (+ 1 (recur i)))",
+            "(loop ([i 0]) (recur i) i)",
+            "(loop ([i (recur 1)]) i)",
+            "(loop ([i 0]) (if (recur i) 1 2))",
+            "(define (f x) (+ 1 (recur x)))",
+            "(define (f x) (return (recur x)))",
+        ] {
+            assert_eq!(
+                vm.eval_str(src).unwrap_err(),
+                CompileError::RecurNotInTailPosition.into(),
+                "{src}"
+            );
+        }
+    }
 }
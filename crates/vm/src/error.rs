@@ -2,10 +2,14 @@
 use std::sync::Arc;
 
 use compact_str::CompactString;
+use serde::Serialize;
 use thiserror::Error;
 
 use crate::{
-    parser::{ast::AstParseError, span::SpanWithSource},
+    parser::{
+        ast::AstParseError,
+        span::{Span, SpanWithSource},
+    },
     val::custom::CustomValError,
 };
 
@@ -23,116 +27,231 @@ pub enum VmError {
         value: String,
     },
     ArityError {
+        src: Option<SpanWithSource<Arc<str>>>,
         function: CompactString,
         expected: usize,
         actual: usize,
     },
-    CompileError(CompileError),
+    CompileError {
+        src: Option<SpanWithSource<Arc<str>>>,
+        error: CompileError,
+    },
     InvalidVmState(BacktraceError),
     SymbolNotDefined {
         src: Option<SpanWithSource<Arc<str>>>,
         symbol: String,
     },
     MaximumFunctionCallDepth {
+        src: Option<SpanWithSource<Arc<str>>>,
         max_depth: usize,
         call_stack: Vec<CompactString>,
     },
-    CustomValError(CustomValError),
-    CustomError(String),
+    CustomValError {
+        src: Option<SpanWithSource<Arc<str>>>,
+        error: CustomValError,
+    },
+    CustomError {
+        src: Option<SpanWithSource<Arc<str>>>,
+        message: String,
+    },
+    ForeignValue {
+        expected_vm_id: u16,
+        actual_vm_id: u16,
+    },
+    Overflow {
+        src: Option<SpanWithSource<Arc<str>>>,
+        context: &'static str,
+    },
+    IoError {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
 }
 
 impl VmError {
+    /// Returns `true` if the error may be recovered from by Spore code, like with a future
+    /// `try`/`catch`. These are errors caused by the evaluated code, like type errors or calling
+    /// undefined values.
+    ///
+    /// Returns `false` for fatal errors. These are internal invariant violations or errors caused
+    /// by misuse of the Rust API, like passing a value from a different VM.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            VmError::TypeError { .. }
+            | VmError::ArityError { .. }
+            | VmError::CompileError { .. }
+            | VmError::SymbolNotDefined { .. }
+            | VmError::MaximumFunctionCallDepth { .. }
+            | VmError::CustomValError { .. }
+            | VmError::CustomError { .. }
+            | VmError::Overflow { .. } => true,
+            VmError::InvalidVmState(_) | VmError::ForeignValue { .. } | VmError::IoError { .. } => {
+                false
+            }
+        }
+    }
+
     /// Return the error with the given source context added.
-    pub fn with_src(self, src: SpanWithSource<Arc<str>>) -> VmError {
+    ///
+    /// Every recoverable error can hold source context. Fatal errors are returned unchanged.
+    pub fn with_src(mut self, src: SpanWithSource<Arc<str>>) -> VmError {
+        if let Some(slot) = self.src_mut() {
+            *slot = Some(src);
+        }
+        self
+    }
+
+    /// Returns the source code related to the error, if it is known.
+    pub fn src(&self) -> Option<&SpanWithSource<Arc<str>>> {
         match self {
-            VmError::TypeError {
-                src: _src,
-                context,
-                expected,
-                actual,
-                value,
-            } => VmError::TypeError {
-                src: Some(src),
-                context,
-                expected,
-                actual,
-                value,
-            },
-            VmError::ArityError {
-                function,
-                expected,
-                actual,
-            } => VmError::ArityError {
-                function,
-                expected,
-                actual,
-            },
-            VmError::CompileError(e) => VmError::CompileError(e),
-            VmError::InvalidVmState(e) => VmError::InvalidVmState(e),
-            VmError::SymbolNotDefined { symbol, .. } => VmError::SymbolNotDefined {
-                src: Some(src),
-                symbol,
-            },
-            VmError::MaximumFunctionCallDepth {
-                max_depth,
-                call_stack,
-            } => VmError::MaximumFunctionCallDepth {
-                max_depth,
-                call_stack,
+            VmError::TypeError { src, .. }
+            | VmError::ArityError { src, .. }
+            | VmError::CompileError { src, .. }
+            | VmError::SymbolNotDefined { src, .. }
+            | VmError::MaximumFunctionCallDepth { src, .. }
+            | VmError::CustomValError { src, .. }
+            | VmError::CustomError { src, .. }
+            | VmError::Overflow { src, .. } => src.as_ref(),
+            VmError::InvalidVmState(_) | VmError::ForeignValue { .. } | VmError::IoError { .. } => {
+                None
+            }
+        }
+    }
+
+    fn src_mut(&mut self) -> Option<&mut Option<SpanWithSource<Arc<str>>>> {
+        match self {
+            VmError::TypeError { src, .. }
+            | VmError::ArityError { src, .. }
+            | VmError::CompileError { src, .. }
+            | VmError::SymbolNotDefined { src, .. }
+            | VmError::MaximumFunctionCallDepth { src, .. }
+            | VmError::CustomValError { src, .. }
+            | VmError::CustomError { src, .. }
+            | VmError::Overflow { src, .. } => Some(src),
+            VmError::InvalidVmState(_) | VmError::ForeignValue { .. } | VmError::IoError { .. } => {
+                None
+            }
+        }
+    }
+
+    /// Convert the error into a machine readable [Diagnostic] for tools like editors.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            message: VmErrorMessage(self).to_string(),
+            severity: if self.is_recoverable() {
+                Severity::Error
+            } else {
+                Severity::Fatal
             },
-            VmError::CustomValError(e) => VmError::CustomValError(e),
-            VmError::CustomError(e) => VmError::CustomError(e),
+            span: self.src().map(|src| src.span),
         }
     }
 }
 
+/// A machine readable description of a [VmError].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// The error message without the related source code.
+    pub message: String,
+    /// How severe the error is.
+    pub severity: Severity,
+    /// The byte offsets of the source code related to the error.
+    pub span: Option<Span>,
+}
+
+/// The severity of a [Diagnostic].
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// An error caused by the evaluated code. See [VmError::is_recoverable].
+    Error,
+    /// An internal error or an error caused by misuse of the Rust API.
+    Fatal,
+}
+
 impl std::fmt::Display for VmError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let format_src = |f: &mut std::fmt::Formatter<'_>,
-                          src: &Option<SpanWithSource<Arc<str>>>| {
-            if let Some(src) = src {
-                write!(f, "\n{}\n", src.contextual_formatter())?;
-            }
-            Ok(())
-        };
-        match self {
+        write!(f, "{}", VmErrorMessage(self))?;
+        if let Some(src) = self.src() {
+            write!(f, "\n{}\n", src.contextual_formatter())?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the message of a [VmError] without the related source code.
+struct VmErrorMessage<'a>(&'a VmError);
+
+impl std::fmt::Display for VmErrorMessage<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
             VmError::TypeError {
-                src,
                 context,
                 expected,
                 actual,
                 value,
+                ..
             } => {
                 write!(
                     f,
                     "{context} expected type {expected} but got {actual}: {value}"
-                )?;
-                format_src(f, src)
+                )
             }
             VmError::ArityError {
                 function,
                 expected,
                 actual,
+                ..
             } => write!(f, "{function} expected {expected} args but got {actual}."),
-            VmError::CompileError(e) => write!(f, "{e}"),
+            VmError::CompileError { error, .. } => write!(f, "{error}"),
             VmError::InvalidVmState(bt) => write!(f, "VM reached invalid state.\n{bt}"),
-            VmError::SymbolNotDefined { symbol, src } => {
-                write!(f, "Value {symbol} is not defined.")?;
-                format_src(f, src)
+            VmError::SymbolNotDefined { symbol, .. } => {
+                write!(f, "Value {symbol} is not defined.")
             }
             VmError::MaximumFunctionCallDepth {
                 max_depth,
                 call_stack,
+                ..
+            } => {
+                writeln!(f, "Maximum function call depth of {max_depth} reached: [")?;
+                write_collapsed_call_stack(f, call_stack)?;
+                write!(f, "]")
+            }
+            VmError::CustomValError { error, .. } => write!(f, "{error}"),
+            VmError::CustomError { message, .. } => write!(f, "{message}"),
+            VmError::ForeignValue {
+                expected_vm_id,
+                actual_vm_id,
             } => write!(
                 f,
-                "Maximum function call depth of {max_depth} reached: {call_stack:#?}"
+                "Expected value from VM {expected_vm_id} but value belongs to VM {actual_vm_id}."
             ),
-            VmError::CustomValError(e) => write!(f, "{e}"),
-            VmError::CustomError(e) => write!(f, "{e}"),
+            VmError::Overflow { context, .. } => {
+                write!(f, "{context} resulted in an integer overflow.")
+            }
+            VmError::IoError { message, .. } => write!(f, "IO error: {message}"),
         }
     }
 }
 
+/// Write each function in `call_stack` on its own line. Runs of the same function are collapsed
+/// into a single line like `"recurse" × 64`.
+fn write_collapsed_call_stack(
+    f: &mut std::fmt::Formatter<'_>,
+    call_stack: &[CompactString],
+) -> std::fmt::Result {
+    let mut remaining = call_stack;
+    while let Some(name) = remaining.first() {
+        let run_length = remaining.iter().take_while(|n| *n == name).count();
+        match run_length {
+            1 => writeln!(f, "    {name:?},")?,
+            n => writeln!(f, "    {name:?} × {n},")?,
+        }
+        remaining = &remaining[run_length..];
+    }
+    Ok(())
+}
+
 impl std::error::Error for VmError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -140,17 +259,32 @@ impl std::error::Error for VmError {
             | VmError::ArityError { .. }
             | VmError::SymbolNotDefined { .. }
             | VmError::MaximumFunctionCallDepth { .. }
-            | VmError::CustomError(_) => None,
-            VmError::CompileError(e) => Some(e),
+            | VmError::CustomError { .. }
+            | VmError::ForeignValue { .. }
+            | VmError::Overflow { .. }
+            | VmError::IoError { .. } => None,
+            VmError::CompileError { error, .. } => Some(error),
             VmError::InvalidVmState(e) => Some(e),
-            VmError::CustomValError(e) => Some(e),
+            VmError::CustomValError { error, .. } => Some(error),
         }
     }
 }
 
 impl From<String> for VmError {
     fn from(v: String) -> VmError {
-        VmError::CustomError(v)
+        VmError::CustomError {
+            src: None,
+            message: v,
+        }
+    }
+}
+
+impl From<std::io::Error> for VmError {
+    fn from(v: std::io::Error) -> VmError {
+        VmError::IoError {
+            kind: v.kind(),
+            message: v.to_string(),
+        }
     }
 }
 
@@ -162,13 +296,19 @@ impl From<BacktraceError> for VmError {
 
 impl From<CompileError> for VmError {
     fn from(v: CompileError) -> VmError {
-        VmError::CompileError(v)
+        VmError::CompileError {
+            src: None,
+            error: v,
+        }
     }
 }
 
 impl From<CustomValError> for VmError {
     fn from(v: CustomValError) -> VmError {
-        VmError::CustomValError(v)
+        VmError::CustomValError {
+            src: None,
+            error: v,
+        }
     }
 }
 
@@ -229,8 +369,22 @@ pub enum CompileError {
     ExpectedIdentifierList { context: &'static str },
     #[error("let expected form: (let ([binding-a expr-a] [binding-b expr-b] ..) (exprs..))")]
     BadLetBindings,
+    #[error("{expression} expected binding of the form: [binding expr]")]
+    BadBinding { expression: &'static str },
     #[error("argument {0} was defined multiple times")]
     ArgumentDefinedMultipleTimes(CompactString),
+    #[error("defmacro is not allowed in this context, defmacro is only allowed at the top level")]
+    DefmacroNotAllowed,
+    #[error("failed to expand macro {name}: {reason}")]
+    MacroExpansionFailed { name: CompactString, reason: String },
+    #[error("recur is only allowed in the tail position of a loop or function")]
+    RecurNotInTailPosition,
+    #[error("jump of {offset} at instruction {instruction_idx} lands outside of bytecode with {instruction_count} instructions")]
+    InvalidJump {
+        instruction_idx: usize,
+        offset: usize,
+        instruction_count: usize,
+    },
 }
 
 #[cfg(test)]
@@ -259,6 +413,168 @@ Source:
         );
     }
 
+    #[test]
+    fn errors_from_evaluated_code_are_recoverable() {
+        let recoverable = [
+            VmError::TypeError {
+                src: None,
+                context: "",
+                expected: "",
+                actual: "",
+                value: "".into(),
+            },
+            VmError::ArityError {
+                src: None,
+                function: "".into(),
+                expected: 0,
+                actual: 1,
+            },
+            VmError::CompileError {
+                src: None,
+                error: CompileError::EmptyExpression,
+            },
+            VmError::SymbolNotDefined {
+                src: None,
+                symbol: "".into(),
+            },
+            VmError::MaximumFunctionCallDepth {
+                src: None,
+                max_depth: 0,
+                call_stack: Vec::new(),
+            },
+            VmError::CustomValError {
+                src: None,
+                error: CustomValError::ForeignValue,
+            },
+            VmError::CustomError {
+                src: None,
+                message: "".into(),
+            },
+            VmError::Overflow {
+                src: None,
+                context: "",
+            },
+        ];
+        for err in recoverable {
+            assert!(err.is_recoverable(), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn type_error_diagnostic_serializes_to_json() {
+        let mut vm = Vm::default();
+        let err = vm.eval_str("(+ 1 \"one\")").unwrap_err();
+        assert_eq!(
+            serde_json::to_string(&err.to_diagnostic()).unwrap(),
+            r#"{"message":"+ expected type int or float but got string: \"one\"","severity":"error","span":{"start":0,"end":11}}"#
+        );
+    }
+
+    #[test]
+    fn diagnostic_without_source_has_no_span() {
+        let err = VmError::ForeignValue {
+            expected_vm_id: 1,
+            actual_vm_id: 2,
+        };
+        assert_eq!(
+            serde_json::to_value(err.to_diagnostic()).unwrap(),
+            serde_json::json!({
+                "message": "Expected value from VM 1 but value belongs to VM 2.",
+                "severity": "fatal",
+                "span": null,
+            })
+        );
+    }
+
+    #[test]
+    fn with_src_adds_source_to_recoverable_errors_only() {
+        let src = Span::new(0, 3).with_src(Arc::from("(-)"));
+        for err in [
+            VmError::ArityError {
+                src: None,
+                function: "-".into(),
+                expected: 1,
+                actual: 0,
+            },
+            VmError::CompileError {
+                src: None,
+                error: CompileError::EmptyExpression,
+            },
+            VmError::CustomValError {
+                src: None,
+                error: CustomValError::LockNotAvailable,
+            },
+            VmError::CustomError {
+                src: None,
+                message: "".into(),
+            },
+        ] {
+            assert_eq!(err.with_src(src.clone()).src(), Some(&src));
+        }
+        let fatal = VmError::ForeignValue {
+            expected_vm_id: 1,
+            actual_vm_id: 2,
+        };
+        assert_eq!(fatal.with_src(src).src(), None);
+    }
+
+    #[test]
+    fn internal_and_api_misuse_errors_are_fatal() {
+        let fatal = [
+            VmError::InvalidVmState(BacktraceError::capture()),
+            VmError::ForeignValue {
+                expected_vm_id: 1,
+                actual_vm_id: 2,
+            },
+            VmError::IoError {
+                kind: std::io::ErrorKind::UnexpectedEof,
+                message: "".into(),
+            },
+        ];
+        for err in fatal {
+            assert!(!err.is_recoverable(), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn errors_from_evaluation_are_classified() {
+        let mut vm = Vm::default();
+        assert!(vm.eval_str("(+ 1 \"1\")").unwrap_err().is_recoverable());
+        assert!(vm
+            .eval_str("(undefined-value)")
+            .unwrap_err()
+            .is_recoverable());
+        assert!(vm.eval_str("(").unwrap_err().is_recoverable());
+    }
+
+    #[test]
+    fn maximum_function_call_depth_collapses_repeated_functions() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define (recurse) (recurse))").unwrap();
+        let err = vm.eval_str("(recurse)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Maximum function call depth of 65 reached: [\n    \"\",\n    \"recurse\" × 64,\n]\nSource:\n  1: (recurse)\n"
+        );
+        assert!(matches!(
+            err,
+            VmError::MaximumFunctionCallDepth { call_stack, .. } if call_stack.len() == 65
+        ));
+    }
+
+    #[test]
+    fn maximum_function_call_depth_keeps_order_of_distinct_functions() {
+        let err = VmError::MaximumFunctionCallDepth {
+            src: None,
+            max_depth: 5,
+            call_stack: ["", "a", "b", "b", "a"].map(Into::into).to_vec(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Maximum function call depth of 5 reached: [\n    \"\",\n    \"a\",\n    \"b\" × 2,\n    \"a\",\n]"
+        );
+    }
+
     #[test]
     fn backtraces_are_all_eq() {
         // Backtraces are abstract so we assume (mostly for tests sake) that they are not
@@ -269,7 +585,11 @@ Source:
     #[test]
     fn hacks_for_code_coverage() {
         // A collection of functions that are not worth testing.
-        VmError::CustomError("".to_string()).source();
+        VmError::CustomError {
+            src: None,
+            message: "".to_string(),
+        }
+        .source();
         assert_ne!(CompileError::EmptyExpression.to_string(), "");
         assert_ne!(format!("{:?}", CompileError::EmptyExpression), "");
         assert_ne!(BacktraceError::capture().to_string(), "");
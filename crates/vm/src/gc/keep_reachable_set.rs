@@ -6,7 +6,10 @@ use std::{
 use compact_str::CompactString;
 use log::*;
 
-use crate::val::{custom::CustomVal, ByteCode, ListVal, StructVal, UnsafeVal, ValId};
+use crate::val::{
+    custom::CustomVal, ArrayVal, ByteCode, BytesVal, ListVal, StructVal, Symbol, UnsafeVal, ValId,
+    VectorVal,
+};
 
 use super::is_garbage_collected;
 
@@ -19,9 +22,15 @@ pub struct KeepReachableSet {
     strings: HashMap<ValId<CompactString>, ReferenceCounter>,
     mutable_boxes: HashMap<ValId<UnsafeVal>, ReferenceCounter>,
     lists: HashMap<ValId<ListVal>, ReferenceCounter>,
+    vectors: HashMap<ValId<VectorVal>, ReferenceCounter>,
+    arrays: HashMap<ValId<ArrayVal>, ReferenceCounter>,
+    bytes: HashMap<ValId<BytesVal>, ReferenceCounter>,
     structs: HashMap<ValId<StructVal>, ReferenceCounter>,
     bytecodes: HashMap<ValId<ByteCode>, ReferenceCounter>,
     customs: HashMap<ValId<CustomVal>, ReferenceCounter>,
+    /// Symbols are only garbage collected if symbol garbage collection is enabled, but they are
+    /// always tracked so that they stay pinned if it is.
+    symbols: HashMap<Symbol, ReferenceCounter>,
 }
 
 impl KeepReachableSet {
@@ -33,8 +42,13 @@ impl KeepReachableSet {
             .map(Into::into)
             .chain(self.mutable_boxes.keys().copied().map(Into::into))
             .chain(self.lists.keys().copied().map(Into::into))
+            .chain(self.vectors.keys().copied().map(Into::into))
+            .chain(self.arrays.keys().copied().map(Into::into))
+            .chain(self.bytes.keys().copied().map(Into::into))
+            .chain(self.structs.keys().copied().map(Into::into))
             .chain(self.bytecodes.keys().copied().map(Into::into))
             .chain(self.customs.keys().copied().map(Into::into))
+            .chain(self.symbols.keys().copied().map(UnsafeVal::Symbol))
     }
 
     /// Insert a value into the set of reachable values.
@@ -43,9 +57,13 @@ impl KeepReachableSet {
             UnsafeVal::String(x) => self.strings.increment(x),
             UnsafeVal::MutableBox(x) => self.mutable_boxes.increment(x),
             UnsafeVal::List(x) => self.lists.increment(x),
+            UnsafeVal::Vector(x) => self.vectors.increment(x),
+            UnsafeVal::Array(x) => self.arrays.increment(x),
+            UnsafeVal::Bytes(x) => self.bytes.increment(x),
             UnsafeVal::Struct(x) => self.structs.increment(x),
             UnsafeVal::ByteCodeFunction(x) => self.bytecodes.increment(x),
             UnsafeVal::Custom(x) => self.customs.increment(x),
+            UnsafeVal::Symbol(x) => self.symbols.increment(x),
             v => assert!(!is_garbage_collected(v)),
         }
     }
@@ -56,9 +74,13 @@ impl KeepReachableSet {
             UnsafeVal::String(x) => self.strings.decrement(x),
             UnsafeVal::MutableBox(x) => self.mutable_boxes.decrement(x),
             UnsafeVal::List(x) => self.lists.decrement(x),
+            UnsafeVal::Vector(x) => self.vectors.decrement(x),
+            UnsafeVal::Array(x) => self.arrays.decrement(x),
+            UnsafeVal::Bytes(x) => self.bytes.decrement(x),
             UnsafeVal::Struct(x) => self.structs.decrement(x),
             UnsafeVal::ByteCodeFunction(x) => self.bytecodes.decrement(x),
             UnsafeVal::Custom(x) => self.customs.decrement(x),
+            UnsafeVal::Symbol(x) => self.symbols.decrement(x),
             v => assert!(!is_garbage_collected(v)),
         }
     }
@@ -101,3 +123,11 @@ impl<T: std::fmt::Debug> ReachableStoreSealed for HashMap<ValId<T>, ReferenceCou
         self
     }
 }
+
+impl ReachableStoreSealed for HashMap<Symbol, ReferenceCounter> {
+    type K = Symbol;
+
+    fn as_mut_hashmap(&mut self) -> &mut HashMap<Self::K, ReferenceCounter> {
+        self
+    }
+}
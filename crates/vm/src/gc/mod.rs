@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use bumpalo::Bump;
 use compact_str::CompactString;
 use keep_reachable_set::KeepReachableSet;
@@ -6,8 +11,14 @@ use log::*;
 use symbol_interner::SymbolInterner;
 
 use crate::{
+    error::{VmError, VmResult},
     gc::object_store::{Color, ObjectStore},
-    val::{custom::CustomVal, ByteCode, ListVal, StructVal, Symbol, UnsafeVal, ValId},
+    log_limiter::LogRateLimiter,
+    parser::span::Span,
+    val::{
+        custom::CustomVal, ArrayVal, ByteCode, BytesVal, Instruction, ListVal, StructVal, Symbol,
+        UnsafeVal, ValId, VectorVal,
+    },
 };
 mod keep_reachable_set;
 mod object_store;
@@ -15,6 +26,17 @@ mod symbol_interner;
 
 type BumpVec<'a, T> = bumpalo::collections::Vec<'a, T>;
 
+/// The number of objects and memory used by a single object store.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StoreStats {
+    /// The name of the store.
+    pub name: &'static str,
+    /// The number of objects in the store.
+    pub count: usize,
+    /// The approximate number of bytes used by the store.
+    pub bytes: usize,
+}
+
 /// ValStore manages the lifetime of Val objects.
 #[derive(Debug)]
 pub struct MemoryManager {
@@ -23,10 +45,18 @@ pub struct MemoryManager {
     strings: ObjectStore<CompactString>,
     mutable_boxes: ObjectStore<UnsafeVal>,
     lists: ObjectStore<ListVal>,
+    vectors: ObjectStore<VectorVal>,
+    arrays: ObjectStore<ArrayVal>,
+    bytes: ObjectStore<BytesVal>,
     structs: ObjectStore<StructVal>,
     bytecodes: ObjectStore<ByteCode>,
+    /// Ids of deduplicated bytecode keyed by [ByteCode::content_hash].
+    bytecode_ids_by_hash: HashMap<u64, Vec<ValId<ByteCode>>>,
     customs: ObjectStore<CustomVal>,
     keep_reachable: KeepReachableSet,
+    /// Values that should be removed from `keep_reachable` before the next garbage collection.
+    /// Used by values that are released without access to the memory manager.
+    pending_unreachable: Arc<Mutex<Vec<UnsafeVal>>>,
     reachable_color: Color,
 }
 
@@ -39,14 +69,35 @@ impl MemoryManager {
             strings: ObjectStore::default(),
             mutable_boxes: ObjectStore::default(),
             lists: ObjectStore::default(),
+            vectors: ObjectStore::default(),
+            arrays: ObjectStore::default(),
+            bytes: ObjectStore::default(),
             structs: ObjectStore::default(),
             bytecodes: ObjectStore::default(),
+            bytecode_ids_by_hash: HashMap::new(),
             customs: ObjectStore::default(),
             keep_reachable: KeepReachableSet::default(),
+            pending_unreachable: Arc::default(),
             reachable_color: Color::default(),
         }
     }
 
+    /// Returns `true` if `id` was created by this memory manager.
+    pub fn owns<T>(&self, id: ValId<T>) -> bool {
+        id.vm_id == self.vm_id
+    }
+
+    /// Returns an error if `v` holds an object that was created by a different VM.
+    pub fn check_owned(&self, v: UnsafeVal) -> VmResult<()> {
+        match owning_vm_id(v) {
+            Some(vm_id) if vm_id != self.vm_id => Err(VmError::ForeignValue {
+                expected_vm_id: self.vm_id,
+                actual_vm_id: vm_id,
+            }),
+            _ => Ok(()),
+        }
+    }
+
     /// Get an interned string's value.
     pub fn symbol_to_str(&self, s: Symbol) -> Option<&str> {
         self.interned_symbols.symbol_to_str(s)
@@ -62,20 +113,138 @@ impl MemoryManager {
         self.interned_symbols.get_or_create_symbol(self.vm_id, s)
     }
 
+    /// Reserve capacity for at least `additional` more interned strings.
+    pub fn reserve_symbols(&mut self, additional: usize) {
+        self.interned_symbols.reserve(additional);
+    }
+
+    /// Get the number of interned strings.
+    pub fn symbol_count(&self) -> usize {
+        self.interned_symbols.len()
+    }
+
+    /// Get the number of objects and approximate number of bytes used by each object store.
+    pub fn store_stats(&self) -> Vec<StoreStats> {
+        use std::mem::size_of;
+        vec![
+            StoreStats {
+                name: "strings",
+                count: self.strings.len(),
+                bytes: self.strings.approximate_bytes(|s| {
+                    if s.is_heap_allocated() {
+                        s.capacity()
+                    } else {
+                        0
+                    }
+                }),
+            },
+            StoreStats {
+                name: "boxes",
+                count: self.mutable_boxes.len(),
+                bytes: self.mutable_boxes.approximate_bytes(|_| 0),
+            },
+            StoreStats {
+                name: "lists",
+                count: self.lists.len(),
+                bytes: self
+                    .lists
+                    .approximate_bytes(|l| l.capacity() * size_of::<UnsafeVal>()),
+            },
+            StoreStats {
+                name: "vectors",
+                count: self.vectors.len(),
+                bytes: self
+                    .vectors
+                    .approximate_bytes(|v| v.len() * size_of::<UnsafeVal>()),
+            },
+            StoreStats {
+                name: "arrays",
+                count: self.arrays.len(),
+                bytes: self
+                    .arrays
+                    .approximate_bytes(|a| a.len() * size_of::<UnsafeVal>()),
+            },
+            StoreStats {
+                name: "bytes",
+                count: self.bytes.len(),
+                bytes: self.bytes.approximate_bytes(|b| b.len()),
+            },
+            StoreStats {
+                name: "structs",
+                count: self.structs.len(),
+                bytes: self.structs.approximate_bytes(|s| {
+                    s.len() * (size_of::<Symbol>() + size_of::<UnsafeVal>())
+                }),
+            },
+            StoreStats {
+                name: "bytecodes",
+                count: self.bytecodes.len(),
+                bytes: self.bytecodes.approximate_bytes(|bc| {
+                    bc.instructions.len() * size_of::<Instruction>()
+                        + bc.instruction_source.len() * size_of::<Span>()
+                }),
+            },
+            StoreStats {
+                name: "customs",
+                count: self.customs.len(),
+                bytes: self.customs.approximate_bytes(|_| 0),
+            },
+            StoreStats {
+                name: "symbols",
+                count: self.interned_symbols.len(),
+                bytes: self
+                    .interned_symbols
+                    .iter()
+                    .map(|(_, s)| 2 * (size_of::<CompactString>() + s.len()))
+                    .sum(),
+            },
+        ]
+    }
+
+    /// Iterate over all interned strings.
+    pub fn symbols(&self) -> impl '_ + Iterator<Item = (Symbol, &str)> {
+        self.interned_symbols.iter()
+    }
+
     /// Run the garbage collector. All known values must be in `values`.
-    pub fn run_gc(&mut self, arena: &Bump, populate_vals: impl Iterator<Item = UnsafeVal>) {
-        self.run_gc_mark(arena, populate_vals);
+    ///
+    /// If `collect_symbols` is `true`, then symbols that are not reachable from `values` are also
+    /// removed. In this case, `values` must also contain all symbols that are in use.
+    pub fn run_gc(
+        &mut self,
+        arena: &Bump,
+        populate_vals: impl Iterator<Item = UnsafeVal>,
+        collect_symbols: bool,
+    ) {
+        let pending_unreachable = std::mem::take(
+            &mut *self
+                .pending_unreachable
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()),
+        );
+        for v in pending_unreachable {
+            self.keep_reachable.remove(v);
+        }
+        self.run_gc_mark(arena, populate_vals, collect_symbols);
         self.run_gc_sweep();
+        if collect_symbols {
+            self.interned_symbols.remove_unreachable();
+        }
         self.reachable_color = self.reachable_color.other();
     }
 
     /// Run the GC mark phase.
-    fn run_gc_mark(&mut self, arena: &Bump, values: impl Iterator<Item = UnsafeVal>) {
+    fn run_gc_mark(
+        &mut self,
+        arena: &Bump,
+        values: impl Iterator<Item = UnsafeVal>,
+        collect_symbols: bool,
+    ) {
         let mut root_set = self.init_root_set(arena, values);
         let mut child_set = BumpVec::new_in(arena);
         while !root_set.is_empty() {
             for val in root_set.drain(..) {
-                self.gc_mark_one(val, &mut child_set);
+                self.gc_mark_one(val, &mut child_set, collect_symbols);
             }
             std::mem::swap(&mut root_set, &mut child_set);
         }
@@ -94,9 +263,14 @@ impl MemoryManager {
         root_set
     }
 
-    fn gc_mark_one(&mut self, val: UnsafeVal, child_queue: &mut BumpVec<UnsafeVal>) {
+    fn gc_mark_one(
+        &mut self,
+        val: UnsafeVal,
+        child_queue: &mut BumpVec<UnsafeVal>,
+        collect_symbols: bool,
+    ) {
         let mut add_child = |v| {
-            if is_garbage_collected(v) {
+            if is_garbage_collected(v) || (collect_symbols && matches!(v, UnsafeVal::Symbol(_))) {
                 child_queue.push(v);
             }
         };
@@ -116,9 +290,27 @@ impl MemoryManager {
                     }
                 }
             }
+            UnsafeVal::Vector(id) => {
+                if let Some(vector) = self.vectors.set_color(id, self.reachable_color) {
+                    for child_val in vector.iter() {
+                        add_child(*child_val);
+                    }
+                }
+            }
+            UnsafeVal::Array(id) => {
+                if let Some(array) = self.arrays.set_color(id, self.reachable_color) {
+                    for child_val in array.iter() {
+                        add_child(*child_val);
+                    }
+                }
+            }
+            UnsafeVal::Bytes(id) => {
+                self.bytes.set_color(id, self.reachable_color);
+            }
             UnsafeVal::Struct(id) => {
                 if let Some(strct) = self.structs.set_color(id, self.reachable_color) {
-                    for child_val in strct.values() {
+                    for (name, child_val) in strct.iter() {
+                        add_child(UnsafeVal::Symbol(name));
                         add_child(child_val);
                     }
                 }
@@ -128,11 +320,15 @@ impl MemoryManager {
                     for child_val in bc.values() {
                         add_child(child_val);
                     }
+                    for symbol in bc.symbols() {
+                        add_child(UnsafeVal::Symbol(symbol));
+                    }
                 }
             }
             UnsafeVal::Custom(id) => {
                 self.customs.set_color(id, self.reachable_color);
             }
+            UnsafeVal::Symbol(symbol) => self.interned_symbols.mark_reachable(symbol),
 
             v => debug_assert!(!is_garbage_collected(v)),
         }
@@ -142,9 +338,41 @@ impl MemoryManager {
         let unreachable_color = self.reachable_color.other();
         self.mutable_boxes.remove_all_with_color(unreachable_color);
         self.lists.remove_all_with_color(unreachable_color);
+        self.vectors.remove_all_with_color(unreachable_color);
+        self.arrays.remove_all_with_color(unreachable_color);
+        self.bytes.remove_all_with_color(unreachable_color);
         self.structs.remove_all_with_color(unreachable_color);
         self.bytecodes.remove_all_with_color(unreachable_color);
-        self.customs.remove_all_with_color(unreachable_color);
+        let (vm_id, bytecodes) = (self.vm_id, &self.bytecodes);
+        self.bytecode_ids_by_hash.retain(|_, ids| {
+            ids.retain(|id| bytecodes.get(vm_id, *id).is_some());
+            !ids.is_empty()
+        });
+        self.customs
+            .remove_all_with_color_and(unreachable_color, |mut v| v.finalize());
+    }
+
+    /// Get the queue of values to remove from the keep reachable set on the next garbage
+    /// collection.
+    pub fn pending_unreachable(&self) -> Arc<Mutex<Vec<UnsafeVal>>> {
+        self.pending_unreachable.clone()
+    }
+
+    /// Returns `true` if `v` is not an object or is an object that has not been garbage collected.
+    #[cfg(test)]
+    pub fn is_alive(&self, v: UnsafeVal) -> bool {
+        match v {
+            UnsafeVal::String(id) => self.strings.get(self.vm_id, id).is_some(),
+            UnsafeVal::MutableBox(id) => self.mutable_boxes.get(self.vm_id, id).is_some(),
+            UnsafeVal::List(id) => self.lists.get(self.vm_id, id).is_some(),
+            UnsafeVal::Vector(id) => self.vectors.get(self.vm_id, id).is_some(),
+            UnsafeVal::Array(id) => self.arrays.get(self.vm_id, id).is_some(),
+            UnsafeVal::Bytes(id) => self.bytes.get(self.vm_id, id).is_some(),
+            UnsafeVal::Struct(id) => self.structs.get(self.vm_id, id).is_some(),
+            UnsafeVal::ByteCodeFunction(id) => self.bytecodes.get(self.vm_id, id).is_some(),
+            UnsafeVal::Custom(id) => self.customs.get(self.vm_id, id).is_some(),
+            _ => true,
+        }
     }
 
     /// Marks `value` as reachable so that it doesn't get garbage collected.
@@ -201,6 +429,13 @@ impl MemoryManager {
         res.unwrap_or(Self::EMPTY_LIST)
     }
 
+    /// Get a mutable reference to a list by its id.
+    pub fn get_list_mut(&mut self, id: ValId<ListVal>) -> &mut ListVal {
+        let res = self.lists.get_mut(self.vm_id, id);
+        assert!(res.is_some(), "{id:?} not found.");
+        res.unwrap()
+    }
+
     /// Insert a list and get its id.
     pub fn insert_list(&mut self, list: ListVal) -> ValId<ListVal> {
         // We mark as unreachable to recurse through `list`'s elements during the next GC mark
@@ -209,6 +444,63 @@ impl MemoryManager {
             .insert(self.vm_id, list, self.reachable_color.other())
     }
 
+    /// Get a vector by its id.
+    pub fn get_vector(&self, id: ValId<VectorVal>) -> &[UnsafeVal] {
+        let res = self.vectors.get(self.vm_id, id);
+        debug_assert!(res.is_some(), "{id:?} not found.");
+        res.map(|v| &**v).unwrap_or(&[])
+    }
+
+    /// Insert a vector and get its id.
+    pub fn insert_vector(&mut self, vector: VectorVal) -> ValId<VectorVal> {
+        // We mark as unreachable to recurse through `vector`'s elements during the next GC mark
+        // phase.
+        self.vectors
+            .insert(self.vm_id, vector, self.reachable_color.other())
+    }
+
+    /// Get an array by its id.
+    pub fn get_array(&self, id: ValId<ArrayVal>) -> &[UnsafeVal] {
+        let res = self.arrays.get(self.vm_id, id);
+        debug_assert!(res.is_some(), "{id:?} not found.");
+        res.map(|a| &**a).unwrap_or(&[])
+    }
+
+    /// Get a mutable reference to an array by its id.
+    pub fn get_array_mut(&mut self, id: ValId<ArrayVal>) -> &mut [UnsafeVal] {
+        let res = self.arrays.get_mut(self.vm_id, id);
+        assert!(res.is_some(), "{id:?} not found.");
+        res.unwrap()
+    }
+
+    /// Insert an array and get its id.
+    pub fn insert_array(&mut self, array: ArrayVal) -> ValId<ArrayVal> {
+        // We mark as unreachable to recurse through `array`'s elements during the next GC mark
+        // phase.
+        self.arrays
+            .insert(self.vm_id, array, self.reachable_color.other())
+    }
+
+    /// Get bytes by their id.
+    pub fn get_bytes(&self, id: ValId<BytesVal>) -> &[u8] {
+        let res = self.bytes.get(self.vm_id, id);
+        debug_assert!(res.is_some(), "{id:?} not found.");
+        res.map(|b| &**b).unwrap_or(&[])
+    }
+
+    /// Get a mutable reference to bytes by their id.
+    pub fn get_bytes_mut(&mut self, id: ValId<BytesVal>) -> &mut [u8] {
+        let res = self.bytes.get_mut(self.vm_id, id);
+        assert!(res.is_some(), "{id:?} not found.");
+        res.unwrap()
+    }
+
+    /// Insert bytes and get their id.
+    pub fn insert_bytes(&mut self, bytes: BytesVal) -> ValId<BytesVal> {
+        self.bytes
+            .insert(self.vm_id, bytes, self.reachable_color.other())
+    }
+
     /// Get a struct by its id.
     pub fn get_struct(&self, id: ValId<StructVal>) -> &StructVal {
         let res = self.structs.get(self.vm_id, id);
@@ -233,8 +525,9 @@ impl MemoryManager {
 
     /// Get a bytecode by its id.
     pub fn get_bytecode(&self, id: ValId<ByteCode>) -> Option<&ByteCode> {
+        static NOT_FOUND_LOG: LogRateLimiter = LogRateLimiter::new(1000);
         let res = self.bytecodes.get(self.vm_id, id);
-        if res.is_none() {
+        if res.is_none() && NOT_FOUND_LOG.should_log() {
             error!("{id:?} not found");
         }
         res
@@ -263,6 +556,27 @@ impl MemoryManager {
             .insert(self.vm_id, bytecode, self.reachable_color.other())
     }
 
+    /// Get the id of bytecode that is equal to `bytecode` or insert it if no such bytecode exists.
+    /// Only bytecode inserted with this function is considered for reuse.
+    pub fn get_or_insert_bytecode(&mut self, bytecode: ByteCode) -> ValId<ByteCode> {
+        let ids = self
+            .bytecode_ids_by_hash
+            .entry(bytecode.content_hash())
+            .or_default();
+        for id in ids.iter() {
+            if self.bytecodes.get(self.vm_id, *id) == Some(&bytecode) {
+                return *id;
+            }
+        }
+        // We mark as unreachable to recurse through the bytecode's values during the next GC mark
+        // phase.
+        let id = self
+            .bytecodes
+            .insert(self.vm_id, bytecode, self.reachable_color.other());
+        ids.push(id);
+        id
+    }
+
     /// Get a custom value by its id.
     pub fn get_custom(&self, id: ValId<CustomVal>) -> &CustomVal {
         let c = self.customs.get(self.vm_id, id);
@@ -290,6 +604,9 @@ pub fn is_garbage_collected(v: UnsafeVal) -> bool {
         UnsafeVal::String(_) => true,
         UnsafeVal::MutableBox(_) => true,
         UnsafeVal::List(_) => true,
+        UnsafeVal::Vector(_) => true,
+        UnsafeVal::Array(_) => true,
+        UnsafeVal::Bytes(_) => true,
         UnsafeVal::Struct(_) => true,
         UnsafeVal::ByteCodeFunction(_) => true,
         UnsafeVal::NativeFunction(_) => false,
@@ -297,6 +614,27 @@ pub fn is_garbage_collected(v: UnsafeVal) -> bool {
     }
 }
 
+/// Returns the id of the VM that created `v` or `None` if `v` is not tied to a VM.
+pub fn owning_vm_id(v: UnsafeVal) -> Option<u16> {
+    match v {
+        UnsafeVal::Void
+        | UnsafeVal::Bool(_)
+        | UnsafeVal::Int(_)
+        | UnsafeVal::Float(_)
+        | UnsafeVal::NativeFunction(_) => None,
+        UnsafeVal::Symbol(s) => Some(s.vm_id),
+        UnsafeVal::String(id) => Some(id.vm_id),
+        UnsafeVal::MutableBox(id) => Some(id.vm_id),
+        UnsafeVal::List(id) => Some(id.vm_id),
+        UnsafeVal::Vector(id) => Some(id.vm_id),
+        UnsafeVal::Array(id) => Some(id.vm_id),
+        UnsafeVal::Bytes(id) => Some(id.vm_id),
+        UnsafeVal::Struct(id) => Some(id.vm_id),
+        UnsafeVal::ByteCodeFunction(id) => Some(id.vm_id),
+        UnsafeVal::Custom(id) => Some(id.vm_id),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,7 +2,7 @@ use std::marker::PhantomData;
 
 use log::*;
 
-use crate::{val::ValId, ISSUE_LINK};
+use crate::{log_limiter::LogRateLimiter, val::ValId, ISSUE_LINK};
 
 #[derive(Clone, Debug)]
 pub struct ValWithColor<T> {
@@ -53,14 +53,38 @@ impl<T: std::fmt::Debug> ObjectStore<T> {
     /// [Self::mark_always_reachable] will not be cleaned up unless undone with
     /// [Self::unmark_always_reachable].
     pub fn remove_all_with_color(&mut self, color: Color) {
+        self.remove_all_with_color_and(color, drop);
+    }
+
+    /// Remove all objects with the given `color` and call `on_remove` on each removed object.
+    pub fn remove_all_with_color_and(&mut self, color: Color, mut on_remove: impl FnMut(T)) {
         for (idx, obj) in self.objects.iter_mut().enumerate() {
             if obj.inner.is_some() && obj.color == color {
-                obj.inner.take();
+                // Unwrap OK: `inner` was checked to be `Some` above.
+                on_remove(obj.inner.take().unwrap());
                 self.free_object_idx.push(idx as _);
             }
         }
     }
 
+    /// Get the number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free_object_idx.len()
+    }
+
+    /// Iterate over all objects in the store.
+    pub fn values(&self) -> impl '_ + Iterator<Item = &T> {
+        self.objects.iter().flat_map(|obj| obj.inner.as_ref())
+    }
+
+    /// Get the approximate number of bytes used by the store. `heap_bytes` should return the
+    /// number of bytes an object uses outside of its own size.
+    pub fn approximate_bytes(&self, heap_bytes: impl Fn(&T) -> usize) -> usize {
+        let slot_bytes = self.objects.capacity() * std::mem::size_of::<ValWithColor<T>>()
+            + self.free_object_idx.capacity() * std::mem::size_of::<u32>();
+        slot_bytes + self.values().map(heap_bytes).sum::<usize>()
+    }
+
     #[cfg(test)]
     pub fn iter(&self, vm_id: u16) -> impl '_ + Iterator<Item = (ValId<T>, &T)> {
         self.objects
@@ -83,11 +107,14 @@ impl<T: std::fmt::Debug> ObjectStore<T> {
     /// Get a reference to the underlying type or `None` if it does not exist.
     pub fn get(&self, vm_id: u16, id: ValId<T>) -> Option<&T> {
         if vm_id != id.vm_id {
-            error!(
-                "Attempted to get value for VM ID {id_vm_id} but store is for VM ID {this_vm_id}",
-                id_vm_id = id.vm_id,
-                this_vm_id = vm_id,
-            );
+            static WRONG_VM_LOG: LogRateLimiter = LogRateLimiter::new(1000);
+            if WRONG_VM_LOG.should_log() {
+                error!(
+                    "Attempted to get value for VM ID {id_vm_id} but store is for VM ID {this_vm_id}",
+                    id_vm_id = id.vm_id,
+                    this_vm_id = vm_id,
+                );
+            }
             return None;
         }
         self.objects
@@ -7,8 +7,12 @@ use crate::val::Symbol;
 #[derive(Clone, Debug, Default)]
 pub struct SymbolInterner {
     vm_id: u16,
-    strings: Vec<CompactString>,
+    /// The string for each symbol. Symbols that have been removed are `None`. Their index is never
+    /// reused so that a stale symbol can not refer to a different string.
+    strings: Vec<Option<CompactString>>,
     string_to_index: HashMap<CompactString, u32>,
+    /// Symbols that have been marked as reachable by the garbage collector.
+    reachable: Vec<bool>,
 }
 
 impl SymbolInterner {
@@ -17,6 +21,7 @@ impl SymbolInterner {
             vm_id,
             strings: Vec::new(),
             string_to_index: HashMap::new(),
+            reachable: Vec::new(),
         }
     }
 
@@ -24,7 +29,7 @@ impl SymbolInterner {
         if id.vm_id != self.vm_id {
             return None;
         }
-        self.strings.get(id.idx as usize).map(CompactString::as_str)
+        self.strings.get(id.idx as usize)?.as_deref()
     }
 
     pub fn get_symbol(&self, s: &str) -> Option<Symbol> {
@@ -34,13 +39,61 @@ impl SymbolInterner {
         })
     }
 
+    /// Returns the number of interned symbols.
+    pub fn len(&self) -> usize {
+        self.string_to_index.len()
+    }
+
+    /// Iterate over all interned symbols and their string representation.
+    pub fn iter(&self) -> impl '_ + Iterator<Item = (Symbol, &str)> {
+        self.strings.iter().enumerate().flat_map(|(idx, s)| {
+            let symbol = Symbol {
+                vm_id: self.vm_id,
+                idx: idx as u32,
+            };
+            Some((symbol, s.as_deref()?))
+        })
+    }
+
+    /// Mark `symbol` as reachable so that it is not removed by [Self::remove_unreachable].
+    pub fn mark_reachable(&mut self, symbol: Symbol) {
+        if symbol.vm_id != self.vm_id {
+            return;
+        }
+        if self.reachable.len() < self.strings.len() {
+            self.reachable.resize(self.strings.len(), false);
+        }
+        if let Some(reachable) = self.reachable.get_mut(symbol.idx as usize) {
+            *reachable = true;
+        }
+    }
+
+    /// Remove all symbols that were not marked as reachable since the last call and reset the
+    /// marks.
+    pub fn remove_unreachable(&mut self) {
+        self.reachable.resize(self.strings.len(), false);
+        for (s, reachable) in self.strings.iter_mut().zip(self.reachable.iter_mut()) {
+            if !std::mem::take(reachable) {
+                if let Some(s) = s.take() {
+                    self.string_to_index.remove(&s);
+                }
+            }
+        }
+    }
+
+    /// Reserve capacity for at least `additional` more symbols.
+    pub fn reserve(&mut self, additional: usize) {
+        self.strings.reserve(additional);
+        self.string_to_index.reserve(additional);
+    }
+
     pub fn get_or_create_symbol(&mut self, vm_id: u16, s: &str) -> Symbol {
         assert_eq!(vm_id, self.vm_id);
         let idx = match self.string_to_index.get(s) {
             Some(idx) => *idx,
             None => {
                 let idx = self.strings.len() as u32;
-                self.strings.push(s.to_compact_string());
+                self.strings.push(Some(s.to_compact_string()));
                 self.string_to_index.insert(s.to_compact_string(), idx);
                 idx
             }
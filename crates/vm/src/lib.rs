@@ -1,26 +1,32 @@
-use std::{collections::HashMap, sync::atomic::AtomicU16};
+use std::{collections::HashMap, fmt::Write, sync::atomic::AtomicU16};
 
 use bumpalo::Bump;
 use compact_str::CompactString;
 use gc::{is_garbage_collected, MemoryManager};
 use log::*;
+use log_limiter::LogRateLimiter;
 
 use compiler::Compiler;
 use error::{BacktraceError, VmError, VmResult};
-pub use settings::Settings;
+pub use gc::StoreStats;
+use parser::span::Span;
+pub use settings::{Settings, SettingsBuilder};
+pub use snapshot::Snapshot;
 use stack_frame::{StackFrame, StackFrameManager};
 use val::{
-    custom::CustomVal, ByteCode, CustomType, Instruction, NativeFunction, NativeFunctionContext,
-    ProtectedVal, Symbol, UnsafeVal, Val, ValId,
+    custom::CustomVal, ByteCode, CustomType, Instruction, ListVal, NativeFunction,
+    NativeFunctionContext, ProtectedVal, RootGuard, StructVal, Symbol, UnsafeVal, Val, ValId,
 };
 
 mod builtins;
 mod compiler;
 pub mod error;
 mod gc;
+mod log_limiter;
 pub mod parser;
 pub mod repl;
 mod settings;
+mod snapshot;
 mod stack_frame;
 pub mod val;
 
@@ -29,6 +35,12 @@ type BumpVec<'a, T> = bumpalo::collections::Vec<'a, T>;
 /// The GitHub issues page to file issues to.
 pub const ISSUE_LINK: &str = "https://github.com/wmedrano/spore/issues";
 
+/// Limits logging when the temporary arena is unexpectedly unavailable.
+static ARENA_UNAVAILABLE_LOG: LogRateLimiter = LogRateLimiter::new(1000);
+
+/// The maximum number of nested calls to `eval`.
+const MAX_EVAL_DEPTH: usize = 16;
+
 /// The Spore virtual machine.
 ///
 /// # Example
@@ -49,6 +61,8 @@ pub struct Vm {
     stack: Vec<UnsafeVal>,
     /// Map from binding name to value. This is used to store global values.
     values: HashMap<Symbol, UnsafeVal>,
+    /// Incremented whenever a global value is set. Used to invalidate [val::InlineCache] entries.
+    globals_version: u64,
     /// The current stack frame. This contains what should be evaluated next and some extra context.
     stack_frames: StackFrameManager,
     /// Manages lifetime of all values, aside from simple atoms like bool/int/float.
@@ -57,6 +71,13 @@ pub struct Vm {
     settings: Settings,
     /// An arena for temporary computations for things like compilation and garbage collection.
     tmp_arena: Option<Bump>,
+    /// Buffers that capture output from functions like `print`. If empty, output is written to
+    /// stdout.
+    pub(crate) output_captures: Vec<String>,
+    /// Map from macro name to the function that transforms the macro call.
+    pub(crate) macros: HashMap<Symbol, UnsafeVal>,
+    /// The number of nested calls to `eval` that are currently running.
+    eval_depth: usize,
 }
 
 impl Default for Vm {
@@ -74,28 +95,37 @@ impl Vm {
     /// Create a new virtual machine.
     pub fn new(settings: Settings) -> Vm {
         let start_t = std::time::Instant::now();
+        let mut vm = Vm::new_without_builtins(settings);
+        for (name, func, _) in builtins::BUILTINS {
+            vm = vm.with_native_function(name, *func);
+        }
+        info!(
+            "Initialized Spore VM in {elapsed:?} with {settings:?}",
+            elapsed = start_t.elapsed()
+        );
+        vm
+    }
+
+    /// Create a new virtual machine with no globals defined.
+    fn new_without_builtins(settings: Settings) -> Vm {
         let mut vm_id = 0;
         while vm_id == 0 {
             vm_id = VM_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         }
-        let mut vm = Vm {
+        Vm {
             // TODO: Determine optimal size for stack. Small values may perform, better, but
             // exceeding the capacity may cause performance degregations.
             stack: Vec::with_capacity(4096),
             values: HashMap::new(),
+            globals_version: 1,
             stack_frames: StackFrameManager::default(),
             objects: MemoryManager::new(vm_id),
             settings,
             tmp_arena: Some(Bump::new()),
-        };
-        for (name, func) in builtins::BUILTINS {
-            vm = vm.with_native_function(name, *func);
+            output_captures: Vec::new(),
+            macros: HashMap::new(),
+            eval_depth: 0,
         }
-        info!(
-            "Initialized Spore VM in {elapsed:?} with {settings:?}",
-            elapsed = start_t.elapsed()
-        );
-        vm
     }
 
     /// Return the VM with the native function registered.
@@ -139,6 +169,37 @@ impl Vm {
         f(self)
     }
 
+    /// Return the VM returned by calling `fn` or the error if `fn` fails. Similar to [Self::with]
+    /// but for setup that may fail, like evaluating code.
+    ///
+    /// ```rust
+    /// let vm = spore_vm::Vm::default()
+    ///     .try_with(|mut vm| {
+    ///         vm.eval_str("(define x 42)")?;
+    ///         Ok(vm)
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn try_with(self, f: impl FnOnce(Vm) -> VmResult<Vm>) -> VmResult<Self> {
+        f(self)
+    }
+
+    /// Evaluate `src` and return the VM. Useful for loading a library of definitions when
+    /// constructing the VM. An error is returned if `src` fails to compile or run.
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default()
+    ///     .with_prelude("(define (double x) (+ x x))")
+    ///     .unwrap();
+    /// assert_eq!(vm.eval_str("(double 21)").unwrap().try_int().ok(), Some(42));
+    /// ```
+    pub fn with_prelude(self, src: &str) -> VmResult<Self> {
+        self.try_with(|mut vm| {
+            vm.eval_str(src)?;
+            Ok(vm)
+        })
+    }
+
     /// Register a value to the VM.
     ///
     /// # Safety
@@ -151,12 +212,92 @@ impl Vm {
         );
         let interned_sym = self.get_or_create_symbol(name);
         self.values.insert(interned_sym, val);
+        self.globals_version += 1;
     }
 }
 
 impl Vm {
+    /// Get the settings for the VM.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Set if source maps should be generated. This only affects subsequent compilations; bytecode
+    /// that has already been compiled keeps its source maps (or lack thereof).
+    ///
+    /// Source maps allow errors to point to the source code that caused them at the cost of higher
+    /// RAM usage and slower compilation.
+    pub fn set_source_maps(&mut self, enable: bool) {
+        self.settings.enable_source_maps = enable;
+    }
+
+    /// Set the global value `name` to `val`, replacing any existing value.
+    ///
+    /// Returns an error if `val` was created by a different [Vm].
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// vm.set_global("answer", spore_vm::val::Val::new_int(42)).unwrap();
+    /// assert_eq!(vm.eval_str("(+ answer 1)").unwrap().try_int().unwrap(), 43);
+    /// ```
+    pub fn set_global(&mut self, name: &str, val: Val) -> VmResult<()> {
+        let val = val.as_unsafe_val();
+        self.objects.check_owned(val)?;
+        // Unsafe OK: `val` is a valid value that belongs to this VM.
+        unsafe { self.register_value(name, val) };
+        Ok(())
+    }
+
+    /// Set each global value `name` to `val`, replacing any existing values.
+    ///
+    /// Returns an error if any `val` was created by a different [Vm]. All values are checked before
+    /// any are set so no globals are changed on error.
+    ///
+    /// # Example
+    /// ```rust
+    /// use spore_vm::val::Val;
+    ///
+    /// let mut vm = spore_vm::Vm::default();
+    /// vm.define_all([("width", Val::new_int(80)), ("height", Val::new_int(24))])
+    ///     .unwrap();
+    /// assert_eq!(vm.eval_str("(+ width height)").unwrap().try_int().unwrap(), 104);
+    /// ```
+    pub fn define_all<'a>(
+        &mut self,
+        items: impl IntoIterator<Item = (&'a str, Val<'a>)>,
+    ) -> VmResult<()> {
+        let items: Vec<(&str, UnsafeVal)> = items
+            .into_iter()
+            .map(|(name, val)| (name, val.as_unsafe_val()))
+            .collect();
+        for (_, val) in items.iter() {
+            self.objects.check_owned(*val)?;
+        }
+        for (name, val) in items {
+            // Unsafe OK: `val` is a valid value that belongs to this VM.
+            unsafe { self.register_value(name, val) };
+        }
+        Ok(())
+    }
+
+    /// Iterate over all global values and their names. The iteration order is unspecified.
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// vm.eval_str("(define x 42)").unwrap();
+    /// assert!(vm.globals().any(|(name, val)| name == "x" && val.try_int().ok() == Some(42)));
+    /// ```
+    pub fn globals(&self) -> impl '_ + Iterator<Item = (&str, Val<'_>)> {
+        self.values.iter().filter_map(|(name, val)| {
+            let name = self.symbol_to_str(*name)?;
+            // Unsafe OK: The value has not been garbage collected as its part of the values map.
+            Some((name, unsafe { Val::from_unsafe_val(*val) }))
+        })
+    }
+
     /// Get the value with the given name or [None] if it does not exist.
-    pub fn val_by_name(&self, name: &str) -> Option<Val> {
+    pub fn val_by_name(&self, name: &str) -> Option<Val<'_>> {
         let interned_name = self.get_symbol(name)?;
         self.values
             .get(&interned_name)
@@ -165,36 +306,425 @@ impl Vm {
             .map(|v| unsafe { Val::from_unsafe_val(v) })
     }
 
+    /// Get the `int` value with the given name or [None] if it does not exist or is not an `int`.
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        self.val_by_name(name)?.try_int().ok()
+    }
+
+    /// Get the `bool` value with the given name or [None] if it does not exist or is not a `bool`.
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.val_by_name(name)?.try_bool().ok()
+    }
+
+    /// Get the `string` value with the given name or [None] if it does not exist or is not a
+    /// `string`.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.val_by_name(name)?.try_str(self).ok()
+    }
+
+    /// Import `val` from `src_vm` into `self` by deep copying it.
+    ///
+    /// Values that hold a [ValId] are only valid within the VM that created them. Strings, symbols,
+    /// boxes, lists, and structs are rebuilt within `self`. Functions and custom values can not be
+    /// imported and return an error.
+    ///
+    /// ```rust
+    /// let mut src_vm = spore_vm::Vm::default();
+    /// let mut dst_vm = spore_vm::Vm::default();
+    /// let src_val = src_vm.eval_str("(list 1 \"two\")").unwrap();
+    /// let dst_val = dst_vm.import_value(src_val.vm(), *src_val).unwrap();
+    /// assert_eq!(dst_val.to_string(), "(1 \"two\")");
+    /// ```
+    pub fn import_value(&mut self, src_vm: &Vm, val: Val) -> VmResult<ProtectedVal<'_>> {
+        src_vm.objects.check_owned(val.as_unsafe_val())?;
+        let mut imported_structs = HashMap::new();
+        let mut imported_boxes = HashMap::new();
+        let v = self.import_unsafe_val(
+            src_vm,
+            val.as_unsafe_val(),
+            &mut imported_structs,
+            &mut imported_boxes,
+        )?;
+        // Unsafe OK: The value was just created and the garbage collector has not run.
+        Ok(ProtectedVal::new(self, unsafe { Val::from_unsafe_val(v) }))
+    }
+
+    /// Capture a deep copy of the global values of `self`. The globals can be set back to the
+    /// captured values with [Self::restore].
+    ///
+    /// Only data is captured. Builtins are not captured. Globals that are functions or custom
+    /// values, or that contain functions or custom values, are not captured and their names are
+    /// listed in [Snapshot::skipped].
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// vm.eval_str("(define score 10) (define (f) score)").unwrap();
+    /// let snapshot = vm.snapshot();
+    /// assert_eq!(snapshot.skipped(), ["f"]);
+    /// vm.eval_str("(define score 0)").unwrap();
+    /// vm.restore(&snapshot).unwrap();
+    /// assert_eq!(vm.get_int("score"), Some(10));
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot {
+            vm: Vm::new_without_builtins(self.settings),
+            names: Vec::new(),
+            skipped: Vec::new(),
+        };
+        // Shared between all globals to preserve sharing across globals.
+        let mut imported_structs = HashMap::new();
+        let mut imported_boxes = HashMap::new();
+        for (name, val) in self.values.iter() {
+            let name = match self.symbol_to_str(*name) {
+                Some(name) => name,
+                None => continue,
+            };
+            if builtins::is_builtin(name, *val) {
+                continue;
+            }
+            let imported = snapshot.vm.import_unsafe_val(
+                self,
+                *val,
+                &mut imported_structs,
+                &mut imported_boxes,
+            );
+            match imported {
+                Ok(v) => {
+                    // Unsafe OK: `v` was just imported into the snapshot's VM.
+                    unsafe { snapshot.vm.register_value(name, v) };
+                    snapshot.names.push(name.into());
+                }
+                Err(_) => snapshot.skipped.push(name.into()),
+            }
+        }
+        snapshot.skipped.sort();
+        snapshot
+    }
+
+    /// Set all globals captured by `snapshot` back to their captured values. Globals that were not
+    /// captured, like functions or globals defined after the snapshot, are left unchanged.
+    ///
+    /// If an error is returned, no globals are changed.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> VmResult<()> {
+        let mut imported_structs = HashMap::new();
+        let mut imported_boxes = HashMap::new();
+        let mut imported = Vec::with_capacity(snapshot.names.len());
+        for name in snapshot.names.iter() {
+            let val = snapshot
+                .vm
+                .val_by_name(name)
+                .ok_or_else(|| VmError::SymbolNotDefined {
+                    src: None,
+                    symbol: name.to_string(),
+                })?
+                .as_unsafe_val();
+            let v = self.import_unsafe_val(
+                &snapshot.vm,
+                val,
+                &mut imported_structs,
+                &mut imported_boxes,
+            )?;
+            imported.push((name, v));
+        }
+        // The garbage collector does not run until all values are registered.
+        for (name, v) in imported {
+            // Unsafe OK: `v` was just imported into `self`.
+            unsafe { self.register_value(name, v) };
+        }
+        Ok(())
+    }
+
+    /// Deep copy `val` from `src_vm`. Structs and boxes that have already been imported are
+    /// reused to preserve sharing and to terminate on cycles.
+    fn import_unsafe_val(
+        &mut self,
+        src_vm: &Vm,
+        val: UnsafeVal,
+        imported_structs: &mut HashMap<ValId<StructVal>, ValId<StructVal>>,
+        imported_boxes: &mut HashMap<ValId<UnsafeVal>, ValId<UnsafeVal>>,
+    ) -> VmResult<UnsafeVal> {
+        let imported = match val {
+            UnsafeVal::Void | UnsafeVal::Bool(_) | UnsafeVal::Int(_) | UnsafeVal::Float(_) => val,
+            UnsafeVal::String(id) => {
+                let s = src_vm.objects.get_str(id);
+                self.objects.insert_string(s.into()).into()
+            }
+            UnsafeVal::Symbol(s) => {
+                let name = src_vm.symbol_to_str(s).unwrap_or_default();
+                UnsafeVal::Symbol(self.get_or_create_symbol(name))
+            }
+            UnsafeVal::MutableBox(id) => {
+                if let Some(imported_id) = imported_boxes.get(&id) {
+                    return Ok(UnsafeVal::MutableBox(*imported_id));
+                }
+                let imported_id = self.objects.insert_mutable_box(UnsafeVal::Void);
+                imported_boxes.insert(id, imported_id);
+                let inner = *src_vm.objects.get_mutable_box(id);
+                let inner =
+                    self.import_unsafe_val(src_vm, inner, imported_structs, imported_boxes)?;
+                self.objects.set_mutable_box(imported_id, inner);
+                imported_id.into()
+            }
+            UnsafeVal::List(id) => {
+                let list = src_vm.objects.get_list(id);
+                let mut imported_list = ListVal::with_capacity(list.len());
+                for v in list.iter() {
+                    imported_list.push(self.import_unsafe_val(
+                        src_vm,
+                        *v,
+                        imported_structs,
+                        imported_boxes,
+                    )?);
+                }
+                self.objects.insert_list(imported_list).into()
+            }
+            UnsafeVal::Vector(id) => {
+                let vector = src_vm.objects.get_vector(id);
+                let mut imported_vector = Vec::with_capacity(vector.len());
+                for v in vector.iter() {
+                    imported_vector.push(self.import_unsafe_val(
+                        src_vm,
+                        *v,
+                        imported_structs,
+                        imported_boxes,
+                    )?);
+                }
+                self.objects
+                    .insert_vector(imported_vector.into_boxed_slice())
+                    .into()
+            }
+            UnsafeVal::Array(id) => {
+                let array = src_vm.objects.get_array(id);
+                let mut imported_array = Vec::with_capacity(array.len());
+                for v in array.iter() {
+                    imported_array.push(self.import_unsafe_val(
+                        src_vm,
+                        *v,
+                        imported_structs,
+                        imported_boxes,
+                    )?);
+                }
+                self.objects.insert_array(imported_array.into()).into()
+            }
+            UnsafeVal::Bytes(id) => {
+                let bytes = src_vm.objects.get_bytes(id);
+                self.objects.insert_bytes(bytes.into()).into()
+            }
+            UnsafeVal::Struct(id) => {
+                if let Some(imported_id) = imported_structs.get(&id) {
+                    return Ok(UnsafeVal::Struct(*imported_id));
+                }
+                let imported_id = self.objects.insert_struct(StructVal::new());
+                imported_structs.insert(id, imported_id);
+                for (field, v) in src_vm.objects.get_struct(id).iter() {
+                    let field_name = src_vm.symbol_to_str(field).unwrap_or_default();
+                    let field = self.get_or_create_symbol(field_name);
+                    let v = self.import_unsafe_val(src_vm, v, imported_structs, imported_boxes)?;
+                    // Unsafe OK: `v` was just imported into `self`.
+                    self.objects
+                        .get_struct_mut(imported_id)
+                        .set(field, unsafe { Val::from_unsafe_val(v) });
+                }
+                imported_id.into()
+            }
+            UnsafeVal::ByteCodeFunction(_)
+            | UnsafeVal::NativeFunction(_)
+            | UnsafeVal::Custom(_) => {
+                return Err(VmError::TypeError {
+                    src: None,
+                    context: "import_value",
+                    expected: "importable value",
+                    actual: val.type_name(),
+                    value: val.format_quoted(src_vm).to_string(),
+                })
+            }
+        };
+        Ok(imported)
+    }
+
     /// Evaluate a string in the virtual machine.
     ///
+    /// Returns `void` if `source` has no expressions, like an empty string or a string containing
+    /// only whitespace and comments.
+    ///
     /// ```rust
     /// let mut vm = spore_vm::Vm::default();
     /// let x = vm.eval_str("(+ 20 22)").unwrap().try_int().unwrap();
     /// ```
-    pub fn eval_str(&mut self, source: &str) -> VmResult<ProtectedVal> {
-        let bytecode = {
-            let mut arena = self.tmp_arena.take().unwrap_or_else(|| {
-                warn!("Arena was unexpectedly unavailable. Please file an issue at {ISSUE_LINK} with proper context.");
-                Bump::new()
-            });
-            arena.reset();
-            let bytecode = Compiler::compile(self, source, &arena)?;
-            self.tmp_arena = Some(arena);
-            bytecode
-        };
+    pub fn eval_str(&mut self, source: &str) -> VmResult<ProtectedVal<'_>> {
+        let bytecode = self.compile_to_bytecode(source)?;
+        let bytecode_id = self.objects.insert_bytecode(bytecode);
+        self.run_bytecode(bytecode_id, true)
+    }
+
+    /// Evaluate the source read from `reader` in the virtual machine.
+    ///
+    /// Failures to read from `reader`, including invalid UTF-8, are returned as
+    /// [VmError::IoError].
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let src = std::io::Cursor::new("(define x 20) (+ x 22)");
+    /// assert_eq!(vm.eval_reader(src).unwrap().try_int().unwrap(), 42);
+    /// ```
+    pub fn eval_reader(&mut self, mut reader: impl std::io::Read) -> VmResult<ProtectedVal<'_>> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        self.eval_str(&source)
+    }
+
+    /// Evaluate each top-level expression in `source` separately and call `f` with the index of the
+    /// expression and its result.
+    ///
+    /// If `continue_on_error` is `true`, then evaluation continues with the next expression after
+    /// an error. Otherwise, evaluation stops after the first error. Parsing errors always stop
+    /// evaluation. Returns the number of expressions that failed.
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let mut results = Vec::new();
+    /// let failures = vm.eval_each("(+ 1 2) (+ 1 true) (+ 3 4)", true, |idx, res| {
+    ///     results.push((idx, res.ok().and_then(|v| v.try_int().ok())));
+    /// });
+    /// assert_eq!(failures, 1);
+    /// assert_eq!(results, [(0, Some(3)), (1, None), (2, Some(7))]);
+    /// ```
+    pub fn eval_each(
+        &mut self,
+        source: &str,
+        continue_on_error: bool,
+        mut f: impl FnMut(usize, VmResult<Val>),
+    ) -> usize {
+        let mut failures = 0;
+        for (idx, node_or_err) in parser::ast::Node::parse(source).enumerate() {
+            let node = match node_or_err {
+                Ok(node) => node,
+                Err(err) => {
+                    f(idx, Err(error::CompileError::AstError(err).into()));
+                    return failures + 1;
+                }
+            };
+            // Compile against all of `source` so that error spans are relative to `source`.
+            let res = self
+                .compile_span_to_bytecode(source, node.span())
+                .and_then(|bytecode| {
+                    let bytecode_id = self.objects.insert_bytecode(bytecode);
+                    self.run_bytecode(bytecode_id, true).map(|v| *v)
+                });
+            match res {
+                Ok(v) => f(idx, Ok(v)),
+                Err(err) => {
+                    failures += 1;
+                    f(idx, Err(err));
+                    if !continue_on_error {
+                        break;
+                    }
+                }
+            }
+        }
+        failures
+    }
 
+    /// Evaluate a string in the virtual machine without running the garbage collector.
+    ///
+    /// Unlike [Self::eval_str], garbage collection is skipped before evaluation. This is useful for
+    /// benchmarks where collection would skew measurements. Memory that is no longer in use is not
+    /// reclaimed until the next call that runs the garbage collector, like [Self::eval_str] or
+    /// [Self::run_gc].
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let x = vm.eval_str_no_gc("(+ 20 22)").unwrap().try_int().unwrap();
+    /// ```
+    pub fn eval_str_no_gc(&mut self, source: &str) -> VmResult<ProtectedVal<'_>> {
+        let bytecode = self.compile_to_bytecode(source)?;
         let bytecode_id = self.objects.insert_bytecode(bytecode);
-        let bytecode = self.objects.get_bytecode(bytecode_id).unwrap();
+        self.run_bytecode(bytecode_id, false)
+    }
+
+    /// Run bytecode that was previously compiled with [Self::compile].
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let id = vm.compile("(+ 20 22)").unwrap();
+    /// assert_eq!(vm.run_compiled(id).unwrap().try_int().unwrap(), 42);
+    /// assert_eq!(vm.run_compiled(id).unwrap().try_int().unwrap(), 42);
+    /// ```
+    pub fn run_compiled(&mut self, id: ValId<ByteCode>) -> VmResult<ProtectedVal<'_>> {
+        if self.objects.get_bytecode(id).is_none() {
+            return Err(VmError::CustomError {
+                src: None,
+                message: "compiled bytecode was not found, it may have been released".into(),
+            });
+        }
+        self.run_bytecode(id, true)
+    }
 
+    /// Run the bytecode with the given id from a fresh stack. The bytecode must exist.
+    fn run_bytecode(
+        &mut self,
+        bytecode_id: ValId<ByteCode>,
+        run_gc: bool,
+    ) -> VmResult<ProtectedVal<'_>> {
+        let bytecode = self.objects.get_bytecode(bytecode_id).unwrap();
         self.stack.clear();
-        self.stack
-            .extend(std::iter::repeat(UnsafeVal::Void).take(bytecode.local_bindings));
+        self.stack.extend(std::iter::repeat_n(
+            UnsafeVal::Void,
+            bytecode.local_bindings,
+        ));
         self.stack_frames
             .reset_with_stack_frame(StackFrame::new(bytecode_id, bytecode, 0));
-        unsafe { self.run_gc() };
+        if run_gc {
+            unsafe { self.run_gc() };
+        }
         self.run_all_protected()
     }
 
+    /// Compile `source` into bytecode without running it and return the id of the bytecode.
+    ///
+    /// The bytecode is kept safe from garbage collection until it is released with
+    /// [Self::release_compiled].
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let id = vm.compile("(+ 20 22)").unwrap();
+    /// let bytecode = vm.bytecode(id).unwrap();
+    /// vm.release_compiled(id);
+    /// ```
+    pub fn compile(&mut self, source: &str) -> VmResult<ValId<ByteCode>> {
+        let bytecode = self.compile_to_bytecode(source)?;
+        let bytecode_id = self.objects.insert_bytecode(bytecode);
+        self.objects.keep_reachable(bytecode_id.into());
+        Ok(bytecode_id)
+    }
+
+    /// Allow bytecode created by [Self::compile] to be garbage collected.
+    pub fn release_compiled(&mut self, id: ValId<ByteCode>) {
+        self.objects.allow_unreachable(id.into());
+    }
+
+    /// Get the bytecode with the given id or [None] if it does not exist.
+    pub fn bytecode(&self, id: ValId<ByteCode>) -> Option<&ByteCode> {
+        self.objects.get_bytecode(id)
+    }
+
+    fn compile_to_bytecode(&mut self, source: &str) -> VmResult<ByteCode> {
+        self.compile_span_to_bytecode(source, Span::new(0, source.len() as u32))
+    }
+
+    fn compile_span_to_bytecode(&mut self, source: &str, span: Span) -> VmResult<ByteCode> {
+        let mut arena = self.tmp_arena.take().unwrap_or_else(|| {
+            if ARENA_UNAVAILABLE_LOG.should_log() {
+                warn!("Arena was unexpectedly unavailable. Please file an issue at {ISSUE_LINK} with proper context.");
+            }
+            Bump::new()
+        });
+        arena.reset();
+        let bytecode = Compiler::compile_span(self, source, span, &arena);
+        self.tmp_arena = Some(arena);
+        Ok(bytecode?)
+    }
+
     /// Call a function with the given name.
     ///
     /// ```rust
@@ -211,7 +741,7 @@ impl Vm {
         &mut self,
         name: &str,
         args: impl ExactSizeIterator<Item = Val<'static>>,
-    ) -> VmResult<ProtectedVal> {
+    ) -> VmResult<ProtectedVal<'_>> {
         let symbol_not_defined_err = || VmError::SymbolNotDefined {
             src: None,
             symbol: name.to_string(),
@@ -225,13 +755,50 @@ impl Vm {
         self.stack_frames.reset();
         self.stack.clear();
         self.stack.push(function_val);
-        self.stack.extend(args.map(|arg| arg.as_unsafe_val()));
+        for arg in args {
+            if let Err(err) = self.objects.check_owned(arg.as_unsafe_val()) {
+                self.stack.clear();
+                return Err(err);
+            }
+            self.stack.push(arg.as_unsafe_val());
+        }
         unsafe { self.run_gc() };
         let stack_len = self.stack.len();
         self.execute_eval(stack_len)?;
         self.run_all_protected()
     }
 
+    /// Abandon any partially executed evaluation by clearing the stack and stack frames.
+    ///
+    /// Global values and interned symbols are preserved. Functions like [Self::eval_str] already
+    /// reset the execution state before running.
+    pub fn reset_execution(&mut self) {
+        self.stack.clear();
+        self.stack_frames.reset();
+        self.output_captures.clear();
+        self.eval_depth = 0;
+    }
+
+    /// Call `func` with `args` without running the garbage collector. This is used to run macros
+    /// while compiling, when newly compiled values are not yet reachable.
+    ///
+    /// Compilation may happen while the VM is running, like through `eval`, so the call is made
+    /// behind an FFI boundary to leave the current stack frames untouched.
+    pub(crate) fn call_at_compile_time(
+        &mut self,
+        func: UnsafeVal,
+        args: &[UnsafeVal],
+    ) -> VmResult<UnsafeVal> {
+        self.stack_frames.push(StackFrame::new(
+            Default::default(),
+            &Default::default(),
+            self.stack.len(),
+        ));
+        let ret = self.call_reentrant(func, args);
+        self.stack_frames.pop();
+        ret
+    }
+
     fn annotate_src(&self, error: VmError) -> VmError {
         for stack_frame in self.stack_frames.iter() {
             if let Some(src) = stack_frame.previous_instruction_source(self) {
@@ -254,7 +821,7 @@ impl Vm {
     }
 
     /// Similar to [Self::run_all] but the returned value is protected from garbage collection.
-    pub(crate) fn run_all_protected(&mut self) -> VmResult<ProtectedVal> {
+    pub(crate) fn run_all_protected(&mut self) -> VmResult<ProtectedVal<'_>> {
         let v = self.run_all()?;
         Ok(ProtectedVal::new(self, unsafe { Val::from_unsafe_val(v) }))
     }
@@ -278,11 +845,15 @@ impl Vm {
                 self.stack.push(f);
             }
             Instruction::Pop(n) => {
-                let start = self.stack.len() - n;
+                let start = self
+                    .stack
+                    .len()
+                    .checked_sub(*n)
+                    .ok_or_else(BacktraceError::capture)?;
                 self.stack.drain(start..);
             }
             Instruction::GetArg(n) => {
-                let val = self.stack[self.stack_frames.current.stack_start + *n];
+                let val = self.get_arg(*n)?;
                 self.stack.push(val);
             }
             Instruction::BindArg(n) => {
@@ -292,21 +863,29 @@ impl Vm {
             Instruction::Deref(symbol) => {
                 let v = match self.values.get(symbol) {
                     Some(v) => *v,
-                    None => {
-                        return Err(VmError::SymbolNotDefined {
-                            src: None,
-                            symbol: self
-                                .symbol_to_str(*symbol)
-                                .unwrap_or("*symbol-not-registered*")
-                                .to_string(),
-                        });
-                    }
+                    None => return Err(self.symbol_not_defined_error(*symbol)),
+                };
+                self.stack.push(v);
+            }
+            Instruction::DerefCached { symbol, cache } => {
+                let v = match cache.get(self.globals_version) {
+                    Some(id) => UnsafeVal::ByteCodeFunction(id),
+                    None => match self.values.get(symbol) {
+                        Some(v) => {
+                            if let UnsafeVal::ByteCodeFunction(id) = v {
+                                cache.set(self.globals_version, *id);
+                            }
+                            *v
+                        }
+                        None => return Err(self.symbol_not_defined_error(*symbol)),
+                    },
                 };
                 self.stack.push(v);
             }
             Instruction::Define(symbol) => {
                 let v = self.stack.pop().ok_or_else(BacktraceError::capture)?;
                 self.values.insert(*symbol, v);
+                self.globals_version += 1;
             }
             Instruction::Eval(n) => {
                 self.execute_eval(*n)?;
@@ -322,19 +901,42 @@ impl Vm {
             Instruction::Jump(n) => {
                 self.stack_frames.current.instruction_idx += *n;
             }
-            Instruction::Return => return Ok(self.execute_return()),
+            Instruction::JumpBack(n) => {
+                self.stack_frames.current.instruction_idx -= *n;
+            }
+            Instruction::Return => return self.execute_return(),
         }
         Ok(None)
     }
 
+    fn symbol_not_defined_error(&self, symbol: Symbol) -> VmError {
+        VmError::SymbolNotDefined {
+            src: None,
+            symbol: self
+                .symbol_to_str(symbol)
+                .unwrap_or("*symbol-not-registered*")
+                .to_string(),
+        }
+    }
+
     fn execute_eval_native(&mut self, func: NativeFunction, arg_count: usize) -> VmResult<()> {
-        let stack_start = self.stack.len() - arg_count;
+        let stack_start = self
+            .stack
+            .len()
+            .checked_sub(arg_count)
+            .ok_or_else(BacktraceError::capture)?;
         self.stack_frames.push(StackFrame::new(
             Default::default(),
             &Default::default(),
             stack_start,
         ));
-        let builder = func(NativeFunctionContext::new(self))?;
+        let builder = match func(NativeFunctionContext::new(self)) {
+            Ok(b) => b,
+            Err(err) => {
+                self.stack_frames.pop();
+                return Err(err);
+            }
+        };
         // Unsafe OK: Value is inserted into VM immediately.
         let v = unsafe { builder.build() };
         match arg_count {
@@ -371,7 +973,13 @@ impl Vm {
                     &Default::default(),
                     stack_start,
                 ));
-                let builder = func(NativeFunctionContext::new(self))?;
+                let builder = match func(NativeFunctionContext::new(self)) {
+                    Ok(b) => b,
+                    Err(err) => {
+                        self.stack_frames.pop();
+                        return Err(err);
+                    }
+                };
                 // Unsafe OK: Value is inserted into VM immediately.
                 let v = unsafe { builder.build() };
                 self.stack[function_idx] = v;
@@ -385,6 +993,7 @@ impl Vm {
                     let arg_count = n - 1;
                     if bytecode.arg_count != arg_count {
                         return Err(VmError::ArityError {
+                            src: None,
                             function: bytecode.name.clone(),
                             expected: bytecode.arg_count,
                             actual: arg_count,
@@ -395,8 +1004,10 @@ impl Vm {
                     }
                     bytecode
                 };
-                self.stack
-                    .extend(std::iter::repeat(UnsafeVal::Void).take(bytecode.local_bindings));
+                self.stack.extend(std::iter::repeat_n(
+                    UnsafeVal::Void,
+                    bytecode.local_bindings,
+                ));
                 self.stack_frames
                     .push(StackFrame::new(bytecode_id, bytecode, stack_start));
                 Ok(())
@@ -411,6 +1022,59 @@ impl Vm {
         }
     }
 
+    /// Call `func` with `args` and return the result.
+    ///
+    /// This may be used by native functions to call back into the VM. The returned value is not
+    /// protected from garbage collection.
+    ///
+    /// If the call fails, the stack and stack frames are restored to their state before the call
+    /// so the caller may handle the error and continue.
+    pub(crate) fn call_reentrant(
+        &mut self,
+        func: UnsafeVal,
+        args: &[UnsafeVal],
+    ) -> VmResult<UnsafeVal> {
+        let function_idx = self.stack.len();
+        let stack_frame_depth = self.stack_frames.depth();
+        self.stack.push(func);
+        self.stack.extend_from_slice(args);
+        let ret = self.execute_eval(args.len() + 1).and_then(|_| match func {
+            // Bytecode functions run until they return to the FFI boundary, which is the stack
+            // frame of the native function that called `call_reentrant`.
+            UnsafeVal::ByteCodeFunction(_) => self.run_all(),
+            _ => Ok(self.stack[function_idx]),
+        });
+        self.stack.truncate(function_idx);
+        if ret.is_err() {
+            self.stack_frames.truncate(stack_frame_depth);
+        }
+        ret
+    }
+
+    /// Compile and run `source` from within a native function. Garbage collection does not run
+    /// while compiling or running `source`.
+    pub(crate) fn eval_reentrant(&mut self, source: &str) -> VmResult<UnsafeVal> {
+        if self.eval_depth >= MAX_EVAL_DEPTH {
+            return Err(self.execute_call_stack_limit_reached());
+        }
+        // The shared arena may already be in use if `eval` is called while compiling a macro.
+        let arena = Bump::new();
+        let bytecode = Compiler::compile(self, source, &arena)?;
+        let bytecode_id = self.objects.insert_bytecode(bytecode);
+        self.eval_depth += 1;
+        let ret = self.call_reentrant(UnsafeVal::ByteCodeFunction(bytecode_id), &[]);
+        self.eval_depth -= 1;
+        ret
+    }
+
+    /// Write `s` to the innermost output capture or to stdout if output is not being captured.
+    pub(crate) fn write_output(&mut self, s: &str) {
+        match self.output_captures.last_mut() {
+            Some(capture) => capture.push_str(s),
+            None => print!("{s}"),
+        }
+    }
+
     pub fn stack_trace(&self) -> Vec<CompactString> {
         let depth = self.stack_frames.stack_trace_depth();
         let mut call_stack = Vec::with_capacity(depth);
@@ -428,13 +1092,57 @@ impl Vm {
         let call_stack = self.stack_trace();
         let max_depth = call_stack.len();
         VmError::MaximumFunctionCallDepth {
+            src: None,
             call_stack,
             max_depth,
         }
     }
 
+    /// Check that the outermost stack frame of an evaluation, which is returning, has not consumed
+    /// values below its arguments and local bindings. Otherwise, a function has corrupted the stack
+    /// of its caller. This is a sign of a compiler bug so it is only checked in debug builds at the
+    /// end of each evaluation.
+    #[cfg(debug_assertions)]
+    fn check_evaluation_stack(&self) -> VmResult<()> {
+        let stack_frame = &self.stack_frames.current;
+        if !stack_frame.has_valid_function_call() {
+            return Ok(());
+        }
+        let bytecode = stack_frame.bytecode(self);
+        let base = stack_frame.stack_start + bytecode.arg_count + bytecode.local_bindings;
+        if self.stack.len() >= base {
+            return Ok(());
+        }
+        error!(
+            "Stack went below the base of function {name:?} by the end of evaluation at instruction index {idx}. Expected at least {base} values in the stack but found {actual}. Instructions: {instructions:?}. Please file an issue at {ISSUE_LINK} with proper context.",
+            name = bytecode.name,
+            idx = stack_frame.instruction_idx.saturating_sub(1),
+            actual = self.stack.len(),
+            instructions = bytecode.instructions,
+        );
+        Err(BacktraceError::capture().into())
+    }
+
+    /// Get the argument or local binding at index `n` of the current stack frame.
+    fn get_arg(&self, n: usize) -> VmResult<UnsafeVal> {
+        let stack_frame = &self.stack_frames.current;
+        let idx = stack_frame.stack_start + n;
+        match self.stack.get(idx) {
+            Some(v) => Ok(*v),
+            None => {
+                error!(
+                    "Argument index {n} is out of range at instruction index {instruction_idx}. The stack frame starts at {stack_start} but the stack only has {len} values. Please file an issue at {ISSUE_LINK} with proper context.",
+                    instruction_idx = stack_frame.instruction_idx.saturating_sub(1),
+                    stack_start = stack_frame.stack_start,
+                    len = self.stack.len(),
+                );
+                Err(BacktraceError::capture().into())
+            }
+        }
+    }
+
     /// Execute returning from the current stack frame.
-    fn execute_return(&mut self) -> Option<UnsafeVal> {
+    fn execute_return(&mut self) -> VmResult<Option<UnsafeVal>> {
         // 1. Return the current value to the top of the stack.
         let ret_val: UnsafeVal = if self.stack_frames.current.stack_start < self.stack.len() {
             // Unwrap OK: The above statement is never true when len == 0.
@@ -452,19 +1160,23 @@ impl Vm {
                     None => unreachable!(),
                 }
                 self.stack_frames.current = c;
-                None
+                Ok(None)
             }
             // 2b. The previous frame is an ffi boundary. Return the value back to the ffi layer.
             Some(c) => {
+                #[cfg(debug_assertions)]
+                self.check_evaluation_stack()?;
                 self.stack.truncate(self.stack_frames.current.stack_start);
                 self.stack_frames.current = c;
-                Some(ret_val)
+                Ok(Some(ret_val))
             }
             // 2c. There is nothing to continue to so return the value.
             None => {
+                #[cfg(debug_assertions)]
+                self.check_evaluation_stack()?;
                 self.stack.truncate(0);
                 std::mem::take(&mut self.stack_frames.current);
-                Some(ret_val)
+                Ok(Some(ret_val))
             }
         }
     }
@@ -479,9 +1191,14 @@ impl Vm {
     /// # Safety
     ///
     pub unsafe fn run_gc(&mut self) {
-        let is_gc = |v: &UnsafeVal| is_garbage_collected(*v);
+        let collect_symbols = self.settings.enable_symbol_gc;
+        let is_gc = |v: &UnsafeVal| {
+            is_garbage_collected(*v) || (collect_symbols && matches!(v, UnsafeVal::Symbol(_)))
+        };
         let mut arena = self.tmp_arena.take().unwrap_or_else(|| {
-            warn!("Arena was unexpectedly unavailable. Please file an issue at {ISSUE_LINK} with proper context.");
+            if ARENA_UNAVAILABLE_LOG.should_log() {
+                warn!("Arena was unexpectedly unavailable. Please file an issue at {ISSUE_LINK} with proper context.");
+            }
             Bump::new()
         });
         {
@@ -497,13 +1214,21 @@ impl Vm {
                 .copied()
                 .filter(is_gc)
                 .chain(self.values.values().copied().filter(is_gc))
+                .chain(self.macros.values().copied())
+                .chain(
+                    self.values
+                        .keys()
+                        .chain(self.macros.keys())
+                        .filter(|_| collect_symbols)
+                        .map(|symbol| UnsafeVal::Symbol(*symbol)),
+                )
                 .chain(bytecodes.iter().flat_map(|(id, bytecode)| {
                     bytecode
                         .values()
                         .filter(is_gc)
                         .chain(std::iter::once((*id).into()))
                 }));
-            self.objects.run_gc(&arena, vals);
+            self.objects.run_gc(&arena, vals, collect_symbols);
         }
         arena.reset();
         self.tmp_arena = Some(arena);
@@ -511,20 +1236,152 @@ impl Vm {
 }
 
 impl Vm {
-    /// Get the symbol for the given `s`, or `None` if it does not exist within the VM.
-    pub fn get_symbol(&self, s: &str) -> Option<Symbol> {
-        self.objects.get_symbol(s)
-    }
+    /// Keep `val` safe from garbage collection until the returned [RootGuard] is dropped.
+    ///
+    /// Returns an error if `val` was created by a different [Vm].
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let root = {
+    ///     let mut list = vm.eval_str("(list 1 2 3)").unwrap();
+    ///     let (vm, list) = list.split();
+    ///     vm.root(*list).unwrap()
+    /// };
+    /// vm.eval_str("(list 4 5 6)").unwrap();
+    /// let list = root.get(&vm);
+    /// assert_eq!(list.formatted(&vm).to_string(), "(1 2 3)");
+    /// ```
+    pub fn root(&mut self, val: Val) -> VmResult<RootGuard> {
+        let val = val.as_unsafe_val();
+        self.objects.check_owned(val)?;
+        self.objects.keep_reachable(val);
+        Ok(RootGuard::new(self, val))
+    }
+
+    /// Keep `symbol` from being garbage collected until the returned [RootGuard] is dropped. This
+    /// is only needed if [Settings::enable_symbol_gc] is set.
+    ///
+    /// Returns an error if `symbol` was created by a different [Vm].
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::new(spore_vm::Settings::builder().with_symbol_gc(true).build());
+    /// let symbol = vm.get_or_create_symbol("my-symbol");
+    /// let root = vm.root_symbol(symbol).unwrap();
+    /// vm.eval_str("(+ 1 2)").unwrap();
+    /// assert_eq!(vm.symbol_to_str(symbol), Some("my-symbol"));
+    /// ```
+    pub fn root_symbol(&mut self, symbol: Symbol) -> VmResult<RootGuard> {
+        let val = UnsafeVal::Symbol(symbol);
+        self.objects.check_owned(val)?;
+        self.objects.keep_reachable(val);
+        Ok(RootGuard::new(self, val))
+    }
+
+    /// Get the symbol for the given `s`, or `None` if it does not exist within the VM.
+    pub fn get_symbol(&self, s: &str) -> Option<Symbol> {
+        self.objects.get_symbol(s)
+    }
 
     /// Get the given symbol within the VM or create it if it does not exist.
+    ///
+    /// If [Settings::enable_symbol_gc] is set, the symbol may be garbage collected unless it is
+    /// reachable from the VM or held with [Self::root_symbol].
     pub fn get_or_create_symbol(&mut self, s: &str) -> Symbol {
         self.objects.get_or_create_symbol(s)
     }
 
+    /// Get or create the symbol for each of `names`. The returned symbols are in the same order as
+    /// `names`. Prefer this over calling [Self::get_or_create_symbol] in a loop when interning many
+    /// symbols at once as capacity is reserved up front. Like [Self::get_or_create_symbol], the
+    /// symbols must be held with [Self::root_symbol] to survive symbol garbage collection.
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let symbols = vm.intern_many(["key-up", "key-down"]);
+    /// assert_eq!(vm.symbol_to_str(symbols[1]), Some("key-down"));
+    /// ```
+    pub fn intern_many<'a>(&mut self, names: impl IntoIterator<Item = &'a str>) -> Vec<Symbol> {
+        let names = names.into_iter();
+        self.objects.reserve_symbols(names.size_hint().0);
+        names
+            .map(|name| self.objects.get_or_create_symbol(name))
+            .collect()
+    }
+
     /// Get the `str` representation for a symbol.
     pub fn symbol_to_str(&self, s: Symbol) -> Option<&str> {
         self.objects.symbol_to_str(s)
     }
+
+    /// Get the number of symbols that have been interned by the VM. Unless
+    /// [Settings::enable_symbol_gc] is set, symbols are never garbage collected so this only grows
+    /// as new symbols are created.
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// let count = vm.symbol_count();
+    /// vm.get_or_create_symbol("my-new-symbol");
+    /// assert_eq!(vm.symbol_count(), count + 1);
+    /// ```
+    pub fn symbol_count(&self) -> usize {
+        self.objects.symbol_count()
+    }
+
+    /// Get a human readable summary of the number of objects and the approximate number of bytes
+    /// used by each kind of object in the VM.
+    ///
+    /// ```rust
+    /// let vm = spore_vm::Vm::default();
+    /// assert!(vm.memory_report().contains("strings"));
+    /// ```
+    pub fn memory_report(&self) -> String {
+        let stats = self.objects.store_stats();
+        let mut report = format!("{:<10} {:>8} {:>12}\n", "store", "objects", "~bytes");
+        for s in stats.iter() {
+            writeln!(report, "{:<10} {:>8} {:>12}", s.name, s.count, s.bytes).unwrap();
+        }
+        writeln!(
+            report,
+            "{:<10} {:>8} {:>12}",
+            "total",
+            stats.iter().map(|s| s.count).sum::<usize>(),
+            stats.iter().map(|s| s.bytes).sum::<usize>()
+        )
+        .unwrap();
+        report
+    }
+
+    /// Run the garbage collector and return the number of objects and approximate bytes freed from
+    /// each kind of object.
+    ///
+    /// ```rust
+    /// let mut vm = spore_vm::Vm::default();
+    /// vm.eval_str("(list 1 2 3)").unwrap();
+    /// let freed = vm.collect_garbage();
+    /// assert!(freed.iter().any(|s| s.name == "lists" && s.count > 0));
+    /// ```
+    pub fn collect_garbage(&mut self) -> Vec<StoreStats> {
+        let before = self.objects.store_stats();
+        // Unsafe OK: Holding `&mut self` means no unprotected values are in use.
+        unsafe { self.run_gc() };
+        let after = self.objects.store_stats();
+        before
+            .iter()
+            .zip(after.iter())
+            .map(|(b, a)| StoreStats {
+                name: b.name,
+                count: b.count.saturating_sub(a.count),
+                bytes: b.bytes.saturating_sub(a.bytes),
+            })
+            .collect()
+    }
+
+    /// Iterate over all symbols that have been interned by the VM along with their `str`
+    /// representation.
+    pub fn symbols(&self) -> impl '_ + Iterator<Item = (Symbol, &str)> {
+        self.objects.symbols()
+    }
 }
 
 impl Drop for Vm {
@@ -547,6 +1404,79 @@ mod tests {
         assert_eq!(actual.try_int().unwrap(), 42);
     }
 
+    #[test]
+    fn eval_reader_evaluates_all_definitions() {
+        let mut vm = Vm::default();
+        let src = std::io::Cursor::new(
+            "(define x 10)\n(define (add-x n) (+ n x))\n(define y (add-x 2))\n(add-x y)",
+        );
+        assert_eq!(vm.eval_reader(src).unwrap().try_int().unwrap(), 22);
+        assert_eq!(vm.get_int("y"), Some(12));
+    }
+
+    #[test]
+    fn eval_each_reports_successes_and_failures() {
+        let mut vm = Vm::default();
+        let mut successes = Vec::new();
+        let mut failures = Vec::new();
+        let failure_count = vm.eval_each(
+            "(define x 1) (+ x 1) (undefined-fn x) (+ x 2)",
+            true,
+            |idx, res| match res {
+                Ok(v) => successes.push((idx, v.try_int().ok())),
+                Err(err) => failures.push((idx, err)),
+            },
+        );
+        assert_eq!(failure_count, 1);
+        assert_eq!(successes, [(0, None), (1, Some(2)), (3, Some(3))]);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, 2);
+        assert!(matches!(
+            failures[0].1,
+            VmError::SymbolNotDefined { ref symbol, .. } if symbol == "undefined-fn"
+        ));
+    }
+
+    #[test]
+    fn eval_each_error_spans_are_relative_to_source() {
+        let mut vm = Vm::default();
+        let src = "(define (f x) (+ x true))\n(+ 1 2)\n(f 1)\n(+ 1 \"x\")";
+        let mut spans = Vec::new();
+        vm.eval_each(src, true, |_, res| {
+            if let Err(err) = res {
+                let err_src = err.src().unwrap();
+                assert_eq!(err_src.src.as_ref(), src);
+                spans.push(err_src.span.with_src(src).as_str());
+            }
+        });
+        assert_eq!(spans, ["(f 1)", "(+ 1 \"x\")"]);
+    }
+
+    #[test]
+    fn eval_each_can_stop_on_first_error() {
+        let mut vm = Vm::default();
+        let mut indices = Vec::new();
+        let failure_count = vm.eval_each("(+ 1 2) (+ 1 true) (define x 3)", false, |idx, _| {
+            indices.push(idx)
+        });
+        assert_eq!(failure_count, 1);
+        assert_eq!(indices, [0, 1]);
+        assert_eq!(vm.get_int("x"), None);
+    }
+
+    #[test]
+    fn eval_reader_with_invalid_utf8_returns_io_error() {
+        let mut vm = Vm::default();
+        let src = std::io::Cursor::new(vec![b'(', 0xff, b')']);
+        assert!(matches!(
+            vm.eval_reader(src).unwrap_err(),
+            VmError::IoError {
+                kind: std::io::ErrorKind::InvalidData,
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn expression_can_evaluate() {
         let mut vm = Vm::default();
@@ -584,7 +1514,10 @@ mod tests {
         let actual = vm.eval_str("((define x 12))").unwrap_err();
         assert_eq!(
             actual,
-            VmError::CompileError(CompileError::DefineNotAllowed)
+            VmError::CompileError {
+                src: None,
+                error: CompileError::DefineNotAllowed
+            }
         );
     }
 
@@ -680,6 +1613,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("((lambda () 10) 1)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 18).with_src("((lambda () 10) 1)".into())),
                 function: "".into(),
                 expected: 0,
                 actual: 1
@@ -688,6 +1622,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("((lambda (a) a))").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 16).with_src("((lambda (a) a))".into())),
                 function: "".into(),
                 expected: 1,
                 actual: 0
@@ -701,6 +1636,7 @@ mod tests {
         assert_eq!(
             vm.eval_str("(takes-two-args 1)").unwrap_err(),
             VmError::ArityError {
+                src: Some(Span::new(0, 18).with_src("(takes-two-args 1)".into())),
                 function: "takes-two-args".into(),
                 expected: 2,
                 actual: 1,
@@ -788,24 +1724,50 @@ mod tests {
         assert_eq!(
             vm.eval_str("(recurse)").unwrap_err(),
             VmError::MaximumFunctionCallDepth {
+                src: Some(Span::new(0, 9).with_src("(recurse)".into())),
                 max_depth: 65,
                 call_stack: std::iter::once("")
-                    .chain(std::iter::repeat("recurse").take(64))
+                    .chain(std::iter::repeat_n("recurse", 64))
                     .map(Into::into)
                     .collect(),
             }
         );
     }
 
+    #[test]
+    fn recoverable_errors_are_annotated_with_source() {
+        fn lock_not_available(_: NativeFunctionContext) -> VmResult<val::ValBuilder> {
+            Err(val::custom::CustomValError::LockNotAvailable.into())
+        }
+        let mut vm = Vm::default().with_native_function("lock-not-available", lock_not_available);
+        vm.eval_str("(define (recurse) (recurse))").unwrap();
+        for src in [
+            "(+ 1 \"1\")",
+            "(not 1 2)",
+            "(read-string \"(\")",
+            "(undefined-function)",
+            "(recurse)",
+            "(lock-not-available)",
+            "(list-set! (list 1) 5 0)",
+            "(+ 9223372036854775807 1)",
+        ] {
+            let err = vm.eval_str(src).unwrap_err();
+            assert!(err.is_recoverable(), "{err:?}");
+            assert_eq!(err.src().map(|s| s.src.as_ref()), Some(src), "{err:?}");
+        }
+    }
+
     #[test]
     fn aggressive_inline_returns_same_results_when_there_are_no_redefinitions() {
         let mut aggressive_inline_vm = Vm::new(Settings {
             enable_aggressive_inline: true,
             enable_source_maps: false,
+            ..Default::default()
         });
         let mut default_vm = Vm::new(Settings {
             enable_aggressive_inline: false,
             enable_source_maps: true,
+            ..Default::default()
         });
         let srcs = ["(define x 12)", "x", "(+ x x)"];
         for src in srcs {
@@ -926,4 +1888,739 @@ x)
             .unwrap());
         assert!(vm.eval_str("(and 1 2 void 3 4)").unwrap().is_void());
     }
+
+    #[test]
+    fn pop_with_too_many_values_returns_invalid_vm_state() {
+        let mut vm = Vm::default();
+        let bytecode = ByteCode {
+            name: "bad-pop".into(),
+            arg_count: 0,
+            local_bindings: 0,
+            instructions: [Instruction::Pop(100), Instruction::Return].into(),
+            source: None,
+            instruction_source: Box::default(),
+        };
+        let bytecode_id = vm.objects.insert_bytecode(bytecode);
+        unsafe { vm.register_value("bad-pop", bytecode_id) };
+        assert!(matches!(
+            vm.eval_str("(bad-pop)").unwrap_err(),
+            VmError::InvalidVmState(_)
+        ));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn unbalanced_top_level_stack_returns_invalid_vm_state() {
+        let mut vm = Vm::default();
+        let bytecode = ByteCode {
+            name: "unbalanced".into(),
+            arg_count: 0,
+            local_bindings: 2,
+            instructions: [
+                Instruction::Pop(2),
+                Instruction::PushConst(1.into()),
+                Instruction::Return,
+            ]
+            .into(),
+            source: None,
+            instruction_source: Box::default(),
+        };
+        let bytecode_id = vm.objects.insert_bytecode(bytecode);
+        assert!(matches!(
+            vm.run_bytecode(bytecode_id, false).unwrap_err(),
+            VmError::InvalidVmState(_)
+        ));
+        assert_eq!(vm.eval_str("(+ 1 2)").unwrap().try_int().unwrap(), 3);
+    }
+
+    #[test]
+    fn get_arg_out_of_range_returns_invalid_vm_state() {
+        let mut vm = Vm::default();
+        let bytecode = ByteCode {
+            name: "bad-get-arg".into(),
+            arg_count: 1,
+            local_bindings: 0,
+            instructions: [Instruction::GetArg(100), Instruction::Return].into(),
+            source: None,
+            instruction_source: Box::default(),
+        };
+        let bytecode_id = vm.objects.insert_bytecode(bytecode);
+        unsafe { vm.register_value("bad-get-arg", bytecode_id) };
+        assert!(matches!(
+            vm.eval_str("(bad-get-arg 1)").unwrap_err(),
+            VmError::InvalidVmState(_)
+        ));
+        assert_eq!(vm.eval_str("(+ 1 2)").unwrap().try_int().unwrap(), 3);
+    }
+
+    #[test]
+    fn eval_native_with_too_many_args_returns_invalid_vm_state() {
+        let mut vm = Vm::default();
+        let bytecode = ByteCode {
+            name: "bad-native-call".into(),
+            arg_count: 0,
+            local_bindings: 0,
+            instructions: [
+                Instruction::EvalNative {
+                    func: builtins::lists::list,
+                    arg_count: 100,
+                },
+                Instruction::Return,
+            ]
+            .into(),
+            source: None,
+            instruction_source: Box::default(),
+        };
+        let bytecode_id = vm.objects.insert_bytecode(bytecode);
+        unsafe { vm.register_value("bad-native-call", bytecode_id) };
+        assert!(matches!(
+            vm.eval_str("(bad-native-call)").unwrap_err(),
+            VmError::InvalidVmState(_)
+        ));
+    }
+
+    #[test]
+    fn restore_sets_globals_back_to_snapshot_values() {
+        let mut vm = Vm::default();
+        vm.eval_str(
+            "(define score 10) (define name \"player\") (define items (list 1 2)) (define pos (struct 'x 1)) (define counter (new-box 0))",
+        )
+        .unwrap();
+        let snapshot = vm.snapshot();
+        vm.eval_str(
+            "(define score 0) (define name \"other\") (define items (list)) (struct-set! pos 'x 100) (set-box! counter 5)",
+        )
+        .unwrap();
+        vm.restore(&snapshot).unwrap();
+        assert_eq!(vm.get_int("score"), Some(10));
+        assert_eq!(vm.get_str("name"), Some("player"));
+        assert_eq!(vm.eval_str("items").unwrap().to_string(), "(1 2)");
+        assert_eq!(vm.eval_str("pos").unwrap().to_string(), "(struct 'x 1)");
+        assert_eq!(vm.eval_str("counter").unwrap().to_string(), "box<0>");
+    }
+
+    #[test]
+    fn restore_can_be_applied_multiple_times() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define items (list 1 2))").unwrap();
+        let snapshot = vm.snapshot();
+        for _ in 0..2 {
+            vm.eval_str("(list-set! items 0 100)").unwrap();
+            vm.restore(&snapshot).unwrap();
+            assert_eq!(vm.eval_str("items").unwrap().to_string(), "(1 2)");
+        }
+    }
+
+    #[test]
+    fn restore_preserves_values_shared_between_globals() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define a (new-box 1)) (define b (list a))")
+            .unwrap();
+        let snapshot = vm.snapshot();
+        vm.eval_str("(define a (new-box 2))").unwrap();
+        vm.restore(&snapshot).unwrap();
+        vm.eval_str("(set-box! a 3)").unwrap();
+        assert_eq!(vm.eval_str("b").unwrap().to_string(), "(box<3>)");
+    }
+
+    #[test]
+    fn restore_leaves_functions_and_new_globals_unchanged() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define (f) 1) (define fs (list f)) (define x 1)")
+            .unwrap();
+        let snapshot = vm.snapshot();
+        vm.eval_str("(define (f) 2) (define fs 3) (define y 4) (define + 5)")
+            .unwrap();
+        vm.restore(&snapshot).unwrap();
+        assert_eq!(vm.eval_str("(f)").unwrap().try_int().unwrap(), 2);
+        assert_eq!(vm.get_int("fs"), Some(3));
+        assert_eq!(vm.get_int("x"), Some(1));
+        assert_eq!(vm.get_int("y"), Some(4));
+        assert_eq!(vm.get_int("+"), Some(5));
+    }
+
+    #[test]
+    fn snapshot_reports_globals_that_were_not_captured() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define (f) 1) (define fs (list 1 f)) (define x 1)")
+            .unwrap();
+        let snapshot = vm.snapshot();
+        assert_eq!(snapshot.skipped(), ["f", "fs"]);
+    }
+
+    #[test]
+    fn import_value_copies_nested_values_into_other_vm() {
+        let src = "(list 1 \"two\" (list 3.0 'four) (struct 'a (list 5) 'b \"six\"))";
+        let mut src_vm = Vm::default();
+        let src_val = src_vm.eval_str(src).unwrap();
+        let mut dst_vm = Vm::default();
+        dst_vm.eval_str("(define unrelated 'symbol)").unwrap();
+        let dst_val = dst_vm
+            .import_value(src_val.vm(), *src_val)
+            .unwrap()
+            .as_unsafe_val();
+        unsafe { dst_vm.register_value("imported", dst_val) };
+        assert!(dst_vm
+            .eval_str(&format!("(= imported {src})"))
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn import_value_preserves_shared_boxes() {
+        let mut src_vm = Vm::default();
+        let src_val = src_vm
+            .eval_str("(define b (new-box 1)) (list b b)")
+            .unwrap();
+        let mut dst_vm = Vm::default();
+        let dst_val = dst_vm
+            .import_value(src_val.vm(), *src_val)
+            .unwrap()
+            .as_unsafe_val();
+        let list = match dst_val {
+            UnsafeVal::List(id) => dst_vm.objects.get_list(id),
+            v => panic!("expected list but got {v:?}"),
+        };
+        assert!(matches!(list[0], UnsafeVal::MutableBox(_)));
+        assert_eq!(list[0], list[1]);
+        assert_eq!(dst_val.formatted(&dst_vm).to_string(), "(box<1> box<1>)");
+    }
+
+    #[test]
+    fn import_value_with_function_returns_error() {
+        let mut src_vm = Vm::default();
+        let src_val = src_vm.eval_str("(lambda () 1)").unwrap();
+        let mut dst_vm = Vm::default();
+        assert_eq!(
+            dst_vm.import_value(src_val.vm(), *src_val).unwrap_err(),
+            VmError::TypeError {
+                src: None,
+                context: "import_value",
+                expected: "importable value",
+                actual: UnsafeVal::FUNCTION_TYPE_NAME,
+                value: src_val.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn value_from_other_vm_returns_error() {
+        let mut vm_a = Vm::default();
+        let list_a = vm_a.eval_str("(list 1 2 3)").unwrap();
+        let mut vm_b = Vm::default();
+        assert!(list_a.try_list(&vm_b).is_err());
+        let args = std::iter::once(unsafe { list_a.as_static() });
+        assert!(matches!(
+            vm_b.eval_function_by_name("list-length", args).unwrap_err(),
+            VmError::ForeignValue { expected_vm_id, actual_vm_id } if expected_vm_id != actual_vm_id
+        ));
+        assert!(matches!(
+            vm_b.import_value(&Vm::default(), *list_a)
+                .map(|v| v.to_string()),
+            Err(VmError::ForeignValue { .. })
+        ));
+        assert_eq!(list_a.to_string(), "(1 2 3)");
+    }
+
+    #[test]
+    fn set_source_maps_affects_subsequent_compilations_only() {
+        let mut vm = Vm::default();
+        let bytecode_source =
+            |vm: &Vm, name: &str| match vm.val_by_name(name).unwrap().as_unsafe_val() {
+                UnsafeVal::ByteCodeFunction(id) => {
+                    vm.objects.get_bytecode(id).unwrap().source.clone()
+                }
+                v => panic!("expected function but got {v:?}"),
+            };
+
+        vm.set_source_maps(false);
+        assert!(!vm.settings().source_maps());
+        vm.eval_str("(define (without-source) 1)").unwrap();
+        assert_eq!(bytecode_source(&vm, "without-source"), None);
+
+        vm.set_source_maps(true);
+        assert!(vm.settings().source_maps());
+        vm.eval_str("(define (with-source) 1)").unwrap();
+        assert_eq!(
+            bytecode_source(&vm, "with-source").as_deref(),
+            Some("(define (with-source) 1)")
+        );
+        assert_eq!(bytecode_source(&vm, "without-source"), None);
+    }
+
+    #[test]
+    fn rooted_value_survives_gc_until_guard_is_dropped() {
+        let mut vm = Vm::default();
+        let root = {
+            let mut list = vm.eval_str("(list 1 2 3)").unwrap();
+            let (vm, list) = list.split();
+            vm.root(*list).unwrap()
+        };
+        let list = root.get(&vm).as_unsafe_val();
+        unsafe { vm.run_gc() };
+        assert!(vm.objects.is_alive(list));
+        assert_eq!(root.get(&vm).formatted(&vm).to_string(), "(1 2 3)");
+
+        drop(root);
+        unsafe { vm.run_gc() };
+        assert!(!vm.objects.is_alive(list));
+    }
+
+    #[test]
+    fn root_with_value_from_other_vm_returns_error() {
+        let mut vm = Vm::default();
+        let mut other_vm = Vm::default();
+        let other_list = other_vm.eval_str("(list 1 2 3)").unwrap();
+        assert!(matches!(
+            vm.root(*other_list).unwrap_err(),
+            VmError::ForeignValue { .. }
+        ));
+    }
+
+    #[test]
+    fn set_global_defines_value_readable_by_name() {
+        let mut vm = Vm::default();
+        vm.set_global("number", Val::new_int(10)).unwrap();
+        assert_eq!(vm.val_by_name("number").unwrap().try_int().unwrap(), 10);
+
+        let mut list = vm.eval_str("(list 1 2 3)").unwrap();
+        let (vm, list) = list.split();
+        vm.set_global("number", *list).unwrap();
+        unsafe { vm.run_gc() };
+        let got = vm.val_by_name("number").unwrap();
+        assert_eq!(got.formatted(vm).to_string(), "(1 2 3)");
+    }
+
+    #[test]
+    fn set_global_with_value_from_other_vm_returns_error() {
+        let mut vm = Vm::default();
+        let mut other_vm = Vm::default();
+        let other_list = other_vm.eval_str("(list 1 2 3)").unwrap();
+        assert!(matches!(
+            vm.set_global("list", *other_list).unwrap_err(),
+            VmError::ForeignValue { .. }
+        ));
+    }
+
+    #[test]
+    fn define_all_defines_every_value() {
+        let mut vm = Vm::default();
+        vm.define_all([
+            ("an-int", Val::new_int(1)),
+            ("a-float", Val::new_float(2.5)),
+            ("a-bool", Val::new_bool(true)),
+        ])
+        .unwrap();
+        assert_eq!(vm.get_int("an-int"), Some(1));
+        assert_eq!(vm.val_by_name("a-float").unwrap().try_float().unwrap(), 2.5);
+        assert!(vm.val_by_name("a-bool").unwrap().try_bool().unwrap());
+    }
+
+    #[test]
+    fn define_all_with_value_from_other_vm_defines_nothing() {
+        let mut vm = Vm::default();
+        let mut other_vm = Vm::default();
+        let other_list = other_vm.eval_str("(list 1 2 3)").unwrap();
+        assert!(matches!(
+            vm.define_all([("number", Val::new_int(1)), ("other-list", *other_list)])
+                .unwrap_err(),
+            VmError::ForeignValue { .. }
+        ));
+        assert!(vm.val_by_name("number").is_none());
+        assert!(vm.val_by_name("other-list").is_none());
+    }
+
+    #[test]
+    fn typed_getters_return_value_if_present_and_correct_type() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define int-val 10) (define bool-val true) (define str-val \"hello\")")
+            .unwrap();
+        assert_eq!(vm.get_int("int-val"), Some(10));
+        assert_eq!(vm.get_bool("bool-val"), Some(true));
+        assert_eq!(vm.get_str("str-val"), Some("hello"));
+    }
+
+    #[test]
+    fn typed_getters_return_none_if_absent_or_wrong_type() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define int-val 10) (define str-val \"hello\")")
+            .unwrap();
+        assert_eq!(vm.get_int("does-not-exist"), None);
+        assert_eq!(vm.get_bool("does-not-exist"), None);
+        assert_eq!(vm.get_str("does-not-exist"), None);
+        assert_eq!(vm.get_int("str-val"), None);
+        assert_eq!(vm.get_bool("int-val"), None);
+        assert_eq!(vm.get_str("int-val"), None);
+    }
+
+    #[test]
+    fn compile_returns_bytecode_without_running_it() {
+        let mut vm = Vm::default();
+        let id = vm.compile("(define (add-one x) (+ x 1))").unwrap();
+        assert!(vm.val_by_name("add-one").is_none());
+        unsafe { vm.run_gc() };
+
+        let bytecode = vm.bytecode(id).unwrap();
+        let add_one = vm.get_symbol("add-one").unwrap();
+        assert!(matches!(
+            bytecode.instructions.as_ref(),
+            [
+                Instruction::PushConst(UnsafeVal::ByteCodeFunction(_)),
+                Instruction::Define(sym),
+            ] if *sym == add_one
+        ));
+        let lambda_id = match bytecode.instructions[0] {
+            Instruction::PushConst(UnsafeVal::ByteCodeFunction(id)) => id,
+            _ => unreachable!(),
+        };
+        let lambda = vm.bytecode(lambda_id).unwrap();
+        assert_eq!(lambda.name, "add-one");
+        assert_eq!(lambda.arg_count, 1);
+
+        vm.release_compiled(id);
+        unsafe { vm.run_gc() };
+        assert!(vm.bytecode(id).is_none());
+    }
+
+    #[test]
+    fn compile_with_bad_source_returns_error() {
+        let mut vm = Vm::default();
+        assert_eq!(
+            vm.compile("(define)").unwrap_err(),
+            VmError::CompileError {
+                src: None,
+                error: CompileError::ExpressionHasWrongArgs {
+                    expression: "define",
+                    expected: 2,
+                    actual: 0
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn run_compiled_can_run_bytecode_multiple_times() {
+        let mut vm = Vm::default();
+        let id = vm.compile("(+ 1 2)").unwrap();
+        vm.eval_str("(list 1 2 3)").unwrap();
+        assert_eq!(vm.run_compiled(id).unwrap().try_int().unwrap(), 3);
+        assert_eq!(vm.run_compiled(id).unwrap().try_int().unwrap(), 3);
+    }
+
+    #[test]
+    fn run_compiled_sees_changes_to_globals() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define counter (new-box 0))").unwrap();
+        let id = vm
+            .compile("(set-box! counter (+ (unbox counter) 1)) (unbox counter)")
+            .unwrap();
+        assert_eq!(vm.run_compiled(id).unwrap().try_int().unwrap(), 1);
+        assert_eq!(vm.run_compiled(id).unwrap().try_int().unwrap(), 2);
+    }
+
+    #[test]
+    fn run_compiled_after_release_returns_error() {
+        let mut vm = Vm::default();
+        let id = vm.compile("(+ 1 2)").unwrap();
+        vm.release_compiled(id);
+        vm.eval_str("").unwrap();
+        assert_eq!(
+            vm.run_compiled(id).unwrap_err(),
+            VmError::CustomError {
+                src: None,
+                message: "compiled bytecode was not found, it may have been released".into()
+            }
+        );
+    }
+
+    #[test]
+    fn reset_execution_after_partial_step_allows_eval() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define x 40)").unwrap();
+        let id = vm.compile("(+ 1 (+ 2 3))").unwrap();
+        let bytecode = vm.objects.get_bytecode(id).unwrap();
+        vm.stack_frames
+            .reset_with_stack_frame(StackFrame::new(id, bytecode, 0));
+        for _ in 0..3 {
+            assert_eq!(vm.run_next().unwrap(), None);
+        }
+        assert!(!vm.stack.is_empty());
+
+        vm.reset_execution();
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.stack_frames.stack_trace_depth(), 1);
+        assert_eq!(vm.eval_str("(+ x 2)").unwrap().try_int().unwrap(), 42);
+    }
+
+    #[test]
+    fn interning_new_symbols_increases_symbol_count() {
+        let mut vm = Vm::default();
+        let initial_count = vm.symbol_count();
+        assert_eq!(vm.symbols().count(), initial_count);
+
+        let a = vm.get_or_create_symbol("interned-symbol-a");
+        let b = vm.get_or_create_symbol("interned-symbol-b");
+        vm.get_or_create_symbol("interned-symbol-a");
+        assert_eq!(vm.symbol_count(), initial_count + 2);
+        for (symbol, name) in vm.symbols() {
+            assert_eq!(vm.symbol_to_str(symbol), Some(name));
+            assert_eq!(vm.get_symbol(name), Some(symbol));
+        }
+        let new_symbols: Vec<_> = vm.symbols().skip(initial_count).collect();
+        assert_eq!(
+            new_symbols,
+            vec![(a, "interned-symbol-a"), (b, "interned-symbol-b")]
+        );
+    }
+
+    #[test]
+    fn memory_report_lists_each_store() {
+        let mut vm = Vm::default();
+        vm.eval_str(
+            "(define s \"a string that is too long to be stored inline\") (define l (list 1 2 3)) (define st (struct 'x 1)) (define b (new-box 1)) (define (f) 1) (define buf (make-buffer))",
+        )
+        .unwrap();
+        let report = vm.memory_report();
+        for store in [
+            "strings",
+            "boxes",
+            "lists",
+            "structs",
+            "bytecodes",
+            "customs",
+            "symbols",
+            "total",
+        ] {
+            let line = report
+                .lines()
+                .find(|line| line.starts_with(store))
+                .unwrap_or_else(|| panic!("{store} not in report:\n{report}"));
+            let count: usize = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+            let bytes: usize = line.split_whitespace().nth(2).unwrap().parse().unwrap();
+            assert!(count > 0, "{line}");
+            assert!(bytes > 0, "{line}");
+        }
+    }
+
+    #[test]
+    fn compiling_same_lambda_twice_reuses_bytecode() {
+        let mut vm = Vm::default();
+        let lambda_id = |vm: &mut Vm, src: &str| match vm.eval_str(src).unwrap().as_unsafe_val() {
+            UnsafeVal::ByteCodeFunction(id) => id,
+            v => panic!("expected function but got {v:?}"),
+        };
+        let src = "(lambda (x) (+ x 1))";
+        let first = lambda_id(&mut vm, src);
+        assert_eq!(lambda_id(&mut vm, src), first);
+        assert_ne!(lambda_id(&mut vm, "(lambda (x) (+ x 2))"), first);
+
+        vm.eval_str("(define inc (lambda (x) (+ x 1)))").unwrap();
+        unsafe { vm.run_gc() };
+        assert_eq!(vm.eval_str("(inc 1)").unwrap().try_int().unwrap(), 2);
+        let after_gc = lambda_id(&mut vm, src);
+        assert_eq!(lambda_id(&mut vm, src), after_gc);
+    }
+
+    #[test]
+    fn inline_cache_setting_controls_call_site_instruction() {
+        for (enable, want) in [(true, "DerefCached"), (false, "Deref")] {
+            let mut vm = Vm::new(Settings::builder().with_inline_cache(enable).build());
+            vm.eval_str("(define (f) 1)").unwrap();
+            let id = vm.compile("(f)").unwrap();
+            let counts = vm.bytecode(id).unwrap().instruction_counts();
+            assert_eq!(counts.get(want), Some(&1), "{counts:?}");
+        }
+    }
+
+    #[test]
+    fn inline_cache_on_and_off_return_same_results() {
+        let src = r#"
+(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+(define (get) 1)
+(define (call-get) (get))
+(define results (new-box (list (fib 20) (call-get))))
+(define (get) 2)
+(define (redefine-and-call) (eval (read-string "(define (get) 3)")) (call-get))
+(set-box! results (list (unbox results) (call-get) (redefine-and-call) (call-get)))
+(flatten (unbox results))
+"#;
+        let results: Vec<String> = [true, false]
+            .into_iter()
+            .map(|enable| {
+                let mut vm = Vm::new(Settings::builder().with_inline_cache(enable).build());
+                let result = vm.eval_str(src).unwrap().to_string();
+                result
+            })
+            .collect();
+        assert_eq!(results, vec!["(6765 1 2 3 3)", "(6765 1 2 3 3)"]);
+    }
+
+    #[test]
+    fn try_with_returns_vm_from_closure() {
+        let mut vm = Vm::default()
+            .try_with(|mut vm| {
+                vm.eval_str("(define (double x) (+ x x))")?;
+                Ok(vm)
+            })
+            .unwrap();
+        assert_eq!(vm.eval_str("(double 21)").unwrap().try_int().unwrap(), 42);
+    }
+
+    #[test]
+    fn try_with_propagates_error_from_closure() {
+        let err = Vm::default()
+            .try_with(|mut vm| {
+                vm.eval_str("(undefined-function)")?;
+                Ok(vm)
+            })
+            .unwrap_err();
+        assert!(matches!(err, VmError::SymbolNotDefined { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn with_prelude_defines_values() {
+        let mut vm = Vm::default()
+            .with_prelude(
+                "(define (multiply a b) (if (< b 1) 0 (+ a (multiply a (- b 1)))))
+                 (define (square x) (multiply x x))",
+            )
+            .unwrap();
+        assert_eq!(vm.eval_str("(square 7)").unwrap().try_int().unwrap(), 49);
+    }
+
+    #[test]
+    fn with_prelude_with_bad_source_returns_error() {
+        assert!(matches!(
+            Vm::default()
+                .with_prelude("(define (broken x) x")
+                .unwrap_err(),
+            VmError::CompileError { .. }
+        ));
+        assert!(matches!(
+            Vm::default()
+                .with_prelude("(undefined-function)")
+                .unwrap_err(),
+            VmError::SymbolNotDefined { .. }
+        ));
+    }
+
+    #[test]
+    fn intern_many_returns_symbols_in_order() {
+        let mut vm = Vm::default();
+        let initial_count = vm.symbol_count();
+        let names: Vec<String> = (0..100).map(|i| format!("event-{i}")).collect();
+        let symbols = vm.intern_many(names.iter().map(String::as_str));
+        assert_eq!(symbols.len(), names.len());
+        assert_eq!(vm.symbol_count(), initial_count + names.len());
+        for (symbol, name) in symbols.iter().zip(names.iter()) {
+            assert_eq!(vm.symbol_to_str(*symbol), Some(name.as_str()));
+        }
+        assert_eq!(
+            vm.intern_many(["event-3", "+"]),
+            vec![symbols[3], vm.get_symbol("+").unwrap()]
+        );
+    }
+
+    #[test]
+    fn symbol_gc_removes_unreachable_symbols() {
+        let mut vm = Vm::new(Settings {
+            enable_symbol_gc: true,
+            ..Default::default()
+        });
+        vm.eval_str("(define kept 'kept-symbol) (define (get-field s) (struct-get s 'field))")
+            .unwrap();
+        vm.eval_str("(define s (struct 'struct-field 1))").unwrap();
+        unsafe { vm.run_gc() };
+        let initial_count = vm.symbol_count();
+
+        vm.eval_str("(read-string \"(throwaway-1 throwaway-2)\")")
+            .unwrap();
+        let throwaway = vm.get_or_create_symbol("throwaway-0");
+        assert_eq!(vm.symbol_count(), initial_count + 3);
+        unsafe { vm.run_gc() };
+        assert_eq!(vm.symbol_count(), initial_count);
+        assert_eq!(vm.symbol_to_str(throwaway), None);
+        assert_eq!(vm.get_symbol("throwaway-1"), None);
+        for name in ["kept-symbol", "get-field", "field", "struct-field", "+"] {
+            assert!(vm.get_symbol(name).is_some(), "{name} was removed");
+        }
+
+        let recreated = vm.get_or_create_symbol("throwaway-0");
+        assert_ne!(recreated, throwaway);
+        assert_eq!(vm.symbol_to_str(recreated), Some("throwaway-0"));
+    }
+
+    #[test]
+    fn rooted_symbols_survive_symbol_gc() {
+        let mut vm = Vm::new(Settings {
+            enable_symbol_gc: true,
+            ..Default::default()
+        });
+        let evaluated_root = {
+            let mut v = vm.eval_str("'evaluated-symbol").unwrap();
+            let (vm, v) = v.split();
+            vm.root(*v).unwrap()
+        };
+        let created = vm.get_or_create_symbol("created-symbol");
+        let created_root = vm.root_symbol(created).unwrap();
+        unsafe { vm.run_gc() };
+        vm.eval_str("(read-string \"(other-1 other-2)\")").unwrap();
+        unsafe { vm.run_gc() };
+        assert_eq!(
+            evaluated_root.get(&vm).formatted(&vm).to_string(),
+            "evaluated-symbol"
+        );
+        assert_eq!(vm.symbol_to_str(created), Some("created-symbol"));
+        assert_eq!(vm.get_or_create_symbol("created-symbol"), created);
+
+        drop(created_root);
+        drop(evaluated_root);
+        unsafe { vm.run_gc() };
+        assert_eq!(vm.symbol_to_str(created), None);
+        assert_eq!(vm.get_symbol("evaluated-symbol"), None);
+    }
+
+    #[test]
+    fn symbols_are_not_collected_by_default() {
+        let mut vm = Vm::default();
+        let throwaway = vm.get_or_create_symbol("throwaway");
+        unsafe { vm.run_gc() };
+        assert_eq!(vm.symbol_to_str(throwaway), Some("throwaway"));
+    }
+
+    #[test]
+    fn eval_str_no_gc_returns_same_result_as_eval_str() {
+        let src = "(define (fib n) (if (< n 2) n (+ (fib (+ n -1)) (fib (+ n -2))))) (fib 15)";
+        let mut vm = Vm::default();
+        let expected = vm.eval_str(src).unwrap().try_int().unwrap();
+        let mut vm = Vm::default();
+        assert_eq!(vm.eval_str_no_gc(src).unwrap().try_int().unwrap(), expected);
+        assert_eq!(
+            vm.eval_str_no_gc("(list 1 2 3)").unwrap().to_string(),
+            "(1 2 3)"
+        );
+    }
+
+    #[test]
+    fn eval_str_without_expressions_returns_void() {
+        let mut vm = Vm::default();
+        assert!(vm.eval_str("").unwrap().is_void());
+        assert!(vm.eval_str("   ").unwrap().is_void());
+        assert!(vm.eval_str("\n\t ").unwrap().is_void());
+        assert!(vm.eval_str("; just a comment").unwrap().is_void());
+        assert!(vm
+            .eval_str("  ; a comment\n  ; another comment\n")
+            .unwrap()
+            .is_void());
+    }
+
+    #[test]
+    fn eval_str_without_expressions_after_error_returns_void() {
+        let mut vm = Vm::default();
+        vm.eval_str("(+ 1 (list 1 2))").unwrap_err();
+        assert!(vm.eval_str("").unwrap().is_void());
+        assert_eq!(vm.eval_str("  42  ").unwrap().try_int().unwrap(), 42);
+        assert!(vm.eval_str("; just a comment").unwrap().is_void());
+    }
 }
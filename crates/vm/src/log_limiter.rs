@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Limits how often a diagnostic is logged. Useful for diagnostics that may trigger within a hot
+/// loop and flood the logs.
+///
+/// The first occurrence is always logged. After that, only one of every `interval` occurrences is
+/// logged.
+pub(crate) struct LogRateLimiter {
+    interval: usize,
+    occurrences: AtomicUsize,
+}
+
+impl LogRateLimiter {
+    /// Create a new rate limiter that logs once every `interval` occurrences.
+    pub const fn new(interval: usize) -> LogRateLimiter {
+        LogRateLimiter {
+            interval,
+            occurrences: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record an occurrence and return `true` if it should be logged.
+    pub fn should_log(&self) -> bool {
+        let occurrence = self.occurrences.fetch_add(1, Ordering::Relaxed);
+        occurrence.is_multiple_of(self.interval.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_log_allows_one_of_every_interval_occurrences() {
+        let limiter = LogRateLimiter::new(100);
+        let logged: Vec<usize> = (0..250).filter(|_| limiter.should_log()).collect();
+        assert_eq!(logged, vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn should_log_with_interval_of_one_logs_everything() {
+        let limiter = LogRateLimiter::new(1);
+        assert!((0..10).all(|_| limiter.should_log()));
+    }
+}
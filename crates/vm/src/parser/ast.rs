@@ -14,13 +14,19 @@ pub enum AstParseError {
     #[error("opening parenthesis was unclosed")]
     UnclosedParen,
     #[error("found unexpected closing parenthesis")]
-    UnexpectedCloseParen,
+    UnexpectedCloseParen(Span),
+    #[error("closing delimiter does not match the opening delimiter")]
+    MismatchedDelimiter { open: Span, close: Span },
     #[error("string was not properly closed, did you forget \"?")]
     UnclosedString(Span),
+    #[error("expected an expression but found nothing")]
+    MissingDatum,
+    #[error("found unexpected token after the end of the expression")]
+    UnexpectedTrailingToken(Span),
 }
 
 /// Describes a node in the AST.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Node {
     /// A node containing the start and end points of the identifier.
     Identifier(Span),
@@ -41,9 +47,28 @@ pub enum Node {
 impl Node {
     /// Parse the contents of `src` into a stream of `Node`.
     pub fn parse(src: &str) -> impl '_ + Iterator<Item = Result<Self>> {
-        let mut tokens = Token::parse_tokens(src);
+        Node::parse_from(src, 0)
+    }
+
+    /// Similar to [Node::parse], but parsing begins at byte offset `start`. Spans are still relative
+    /// to the beginning of `src`.
+    pub fn parse_from(src: &str, start: usize) -> impl '_ + Iterator<Item = Result<Self>> {
+        let mut tokens = Token::parse_tokens_from(src, start);
         std::iter::from_fn(move || Node::parse_next(src, &mut tokens))
     }
+
+    /// Parse the contents of `src` as a single `Node`.
+    ///
+    /// Unlike [Node::parse], an error is returned if `src` contains anything after the first
+    /// `Node`. Comments are allowed.
+    pub fn parse_one(src: &str) -> Result<Self> {
+        let mut tokens = Token::parse_tokens(src);
+        let node = Node::parse_next(src, &mut tokens).ok_or(AstParseError::MissingDatum)??;
+        match tokens.find(|t| t.token_type != TokenType::Comment) {
+            Some(extra) => Err(AstParseError::UnexpectedTrailingToken(extra.span)),
+            None => Ok(node),
+        }
+    }
 }
 
 impl Node {
@@ -59,14 +84,16 @@ impl Node {
     fn parse_next(src: &str, tokenizer: &mut impl Iterator<Item = Token>) -> Option<Result<Node>> {
         while let Some(next_token) = tokenizer.next() {
             match next_token.token_type {
-                TokenType::OpenParen => match Node::parse_until_close(src, tokenizer) {
+                TokenType::OpenParen => match Node::parse_until_close(src, next_token, tokenizer) {
                     Ok((end, tree)) => {
                         let span = next_token.span.extend_end(end);
                         return Some(Ok(Node::Tree(span, tree)));
                     }
                     Err(err) => return Some(Err(err)),
                 },
-                TokenType::CloseParen => return Some(Err(AstParseError::UnexpectedCloseParen)),
+                TokenType::CloseParen => {
+                    return Some(Err(AstParseError::UnexpectedCloseParen(next_token.span)))
+                }
                 TokenType::UnterminatedString => {
                     return Some(Err(AstParseError::UnclosedString(next_token.span)))
                 }
@@ -79,24 +106,40 @@ impl Node {
         None
     }
 
-    /// Parse the nodes in `tokenizer` until a closing parenthesis is encountered.
+    /// Parse the nodes in `tokenizer` until the closing parenthesis or bracket for `open` is
+    /// encountered.
     ///
-    /// An error is returned if no closing parenthesis is ever encountered.
+    /// An error is returned if no closing parenthesis is ever encountered or if the closing
+    /// delimiter does not match `open`. For example, `(` must be closed by `)` and `[` must be
+    /// closed by `]`.
     fn parse_until_close(
         src: &str,
+        open: Token,
         tokenizer: &mut impl Iterator<Item = Token>,
     ) -> Result<(u32, Vec<Node>)> {
         let mut tree = vec![];
         while let Some(next_token) = tokenizer.next() {
             match next_token.token_type {
-                TokenType::OpenParen => match Node::parse_until_close(src, tokenizer) {
+                TokenType::OpenParen => match Node::parse_until_close(src, next_token, tokenizer) {
                     Ok((end, t)) => {
                         let span = next_token.span.extend_end(end);
                         tree.push(Node::Tree(span, t))
                     }
                     err @ Err(_) => return err,
                 },
-                TokenType::CloseParen => return Ok((next_token.span.end, tree)),
+                TokenType::CloseParen => {
+                    let is_match = matches!(
+                        (open.as_str(src), next_token.as_str(src)),
+                        ("(", ")") | ("[", "]")
+                    );
+                    if !is_match {
+                        return Err(AstParseError::MismatchedDelimiter {
+                            open: open.span,
+                            close: next_token.span,
+                        });
+                    }
+                    return Ok((next_token.span.end, tree));
+                }
                 TokenType::UnterminatedString => {
                     return Err(AstParseError::UnclosedString(next_token.span))
                 }
@@ -286,6 +329,49 @@ mod tests {
         assert_eq!(actual[0].to_string_literal(src).unwrap(), "\\");
     }
 
+    #[test]
+    fn parse_one_returns_single_node() {
+        assert_eq!(
+            Node::parse_one("(+ 1 2) ; comment").unwrap(),
+            Node::Tree(
+                Span::new(0, 7),
+                vec![
+                    Node::Identifier(Span::new(1, 2)),
+                    Node::Int(Span::new(3, 4), 1),
+                    Node::Int(Span::new(5, 6), 2),
+                ]
+            )
+        );
+        assert_eq!(
+            Node::parse_one(" 1 ").unwrap(),
+            Node::Int(Span::new(1, 2), 1)
+        );
+    }
+
+    #[test]
+    fn parse_one_with_trailing_tokens_returns_error() {
+        assert_eq!(
+            Node::parse_one("1 2").unwrap_err(),
+            AstParseError::UnexpectedTrailingToken(Span::new(2, 3))
+        );
+        assert_eq!(
+            Node::parse_one("(a) )").unwrap_err(),
+            AstParseError::UnexpectedTrailingToken(Span::new(4, 5))
+        );
+    }
+
+    #[test]
+    fn parse_one_with_no_nodes_returns_error() {
+        assert_eq!(
+            Node::parse_one(" ; just a comment").unwrap_err(),
+            AstParseError::MissingDatum
+        );
+        assert_eq!(
+            Node::parse_one("(unclosed").unwrap_err(),
+            AstParseError::UnclosedParen
+        );
+    }
+
     #[test]
     fn unclosed_paren_returns_error() {
         let src = "(not closed";
@@ -297,7 +383,59 @@ mod tests {
     fn unexpected_close_paren_returns_error() {
         let src = "not closed)";
         let actual_err = Node::parse_to_vec(src).unwrap_err();
-        assert_eq!(actual_err, AstParseError::UnexpectedCloseParen);
+        assert_eq!(
+            actual_err,
+            AstParseError::UnexpectedCloseParen(Span::new(10, 11))
+        );
+    }
+
+    #[test]
+    fn brackets_are_parsed_as_tree() {
+        let src = "[+ 1 (- a b)]";
+        let actual = Node::parse_to_vec(src).unwrap();
+        assert_eq!(
+            actual,
+            vec![Node::Tree(
+                Span::new(0, 13),
+                vec![
+                    Node::Identifier(Span::new(1, 2)),
+                    Node::Int(Span::new(3, 4), 1),
+                    Node::Tree(
+                        Span::new(5, 12),
+                        vec![
+                            Node::Identifier(Span::new(6, 7)),
+                            Node::Identifier(Span::new(8, 9)),
+                            Node::Identifier(Span::new(10, 11))
+                        ]
+                    ),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn mismatched_close_delimiter_returns_error() {
+        assert_eq!(
+            Node::parse_to_vec("(a b]").unwrap_err(),
+            AstParseError::MismatchedDelimiter {
+                open: Span::new(0, 1),
+                close: Span::new(4, 5)
+            }
+        );
+        assert_eq!(
+            Node::parse_to_vec("[a b)").unwrap_err(),
+            AstParseError::MismatchedDelimiter {
+                open: Span::new(0, 1),
+                close: Span::new(4, 5)
+            }
+        );
+        assert_eq!(
+            Node::parse_to_vec("(x [y)]").unwrap_err(),
+            AstParseError::MismatchedDelimiter {
+                open: Span::new(3, 4),
+                close: Span::new(5, 6)
+            }
+        );
     }
 
     #[test]
@@ -1,5 +1,7 @@
+use serde::Serialize;
+
 /// Describes the location of a substring within a string.
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
 pub struct Span {
     /// The start of the substring.
     pub start: u32,
@@ -44,6 +46,15 @@ impl Span {
         }
     }
 
+    /// Returns the smallest span that contains both `self` and `other`, including anything between
+    /// them if they are disjoint.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
     /// Returns a span that overlaps with both `self` and `other` or `None` if there is no overlap.
     pub fn overlap(self, other: Span) -> Option<Span> {
         let start = self.start.max(other.start);
@@ -99,3 +110,21 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_overlapping_spans_covers_both() {
+        assert_eq!(Span::new(2, 6).merge(Span::new(4, 10)), Span::new(2, 10));
+        assert_eq!(Span::new(4, 10).merge(Span::new(2, 6)), Span::new(2, 10));
+        assert_eq!(Span::new(0, 10).merge(Span::new(3, 4)), Span::new(0, 10));
+    }
+
+    #[test]
+    fn merge_disjoint_spans_covers_the_gap() {
+        assert_eq!(Span::new(0, 2).merge(Span::new(5, 8)), Span::new(0, 8));
+        assert_eq!(Span::new(5, 8).merge(Span::new(0, 2)), Span::new(0, 8));
+    }
+}
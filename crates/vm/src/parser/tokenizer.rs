@@ -34,7 +34,13 @@ impl Token {
 
     /// Parse an input source into a stream of tokens.
     pub fn parse_tokens(input_source: &str) -> impl '_ + Iterator<Item = Token> {
-        let mut start = 0;
+        Token::parse_tokens_from(input_source, 0)
+    }
+
+    /// Similar to [Self::parse_tokens], but parsing begins at byte offset `start`. Spans are still
+    /// relative to the beginning of `input_source`.
+    pub fn parse_tokens_from(input_source: &str, start: usize) -> impl '_ + Iterator<Item = Token> {
+        let mut start = start;
         std::iter::from_fn(move || {
             Token::parse_next(input_source, start).inspect(|t| start = t.span.end as usize)
         })
@@ -1,6 +1,22 @@
 use rustyline::DefaultEditor;
 
-use crate::{parser::ast::Node, val::ProtectedVal, Vm};
+use std::fmt::Write;
+
+use crate::{
+    builtins,
+    error::VmResult,
+    parser::ast::Node,
+    val::{FormatOptions, ProtectedVal, Val},
+    StoreStats, Vm,
+};
+
+/// The options used to print evaluated values. Large values are truncated to keep the output
+/// readable.
+const REPL_FORMAT_OPTIONS: FormatOptions = FormatOptions {
+    quote_strings: true,
+    max_depth: Some(16),
+    max_length: Some(100),
+};
 
 /// Run an read/evaluate/print/loop.
 ///
@@ -21,13 +37,67 @@ use crate::{parser::ast::Node, val::ProtectedVal, Vm};
 pub struct Repl {
     vm: Vm,
     editor: DefaultEditor,
+    config: ReplConfig,
+    result_count: usize,
+}
+
+/// Configures the behavior of a [Repl].
+///
+/// ```rust
+/// let config = spore_vm::repl::ReplConfig {
+///     prompt: "spore> ".into(),
+///     ..Default::default()
+/// };
+/// let repl = spore_vm::repl::Repl::with_config(spore_vm::Vm::default(), config);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplConfig {
+    /// The prompt displayed when reading a new expression.
+    pub prompt: String,
+    /// The prompt displayed when reading the next line of an incomplete expression.
+    pub continuation_prompt: String,
+}
+
+impl Default for ReplConfig {
+    fn default() -> ReplConfig {
+        ReplConfig {
+            prompt: ">> ".into(),
+            continuation_prompt: ".. ".into(),
+        }
+    }
+}
+
+impl ReplConfig {
+    /// Get the prompt to display when `input` has been read so far.
+    fn prompt_for(&self, input: &str) -> &str {
+        if input.is_empty() {
+            &self.prompt
+        } else {
+            &self.continuation_prompt
+        }
+    }
 }
 
 impl Repl {
     /// Create a new REPL.
     pub fn new(vm: Vm) -> rustyline::Result<Repl> {
+        Repl::with_config(vm, ReplConfig::default())
+    }
+
+    /// Create a new REPL configured with `config`.
+    pub fn with_config(vm: Vm, config: ReplConfig) -> rustyline::Result<Repl> {
         let editor = DefaultEditor::new()?;
-        Ok(Repl { vm, editor })
+        Ok(Repl {
+            vm,
+            editor,
+            config,
+            result_count: 0,
+        })
+    }
+
+    /// Get the configuration of the REPL.
+    pub fn config(&self) -> &ReplConfig {
+        &self.config
     }
 
     /// Convert `self` into a [Vm].
@@ -47,6 +117,14 @@ impl Repl {
 
     /// Evaluate the next user input. The input is read through `stdin` and the result is written
     /// through `stdout`.
+    ///
+    /// Each result is bound to a global `$N` where `N` starts at 1 and increments with each
+    /// successful evaluation.
+    ///
+    /// Input that starts with `,` is treated as a REPL command instead of being evaluated:
+    ///   - `,env` - Print the name and type of all globals that are not builtins.
+    ///   - `,mem` - Print the number of objects and approximate memory used by the VM.
+    ///   - `,gc` - Run the garbage collector and print the number of objects freed.
     pub fn eval_next_input(&mut self) -> rustyline::Result<ProtectedVal<'_>> {
         let mut input = String::new();
         fn input_is_ready(input: &str) -> rustyline::Result<bool> {
@@ -68,19 +146,187 @@ impl Repl {
             }
             Ok(true)
         }
-        while !input_is_ready(&input)? {
-            let prompt = if input.is_empty() { ">> " } else { ".. " };
+        while !input.starts_with(',') && !input_is_ready(&input)? {
+            let prompt = self.config.prompt_for(&input);
             match self.editor.readline(prompt) {
                 Ok(line) => input.push_str(line.as_str()),
                 Err(err) => return Err(err),
             };
         }
-        let res = self
-            .vm
-            .eval_str(&input)
-            .inspect(|v| println!("{v}"))
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err).into());
-        let _ = self.editor.add_history_entry(input);
-        res
+        if let Some(command) = input.strip_prefix(',') {
+            let _ = self.editor.add_history_entry(input.as_str());
+            return self.run_command(command.trim());
+        }
+        let _ = self.editor.add_history_entry(input.as_str());
+        self.eval_and_record(&input)
+            .inspect(|v| println!("{}", v.formatted_with(v.vm(), REPL_FORMAT_OPTIONS)))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err).into())
+    }
+
+    /// Evaluate `input` and bind the result to the next `$N` global.
+    fn eval_and_record(&mut self, input: &str) -> VmResult<ProtectedVal<'_>> {
+        let mut res = self.vm.eval_str(input)?;
+        self.result_count += 1;
+        let name = format!("${}", self.result_count);
+        let (vm, val) = res.split();
+        vm.set_global(&name, *val)?;
+        Ok(res)
+    }
+
+    /// Run the REPL command `command`.
+    fn run_command(&mut self, command: &str) -> rustyline::Result<ProtectedVal<'_>> {
+        print!("{}", self.command_output(command)?);
+        Ok(ProtectedVal::new(&mut self.vm, Val::new_void()))
+    }
+
+    /// Run the REPL command `command` and return the output that should be printed.
+    fn command_output(&mut self, command: &str) -> std::io::Result<String> {
+        match command {
+            "env" => Ok(env_listing(&self.vm)),
+            "mem" => Ok(self.vm.memory_report()),
+            "gc" => Ok(freed_listing(&self.vm.collect_garbage())),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("unknown REPL command ,{command}"),
+            )),
+        }
+    }
+}
+
+/// List the name and type of all globals in `vm` that are not builtins, sorted by name.
+fn env_listing(vm: &Vm) -> String {
+    let mut globals: Vec<_> = vm
+        .globals()
+        .filter(|(name, val)| !builtins::is_builtin(name, val.as_unsafe_val()))
+        .collect();
+    globals.sort_by_key(|(name, _)| *name);
+    let mut listing = String::new();
+    for (name, val) in globals {
+        // Unwrap OK: Writing to a string does not fail.
+        writeln!(listing, "{name} : {type_name}", type_name = val.type_name()).unwrap();
+    }
+    listing
+}
+
+/// Describe the number of objects and approximate bytes freed from each store.
+fn freed_listing(freed: &[StoreStats]) -> String {
+    let mut listing = String::new();
+    for s in freed.iter() {
+        // Unwrap OK: Writing to a string does not fail.
+        writeln!(listing, "freed {} {} (~{} bytes)", s.count, s.name, s.bytes).unwrap();
+    }
+    writeln!(
+        listing,
+        "freed {} objects total",
+        freed.iter().map(|s| s.count).sum::<usize>()
+    )
+    .unwrap();
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_uses_standard_prompts() {
+        let config = ReplConfig::default();
+        assert_eq!(config.prompt_for(""), ">> ");
+        assert_eq!(config.prompt_for("(+ 1"), ".. ");
+    }
+
+    #[test]
+    fn custom_prompts_are_used_by_repl() {
+        let config = ReplConfig {
+            prompt: "spore> ".into(),
+            continuation_prompt: "...... ".into(),
+        };
+        let repl = Repl::with_config(Vm::default(), config).unwrap();
+        assert_eq!(repl.config().prompt_for(""), "spore> ");
+        assert_eq!(repl.config().prompt_for("(+ 1"), "...... ");
+    }
+
+    #[test]
+    fn env_listing_contains_user_defined_globals() {
+        let mut vm = Vm::default();
+        vm.eval_str("(define x 42) (define (double n) (+ n n)) (define name \"spore\")")
+            .unwrap();
+        assert_eq!(
+            env_listing(&vm),
+            "double : function\nname : string\nx : int\n"
+        );
+    }
+
+    #[test]
+    fn env_listing_excludes_builtins_unless_redefined() {
+        let mut vm = Vm::default();
+        assert_eq!(env_listing(&vm), "");
+        vm.eval_str("(define list 1)").unwrap();
+        assert_eq!(env_listing(&vm), "list : int\n");
+    }
+
+    #[test]
+    fn mem_command_prints_memory_report() {
+        let mut repl = Repl::new(Vm::default()).unwrap();
+        let output = repl.command_output("mem").unwrap();
+        for store in ["strings", "lists", "bytecodes", "symbols"] {
+            assert!(output.contains(store), "{store} not in {output}");
+        }
+    }
+
+    #[test]
+    fn gc_command_prints_freed_objects() {
+        let mut repl = Repl::new(Vm::default()).unwrap();
+        repl.eval_and_record("(define (make-garbage) (list 1 2 3) (list 4 5 6) 0)")
+            .unwrap();
+        repl.eval_and_record("(make-garbage)").unwrap();
+        let output = repl.command_output("gc").unwrap();
+        let freed_lists: usize = output
+            .lines()
+            .find_map(|line| line.strip_prefix("freed ")?.split_once(" lists "))
+            .map(|(count, _)| count)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(freed_lists > 0, "{output}");
+    }
+
+    #[test]
+    fn unknown_command_returns_error() {
+        let mut repl = Repl::new(Vm::default()).unwrap();
+        assert_eq!(
+            repl.command_output("not-a-command")
+                .unwrap_err()
+                .to_string(),
+            "unknown REPL command ,not-a-command"
+        );
+    }
+
+    #[test]
+    fn results_are_bound_to_dollar_globals() {
+        let mut repl = Repl::new(Vm::default()).unwrap();
+        assert_eq!(
+            repl.eval_and_record("(+ 1 2)").unwrap().try_int().unwrap(),
+            3
+        );
+        assert_eq!(repl.as_vm().get_int("$1"), Some(3));
+        assert_eq!(
+            repl.eval_and_record("(+ $1 10)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            13
+        );
+        assert_eq!(repl.as_vm().get_int("$2"), Some(13));
+        assert_eq!(repl.as_vm().get_int("$1"), Some(3));
+    }
+
+    #[test]
+    fn failed_evaluations_are_not_bound() {
+        let mut repl = Repl::new(Vm::default()).unwrap();
+        repl.eval_and_record("(+ 1 \"2\")").unwrap_err();
+        assert!(repl.as_vm().val_by_name("$1").is_none());
+        repl.eval_and_record("4").unwrap();
+        assert_eq!(repl.as_vm().get_int("$1"), Some(4));
     }
 }
@@ -1,11 +1,37 @@
 /// Settings for the Spore virtual machine.
+///
+/// Settings may be created with [Settings::builder] or by modifying [Settings::default].
+///
+/// ```rust
+/// let settings = spore_vm::Settings::builder()
+///     .with_aggressive_inline(true)
+///     .build();
+/// assert!(settings.aggressive_inline());
+/// assert!(settings.source_maps());
+/// ```
 #[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
 pub struct Settings {
     /// If aggressive inlining should be used. This should be disabled for any interactive
-    /// development where values may be redefined.
+    /// development where values may be redefined. Calls to inlined builtins with the wrong number
+    /// of args are reported as compile errors.
     pub enable_aggressive_inline: bool,
     /// If true, debug information will be preserved at the cost of higher RAM usage.
     pub enable_source_maps: bool,
+    /// The number of digits to display after the decimal point when formatting floats. If `None`,
+    /// floats are displayed with as many digits as needed to represent the value.
+    ///
+    /// This only affects how floats are displayed. The underlying values are not rounded.
+    pub float_precision: Option<usize>,
+    /// If true, symbols that are no longer reachable are removed during garbage collection.
+    ///
+    /// Symbols held outside of the VM, like those returned by [crate::Vm::get_or_create_symbol],
+    /// are not tracked and may stop resolving to their string after garbage collection. Removed
+    /// symbol ids are never reused.
+    pub enable_symbol_gc: bool,
+    /// If true, call sites that call a global function cache the function they resolved to. The
+    /// cache is invalidated whenever a global value is redefined.
+    pub enable_inline_cache: bool,
 }
 
 impl Default for Settings {
@@ -13,6 +39,129 @@ impl Default for Settings {
         Settings {
             enable_aggressive_inline: false,
             enable_source_maps: true,
+            float_precision: None,
+            enable_symbol_gc: false,
+            enable_inline_cache: true,
         }
     }
 }
+
+impl Settings {
+    /// Create a builder for settings. Any unspecified settings use the value from
+    /// [Settings::default].
+    pub fn builder() -> SettingsBuilder {
+        SettingsBuilder {
+            settings: Settings::default(),
+        }
+    }
+
+    /// Returns `true` if aggressive inlining is enabled.
+    pub fn aggressive_inline(&self) -> bool {
+        self.enable_aggressive_inline
+    }
+
+    /// Returns `true` if source maps are enabled.
+    pub fn source_maps(&self) -> bool {
+        self.enable_source_maps
+    }
+
+    /// Get the number of digits to display after the decimal point when formatting floats.
+    pub fn float_precision(&self) -> Option<usize> {
+        self.float_precision
+    }
+
+    /// Returns `true` if unreachable symbols are garbage collected.
+    pub fn symbol_gc(&self) -> bool {
+        self.enable_symbol_gc
+    }
+
+    /// Returns `true` if call sites cache the functions they resolve to.
+    pub fn inline_cache(&self) -> bool {
+        self.enable_inline_cache
+    }
+}
+
+/// Builds [Settings]. Created with [Settings::builder].
+#[derive(Copy, Clone, Debug)]
+pub struct SettingsBuilder {
+    settings: Settings,
+}
+
+impl SettingsBuilder {
+    /// Set if aggressive inlining should be used. See [Settings::enable_aggressive_inline].
+    pub fn with_aggressive_inline(mut self, enable: bool) -> SettingsBuilder {
+        self.settings.enable_aggressive_inline = enable;
+        self
+    }
+
+    /// Set if source maps should be preserved. See [Settings::enable_source_maps].
+    pub fn with_source_maps(mut self, enable: bool) -> SettingsBuilder {
+        self.settings.enable_source_maps = enable;
+        self
+    }
+
+    /// Set the float formatting precision. See [Settings::float_precision].
+    pub fn with_float_precision(mut self, precision: Option<usize>) -> SettingsBuilder {
+        self.settings.float_precision = precision;
+        self
+    }
+
+    /// Set if unreachable symbols should be garbage collected. See
+    /// [Settings::enable_symbol_gc].
+    pub fn with_symbol_gc(mut self, enable: bool) -> SettingsBuilder {
+        self.settings.enable_symbol_gc = enable;
+        self
+    }
+
+    /// Set if call sites should cache the functions they resolve to. See
+    /// [Settings::enable_inline_cache].
+    pub fn with_inline_cache(mut self, enable: bool) -> SettingsBuilder {
+        self.settings.enable_inline_cache = enable;
+        self
+    }
+
+    /// Build the settings.
+    pub fn build(self) -> Settings {
+        self.settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_with_no_changes_is_default() {
+        let settings = Settings::builder().build();
+        let default = Settings::default();
+        assert_eq!(settings.aggressive_inline(), default.aggressive_inline());
+        assert_eq!(settings.source_maps(), default.source_maps());
+        assert_eq!(settings.float_precision(), default.float_precision());
+        assert_eq!(settings.symbol_gc(), default.symbol_gc());
+        assert_eq!(settings.inline_cache(), default.inline_cache());
+    }
+
+    #[test]
+    fn builder_sets_specified_fields_only() {
+        let settings = Settings::builder()
+            .with_aggressive_inline(true)
+            .with_float_precision(Some(3))
+            .build();
+        assert!(settings.aggressive_inline());
+        assert_eq!(settings.float_precision(), Some(3));
+        assert!(settings.source_maps());
+
+        let settings = Settings::builder().with_source_maps(false).build();
+        assert!(!settings.aggressive_inline());
+        assert!(!settings.source_maps());
+        assert_eq!(settings.float_precision(), None);
+
+        let settings = Settings::builder().with_symbol_gc(true).build();
+        assert!(settings.symbol_gc());
+        assert!(settings.source_maps());
+
+        let settings = Settings::builder().with_inline_cache(false).build();
+        assert!(!settings.inline_cache());
+        assert!(!settings.symbol_gc());
+    }
+}
@@ -0,0 +1,23 @@
+use compact_str::CompactString;
+
+use crate::Vm;
+
+/// A self-contained copy of the global values of a [Vm]. Created with [Vm::snapshot] and applied
+/// with [Vm::restore].
+#[derive(Debug)]
+pub struct Snapshot {
+    /// Holds deep copies of the captured values as globals.
+    pub(crate) vm: Vm,
+    /// The names of the captured globals.
+    pub(crate) names: Vec<CompactString>,
+    /// The names of the globals that could not be captured.
+    pub(crate) skipped: Vec<CompactString>,
+}
+
+impl Snapshot {
+    /// The names of the globals that were not captured because they are or contain functions or
+    /// custom values. The names are sorted.
+    pub fn skipped(&self) -> &[CompactString] {
+        &self.skipped
+    }
+}
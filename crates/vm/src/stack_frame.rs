@@ -35,6 +35,18 @@ impl StackFrameManager {
         self.current = self.previous.pop().unwrap();
     }
 
+    /// The number of stack frames below the current stack frame.
+    pub fn depth(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// Pop stack frames until there are `depth` stack frames below the current stack frame.
+    pub fn truncate(&mut self, depth: usize) {
+        while self.previous.len() > depth {
+            self.pop();
+        }
+    }
+
     pub fn iter(&self) -> impl '_ + Iterator<Item = &StackFrame> {
         self.previous.iter().chain(std::iter::once(&self.current))
     }
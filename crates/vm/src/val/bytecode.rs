@@ -1,10 +1,18 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use compact_str::CompactString;
 
-use crate::parser::span::Span;
+use crate::{error::CompileError, parser::span::Span};
 
-use super::{NativeFunction, Symbol, UnsafeVal};
+use super::{NativeFunction, Symbol, UnsafeVal, ValId};
 
 /// Contains a set of instructions for the Spore VM to evaluate.
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -51,18 +59,111 @@ impl ByteCode {
                 Instruction::GetArg(_) => None,
                 Instruction::BindArg(_) => None,
                 Instruction::Deref(_) => None,
+                Instruction::DerefCached { .. } => None,
                 Instruction::Define(_) => None,
                 Instruction::Eval(_) => None,
                 Instruction::EvalNative { .. } => None,
                 Instruction::JumpIf(_) => None,
                 Instruction::Jump(_) => None,
+                Instruction::JumpBack(_) => None,
                 Instruction::Return => None,
             })
     }
+
+    /// Get the number of instructions.
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Get the number of instructions of each kind, keyed by [Instruction::name].
+    pub fn instruction_counts(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for instruction in self.instructions.iter() {
+            *counts.entry(instruction.name()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Get a hash of the contents of the bytecode. The hash is cheap to compute and may collide so
+    /// equality should still be checked.
+    ///
+    /// Equal bytecode usually has the same hash. Floats are hashed by their bits so bytecode that
+    /// differs only by `0.0` and `-0.0` is equal but may hash differently. This only means that
+    /// the bytecode is not deduplicated.
+    pub(crate) fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.name.hash(&mut hasher);
+        self.arg_count.hash(&mut hasher);
+        self.local_bindings.hash(&mut hasher);
+        self.instructions.len().hash(&mut hasher);
+        for instruction in self.instructions.iter() {
+            instruction.name().hash(&mut hasher);
+            match instruction {
+                Instruction::PushConst(v) => match v {
+                    UnsafeVal::Int(x) => x.hash(&mut hasher),
+                    UnsafeVal::Float(x) => x.to_bits().hash(&mut hasher),
+                    v => v.type_name().hash(&mut hasher),
+                },
+                Instruction::Deref(symbol)
+                | Instruction::DerefCached { symbol, .. }
+                | Instruction::Define(symbol) => symbol.idx.hash(&mut hasher),
+                Instruction::Pop(n)
+                | Instruction::GetArg(n)
+                | Instruction::BindArg(n)
+                | Instruction::Eval(n)
+                | Instruction::EvalNative { arg_count: n, .. }
+                | Instruction::JumpIf(n)
+                | Instruction::Jump(n)
+                | Instruction::JumpBack(n) => n.hash(&mut hasher),
+                Instruction::PushCurrentFunction | Instruction::Return => {}
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Check that all [Instruction::Jump], [Instruction::JumpIf], and [Instruction::JumpBack]
+    /// instructions land within the bytecode. Landing just past the last instruction is allowed as
+    /// it returns from the bytecode.
+    pub fn validate_jumps(&self) -> Result<(), CompileError> {
+        let len = self.instructions.len();
+        for (idx, instruction) in self.instructions.iter().enumerate() {
+            // Jumps are relative to the instruction after the jump.
+            let (offset, target) = match instruction {
+                Instruction::Jump(n) | Instruction::JumpIf(n) => (*n, (idx + 1).checked_add(*n)),
+                Instruction::JumpBack(n) => (*n, (idx + 1).checked_sub(*n)),
+                _ => continue,
+            };
+            match target {
+                Some(target) if target <= len => {}
+                _ => {
+                    return Err(CompileError::InvalidJump {
+                        instruction_idx: idx,
+                        offset,
+                        instruction_count: len,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterate over all symbols that are referenced by the bytecode's instructions. This does not
+    /// include symbols within [Self::values].
+    pub fn symbols(&self) -> impl '_ + Iterator<Item = Symbol> {
+        self.instructions
+            .iter()
+            .flat_map(|instruction| match instruction {
+                Instruction::Deref(symbol)
+                | Instruction::DerefCached { symbol, .. }
+                | Instruction::Define(symbol) => Some(*symbol),
+                _ => None,
+            })
+    }
 }
 
 /// An instruction for the VM to execute.
 #[derive(Clone, Debug, PartialEq)]
+#[allow(unpredictable_function_pointer_comparisons)]
 pub enum Instruction {
     /// Push a constant onto the stack.
     PushConst(UnsafeVal),
@@ -76,6 +177,12 @@ pub enum Instruction {
     BindArg(usize),
     /// Get the value of a symbol at push it onto the stack.
     Deref(Symbol),
+    /// Similar to [Instruction::Deref] but the resolved function is cached in `cache`. Used for
+    /// call sites that call a global function.
+    DerefCached {
+        symbol: Symbol,
+        cache: Box<InlineCache>,
+    },
     /// Pop the top value of the stack and assign it to the given symbol.
     Define(Symbol),
     /// Pop the top `n` values of the stack. The deepmost value should be function with the rest of
@@ -91,10 +198,96 @@ pub enum Instruction {
     JumpIf(usize),
     /// Jump `n` instructions.
     Jump(usize),
+    /// Jump back `n` instructions, relative to the instruction after this one.
+    JumpBack(usize),
     /// Return from the current function.
     Return,
 }
 
+impl Instruction {
+    /// Get the name of the kind of instruction.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::PushConst(_) => "PushConst",
+            Instruction::PushCurrentFunction => "PushCurrentFunction",
+            Instruction::Pop(_) => "Pop",
+            Instruction::GetArg(_) => "GetArg",
+            Instruction::BindArg(_) => "BindArg",
+            Instruction::Deref(_) => "Deref",
+            Instruction::DerefCached { .. } => "DerefCached",
+            Instruction::Define(_) => "Define",
+            Instruction::Eval(_) => "Eval",
+            Instruction::EvalNative { .. } => "EvalNative",
+            Instruction::JumpIf(_) => "JumpIf",
+            Instruction::Jump(_) => "Jump",
+            Instruction::JumpBack(_) => "JumpBack",
+            Instruction::Return => "Return",
+        }
+    }
+}
+
+/// Caches the bytecode function that an [Instruction::DerefCached] resolved to.
+///
+/// Entries are tagged with the version of the VM's globals at the time they were stored. The VM
+/// increments the version whenever a global is set so any redefinition invalidates all caches.
+/// Caches do not take part in equality so two instructions that only differ by their cache are
+/// equal.
+#[derive(Default)]
+pub struct InlineCache {
+    /// The globals version the cache entry is valid for. `0` is never a valid version.
+    version: AtomicU64,
+    /// The packed id of the cached bytecode function.
+    function: AtomicU64,
+}
+
+impl InlineCache {
+    /// Get the cached function if it was stored for `version`.
+    pub(crate) fn get(&self, version: u64) -> Option<ValId<ByteCode>> {
+        if self.version.load(Ordering::Relaxed) != version {
+            return None;
+        }
+        let packed = self.function.load(Ordering::Relaxed);
+        Some(ValId {
+            vm_id: (packed >> 48) as u16,
+            obj_id: (packed >> 32) as u16,
+            idx: packed as u32,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Cache `function` for `version`.
+    pub(crate) fn set(&self, version: u64, function: ValId<ByteCode>) {
+        let packed = ((function.vm_id as u64) << 48)
+            | ((function.obj_id as u64) << 32)
+            | function.idx as u64;
+        self.function.store(packed, Ordering::Relaxed);
+        self.version.store(version, Ordering::Relaxed);
+    }
+}
+
+impl Clone for InlineCache {
+    fn clone(&self) -> InlineCache {
+        InlineCache {
+            version: AtomicU64::new(self.version.load(Ordering::Relaxed)),
+            function: AtomicU64::new(self.function.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl PartialEq for InlineCache {
+    fn eq(&self, _: &InlineCache) -> bool {
+        true
+    }
+}
+
+impl std::fmt::Debug for InlineCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InlineCache")
+            .field("version", &self.version.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,4 +297,96 @@ mod tests {
     fn struct_sizes_are_small_enough() {
         assert_eq!(size_of::<Instruction>(), 3 * size_of::<usize>());
     }
+
+    #[test]
+    fn inline_cache_returns_function_for_matching_version_only() {
+        let cache = InlineCache::default();
+        assert_eq!(cache.get(1), None);
+        let function = ValId {
+            vm_id: 3,
+            obj_id: 7,
+            idx: 42,
+            _marker: PhantomData,
+        };
+        cache.set(1, function);
+        let got = cache.get(1).unwrap();
+        assert_eq!(
+            (got.vm_id, got.obj_id, got.idx),
+            (function.vm_id, function.obj_id, function.idx)
+        );
+        assert_eq!(cache.get(2), None);
+    }
+
+    #[test]
+    fn instruction_counts_sum_to_instruction_count() {
+        let mut vm = crate::Vm::default();
+        let id = vm.compile("(+ 1 (+ 2 3))").unwrap();
+        let bytecode = vm.bytecode(id).unwrap();
+        assert_eq!(bytecode.instruction_count(), 7);
+        assert_eq!(
+            bytecode.instruction_counts(),
+            HashMap::from([("Deref", 2), ("PushConst", 3), ("Eval", 2)])
+        );
+        assert_eq!(
+            bytecode.instruction_counts().values().sum::<usize>(),
+            bytecode.instruction_count()
+        );
+    }
+
+    #[test]
+    fn validate_jumps_with_jump_past_end_returns_invalid_jump() {
+        let bytecode = ByteCode {
+            instructions: [
+                Instruction::PushConst(true.into()),
+                Instruction::JumpIf(2),
+                Instruction::PushConst(1.into()),
+            ]
+            .into(),
+            ..ByteCode::default()
+        };
+        assert_eq!(
+            bytecode.validate_jumps(),
+            Err(CompileError::InvalidJump {
+                instruction_idx: 1,
+                offset: 2,
+                instruction_count: 3,
+            })
+        );
+        let bytecode = ByteCode {
+            instructions: [Instruction::Jump(usize::MAX)].into(),
+            ..ByteCode::default()
+        };
+        assert!(bytecode.validate_jumps().is_err());
+    }
+
+    #[test]
+    fn validate_jumps_with_jump_to_end_is_ok() {
+        let bytecode = ByteCode {
+            instructions: [
+                Instruction::PushConst(true.into()),
+                Instruction::JumpIf(1),
+                Instruction::PushConst(1.into()),
+            ]
+            .into(),
+            ..ByteCode::default()
+        };
+        assert_eq!(bytecode.validate_jumps(), Ok(()));
+    }
+
+    #[test]
+    fn compiled_bytecode_has_valid_jumps() {
+        let mut vm = crate::Vm::default();
+        for src in [
+            "(if true 1 2)",
+            "(if false 1)",
+            "(or false 1 2)",
+            "(and true 1 false)",
+            "(define (f x) (if (< x 1) (or x false) (and x (f (- x 1)))))",
+            "(if (if true false true) (or) (and))",
+        ] {
+            let id = vm.compile(src).unwrap();
+            assert_eq!(vm.bytecode(id).unwrap().validate_jumps(), Ok(()), "{src}");
+            vm.release_compiled(id);
+        }
+    }
 }
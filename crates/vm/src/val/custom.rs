@@ -20,6 +20,8 @@ pub enum CustomValError {
         expected: &'static str,
         actual: &'static str,
     },
+    #[error("value belongs to a different VM")]
+    ForeignValue,
 }
 
 impl<T> From<TryLockError<T>> for CustomValError {
@@ -72,7 +74,7 @@ impl CustomVal {
     ///
     /// # Panic
     /// Panics if the value if the value is under a [Self::get] or [Self::get_mut].
-    pub fn get_mut<T>(&self) -> Result<CustomValMut<T>, CustomValError>
+    pub fn get_mut<T>(&self) -> Result<CustomValMut<'_, T>, CustomValError>
     where
         T: CustomType,
     {
@@ -93,6 +95,16 @@ impl CustomVal {
     }
 }
 
+impl CustomVal {
+    /// Run the finalizer for the underlying value.
+    pub(crate) fn finalize(&mut self) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner())
+            .finalize();
+    }
+}
+
 impl std::fmt::Display for CustomVal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0.try_read() {
@@ -148,6 +160,7 @@ pub(crate) trait CustomTypeSealed:
     fn name(&self) -> &'static str {
         std::any::type_name_of_val(self)
     }
+    fn finalize(&mut self);
 }
 
 /// A trait that defines a value that can be created or referenced within the VM.
@@ -165,6 +178,12 @@ pub(crate) trait CustomTypeSealed:
 pub trait CustomType:
     'static + Send + Sync + std::fmt::Display + std::fmt::Debug + std::any::Any
 {
+    /// Called when the value is garbage collected. Useful for releasing resources like file
+    /// handles.
+    ///
+    /// Finalizers run during garbage collection so they should be quick and must not attempt to
+    /// create or access [crate::Vm] values.
+    fn finalize(&mut self) {}
 }
 
 impl<T> CustomTypeSealed for T
@@ -182,6 +201,10 @@ where
     fn name(&self) -> &'static str {
         std::any::type_name::<T>()
     }
+
+    fn finalize(&mut self) {
+        CustomType::finalize(self)
+    }
 }
 
 #[cfg(test)]
@@ -401,4 +424,39 @@ mod tests {
         other_type.as_any_mut();
         other_type.to_string();
     }
+
+    #[derive(Debug)]
+    struct Resource {
+        finalized: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl CustomType for Resource {
+        fn finalize(&mut self) {
+            self.finalized
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    impl std::fmt::Display for Resource {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "resource")
+        }
+    }
+
+    #[test]
+    fn finalize_is_called_when_custom_value_is_garbage_collected() {
+        let finalized = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut vm = Vm::default().with_custom_value(
+            "resource",
+            Resource {
+                finalized: finalized.clone(),
+            },
+        );
+        unsafe { vm.run_gc() };
+        assert!(!finalized.load(std::sync::atomic::Ordering::Relaxed));
+
+        vm.eval_str("(define resource 0)").unwrap();
+        unsafe { vm.run_gc() };
+        assert!(finalized.load(std::sync::atomic::Ordering::Relaxed));
+    }
 }
@@ -2,32 +2,90 @@ use crate::Vm;
 
 use super::UnsafeVal;
 
+/// Options that control how values are formatted.
+///
+/// ```rust
+/// let mut vm = spore_vm::Vm::default();
+/// let options = spore_vm::val::FormatOptions {
+///     max_length: Some(3),
+///     ..Default::default()
+/// };
+/// let list = vm.eval_str("(list 1 2 3 4 5)").unwrap();
+/// assert_eq!(list.formatted_with(list.vm(), options).to_string(), "(1 2 3 ...)");
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FormatOptions {
+    /// If true, strings are printed in quotes and symbols are printed with a leading `'`.
+    pub quote_strings: bool,
+    /// The maximum depth of nested containers to display. Containers that are nested deeper are
+    /// elided, for example lists as `(...)`, structs as `(struct ...)`, and boxes as `box<...>`.
+    /// If `None`, all levels are displayed.
+    pub max_depth: Option<usize>,
+    /// The maximum number of elements to display for a list or the number of fields for a struct.
+    /// The remaining elements are elided with `...`. If `None`, all elements are displayed.
+    pub max_length: Option<usize>,
+}
+
 /// Formats a value for display.
 pub struct ValFormatter<'a> {
     vm: &'a Vm,
     val: UnsafeVal,
-    quote_strings: bool,
+    options: FormatOptions,
+    depth: usize,
 }
 
 impl<'a> ValFormatter<'a> {
     /// Create a new value formatter that implements display.
-    pub fn new(vm: &'a Vm, v: UnsafeVal) -> ValFormatter {
-        ValFormatter {
-            vm,
-            val: v,
-            quote_strings: false,
-        }
+    pub fn new(vm: &'a Vm, v: UnsafeVal) -> ValFormatter<'a> {
+        ValFormatter::new_with_options(vm, v, FormatOptions::default())
     }
 
     /// Create a new value formatter that implements display. Strings are printed in quotes. For
     /// example, a string containing the string test-string will print to "test-string".
-    pub fn new_quoted(vm: &'a Vm, v: UnsafeVal) -> ValFormatter {
+    pub fn new_quoted(vm: &'a Vm, v: UnsafeVal) -> ValFormatter<'a> {
+        let options = FormatOptions {
+            quote_strings: true,
+            ..FormatOptions::default()
+        };
+        ValFormatter::new_with_options(vm, v, options)
+    }
+
+    /// Create a new value formatter that implements display according to `options`.
+    pub fn new_with_options(vm: &'a Vm, v: UnsafeVal, options: FormatOptions) -> ValFormatter<'a> {
         ValFormatter {
             vm,
             val: v,
-            quote_strings: true,
+            options,
+            depth: 0,
         }
     }
+
+    /// Create a formatter for a value contained within `self.val`.
+    fn child(&self, val: UnsafeVal) -> ValFormatter<'a> {
+        ValFormatter {
+            vm: self.vm,
+            val,
+            options: FormatOptions {
+                quote_strings: true,
+                ..self.options
+            },
+            depth: self.depth + 1,
+        }
+    }
+
+    /// Returns `true` if nested values are too deep to be displayed.
+    fn exceeds_max_depth(&self) -> bool {
+        self.options
+            .max_depth
+            .is_some_and(|max_depth| self.depth >= max_depth)
+    }
+
+    /// Returns `true` if the element at `idx` should be elided.
+    fn exceeds_max_length(&self, idx: usize) -> bool {
+        self.options
+            .max_length
+            .is_some_and(|max_length| idx >= max_length)
+    }
 }
 
 impl<'a> std::fmt::Display for ValFormatter<'a> {
@@ -36,41 +94,91 @@ impl<'a> std::fmt::Display for ValFormatter<'a> {
             UnsafeVal::Void => write!(f, "<void>"),
             UnsafeVal::Bool(x) => write!(f, "{x}"),
             UnsafeVal::Int(x) => write!(f, "{x}"),
-            UnsafeVal::Float(x) => write!(f, "{x}"),
+            UnsafeVal::Float(x) => match self.vm.settings.float_precision {
+                Some(precision) => write!(f, "{x:.precision$}"),
+                // Whole floats end with `.0` to distinguish them from ints.
+                None if x.is_finite() && x.fract() == 0.0 => write!(f, "{x:.1}"),
+                None => write!(f, "{x}"),
+            },
             UnsafeVal::Symbol(x) => {
                 let name = self.vm.symbol_to_str(*x).unwrap_or("*corrupt-symbol*");
-                write!(f, "'{name}")
+                if self.options.quote_strings {
+                    write!(f, "'{name}")
+                } else {
+                    write!(f, "{name}")
+                }
             }
             UnsafeVal::String(x) => {
-                if self.quote_strings {
+                if self.options.quote_strings {
                     write!(f, "{:?}", self.vm.objects.get_str(*x))
                 } else {
                     write!(f, "{}", self.vm.objects.get_str(*x))
                 }
             }
+            UnsafeVal::MutableBox(_) if self.exceeds_max_depth() => write!(f, "box<...>"),
             UnsafeVal::MutableBox(x) => {
-                let inner = ValFormatter {
-                    vm: self.vm,
-                    val: *self.vm.objects.get_mutable_box(*x),
-                    quote_strings: true,
-                };
+                let inner = self.child(*self.vm.objects.get_mutable_box(*x));
                 write!(f, "box<{}>", inner)
             }
+            UnsafeVal::List(_) if self.exceeds_max_depth() => write!(f, "(...)"),
             UnsafeVal::List(x) => {
                 write!(f, "(")?;
                 for (idx, val) in self.vm.objects.get_list(*x).iter().enumerate() {
-                    if idx == 0 {
-                        write!(f, "{}", val.format_quoted(self.vm))?;
-                    } else {
-                        write!(f, " {}", val.format_quoted(self.vm))?;
+                    if idx > 0 {
+                        write!(f, " ")?;
+                    }
+                    if self.exceeds_max_length(idx) {
+                        write!(f, "...")?;
+                        break;
+                    }
+                    write!(f, "{}", self.child(*val))?;
+                }
+                write!(f, ")")
+            }
+            UnsafeVal::Vector(_) if self.exceeds_max_depth() => write!(f, "(vector ...)"),
+            UnsafeVal::Vector(x) => {
+                write!(f, "(vector")?;
+                for (idx, val) in self.vm.objects.get_vector(*x).iter().enumerate() {
+                    if self.exceeds_max_length(idx) {
+                        write!(f, " ...")?;
+                        break;
+                    }
+                    write!(f, " {}", self.child(*val))?;
+                }
+                write!(f, ")")
+            }
+            UnsafeVal::Array(_) if self.exceeds_max_depth() => write!(f, "(array ...)"),
+            UnsafeVal::Array(x) => {
+                write!(f, "(array")?;
+                for (idx, val) in self.vm.objects.get_array(*x).iter().enumerate() {
+                    if self.exceeds_max_length(idx) {
+                        write!(f, " ...")?;
+                        break;
                     }
+                    write!(f, " {}", self.child(*val))?;
                 }
                 write!(f, ")")
             }
+            UnsafeVal::Bytes(x) => {
+                write!(f, "(bytes")?;
+                for (idx, b) in self.vm.objects.get_bytes(*x).iter().enumerate() {
+                    if self.exceeds_max_length(idx) {
+                        write!(f, " ...")?;
+                        break;
+                    }
+                    write!(f, " {b}")?;
+                }
+                write!(f, ")")
+            }
+            UnsafeVal::Struct(_) if self.exceeds_max_depth() => write!(f, "(struct ...)"),
             UnsafeVal::Struct(x) => {
                 write!(f, "(struct")?;
-                for (name, val) in self.vm.objects.get_struct(*x).iter() {
-                    let val = val.format_quoted(self.vm);
+                for (idx, (name, val)) in self.vm.objects.get_struct(*x).iter().enumerate() {
+                    if self.exceeds_max_length(idx) {
+                        write!(f, " ...")?;
+                        break;
+                    }
+                    let val = self.child(val);
                     let name = self
                         .vm
                         .symbol_to_str(name)
@@ -102,6 +210,8 @@ impl<'a> std::fmt::Display for ValFormatter<'a> {
 
 #[cfg(test)]
 mod tests {
+    use crate::Settings;
+
     use super::*;
 
     #[test]
@@ -140,7 +250,15 @@ mod tests {
     fn format_float_prints_number() {
         assert_eq!(
             UnsafeVal::from(0.0).formatted(&Vm::default()).to_string(),
-            "0"
+            "0.0"
+        );
+        assert_eq!(
+            UnsafeVal::from(2.0).formatted(&Vm::default()).to_string(),
+            "2.0"
+        );
+        assert_eq!(
+            UnsafeVal::from(-3.0).formatted(&Vm::default()).to_string(),
+            "-3.0"
         );
         assert_eq!(
             UnsafeVal::from(-1.5).formatted(&Vm::default()).to_string(),
@@ -148,6 +266,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn format_float_with_precision_rounds_displayed_number() {
+        let mut vm = Vm::new(Settings {
+            float_precision: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(
+            UnsafeVal::from(1.0 / 3.0).formatted(&vm).to_string(),
+            "0.33"
+        );
+        assert_eq!(
+            vm.eval_str("(list 0.3333333 2.0)").unwrap().to_string(),
+            "(0.33 2.00)"
+        );
+        assert!(!vm
+            .eval_str("(= 0.3333333 0.33)")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+        assert!(vm
+            .eval_str("(= 0.3333333 0.3333333)")
+            .unwrap()
+            .try_bool()
+            .unwrap());
+    }
+
     #[test]
     fn format_string_returns_string() {
         let mut vm = Vm::default();
@@ -194,4 +338,76 @@ mod tests {
         let v = vm.eval_str("+").unwrap();
         assert_eq!(v.to_string(), "<native-function>");
     }
+
+    #[test]
+    fn format_with_max_length_elides_remaining_elements() {
+        let mut vm = Vm::default();
+        let options = FormatOptions {
+            max_length: Some(3),
+            ..Default::default()
+        };
+        let list = (1..=1000).map(UnsafeVal::Int).collect();
+        let v = UnsafeVal::List(vm.objects.insert_list(list));
+        assert_eq!(
+            ValFormatter::new_with_options(&vm, v, options).to_string(),
+            "(1 2 3 ...)"
+        );
+    }
+
+    #[test]
+    fn format_with_max_length_elides_struct_fields() {
+        let mut vm = Vm::default();
+        let options = FormatOptions {
+            max_length: Some(0),
+            ..Default::default()
+        };
+        let v = vm.eval_str("(struct 'field 1)").unwrap();
+        assert_eq!(
+            v.formatted_with(v.vm(), options).to_string(),
+            "(struct ...)"
+        );
+    }
+
+    #[test]
+    fn format_with_max_depth_truncates_nested_values() {
+        let mut vm = Vm::default();
+        let options = FormatOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let v = vm
+            .eval_str("(list 1 (list 2 (list 3 (list 4))) (struct 'field (struct 'inner 5)))")
+            .unwrap();
+        assert_eq!(
+            v.formatted_with(v.vm(), options).to_string(),
+            "(1 (2 (...)) (struct 'field (struct ...)))"
+        );
+    }
+
+    #[test]
+    fn format_with_max_depth_truncates_self_referential_box() {
+        let mut vm = Vm::default();
+        let options = FormatOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let v = vm
+            .eval_str("(define b (new-box 0)) (set-box! b b) b")
+            .unwrap();
+        assert_eq!(
+            v.formatted_with(v.vm(), options).to_string(),
+            "box<box<box<...>>>"
+        );
+    }
+
+    #[test]
+    fn format_with_default_options_is_unbounded() {
+        let mut vm = Vm::default();
+        let v = vm.eval_str("(list 1 (list 2 (list 3)) \"s\")").unwrap();
+        assert_eq!(
+            v.formatted_with(v.vm(), FormatOptions::default())
+                .to_string(),
+            v.formatted(v.vm()).to_string()
+        );
+    }
 }
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use compact_str::CompactString;
+
+use crate::Vm;
+
+use super::{ListVal, StructVal, UnsafeVal, Val};
+
+/// Converts a Rust value into a Spore value. Used by [super::ValBuilder::from_rust] to return Rust
+/// values from native functions.
+///
+/// | Rust                 | Spore                 |
+/// |----------------------|-----------------------|
+/// | `()`                 | void                  |
+/// | `bool`               | bool                  |
+/// | `i64`                | int                   |
+/// | `f64`                | float                 |
+/// | `String`, `&str`     | string                |
+/// | `Option<T>`          | `T` or void if `None` |
+/// | `Vec<T>`             | list                  |
+/// | `HashMap<String, T>` | struct                |
+pub trait IntoVal {
+    /// Insert `self` into `vm` and return the new value.
+    ///
+    /// The returned value is not protected from the garbage collector so it should be returned to
+    /// the VM before the garbage collector has a chance to run.
+    fn into_unsafe_val(self, vm: &mut Vm) -> UnsafeVal;
+}
+
+macro_rules! into_val_impl {
+    ($rust_type:ty) => {
+        impl IntoVal for $rust_type {
+            fn into_unsafe_val(self, _: &mut Vm) -> UnsafeVal {
+                self.into()
+            }
+        }
+    };
+}
+
+into_val_impl!(());
+into_val_impl!(bool);
+into_val_impl!(i64);
+into_val_impl!(f64);
+
+impl IntoVal for &str {
+    fn into_unsafe_val(self, vm: &mut Vm) -> UnsafeVal {
+        vm.objects.insert_string(self.into()).into()
+    }
+}
+
+impl IntoVal for String {
+    fn into_unsafe_val(self, vm: &mut Vm) -> UnsafeVal {
+        vm.objects.insert_string(CompactString::from(self)).into()
+    }
+}
+
+impl<T: IntoVal> IntoVal for Option<T> {
+    fn into_unsafe_val(self, vm: &mut Vm) -> UnsafeVal {
+        match self {
+            Some(v) => v.into_unsafe_val(vm),
+            None => UnsafeVal::Void,
+        }
+    }
+}
+
+impl<T: IntoVal> IntoVal for Vec<T> {
+    fn into_unsafe_val(self, vm: &mut Vm) -> UnsafeVal {
+        // Garbage collection does not run while the elements are inserted so they remain valid.
+        let list: ListVal = self.into_iter().map(|v| v.into_unsafe_val(vm)).collect();
+        vm.objects.insert_list(list).into()
+    }
+}
+
+impl<T: IntoVal> IntoVal for HashMap<String, T> {
+    fn into_unsafe_val(self, vm: &mut Vm) -> UnsafeVal {
+        let mut strct = StructVal::with_capacity(self.len());
+        for (field, v) in self {
+            let field = vm.get_or_create_symbol(&field);
+            let v = v.into_unsafe_val(vm);
+            // Unsafe OK: Garbage collection does not run until the struct is returned to the VM.
+            strct.set(field, unsafe { Val::from_unsafe_val(v) });
+        }
+        vm.objects.insert_struct(strct).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::VmResult,
+        val::{NativeFunctionContext, ValBuilder},
+    };
+
+    use super::*;
+
+    #[test]
+    fn vec_of_ints_converts_to_list() {
+        fn numbers(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+            Ok(ValBuilder::from_rust(ctx, vec![1i64, 2, 3]))
+        }
+        let mut vm = Vm::default().with_native_function("numbers", numbers);
+        assert_eq!(vm.eval_str("(numbers)").unwrap().to_string(), "(1 2 3)");
+        assert_eq!(
+            vm.eval_str("(list-length (numbers))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn string_converts_to_string() {
+        fn greeting(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+            Ok(ValBuilder::from_rust(ctx, String::from("hello")))
+        }
+        let mut vm = Vm::default().with_native_function("greeting", greeting);
+        assert_eq!(
+            vm.eval_str("(greeting)").unwrap().try_str().unwrap(),
+            "hello"
+        );
+        assert_eq!(
+            vm.eval_str("(type-of (greeting))").unwrap().to_string(),
+            "'string"
+        );
+    }
+
+    #[test]
+    fn nested_collections_convert_to_nested_values() {
+        fn nested(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+            let value = HashMap::from([
+                ("names".to_string(), vec![Some("a"), None]),
+                ("empty".to_string(), vec![]),
+            ]);
+            Ok(ValBuilder::from_rust(ctx, value))
+        }
+        let mut vm = Vm::default().with_native_function("nested", nested);
+        assert_eq!(
+            vm.eval_str("(struct-get (nested) 'names)")
+                .unwrap()
+                .to_string(),
+            "(\"a\" <void>)"
+        );
+        assert_eq!(
+            vm.eval_str("(struct-get (nested) 'empty)")
+                .unwrap()
+                .to_string(),
+            "()"
+        );
+    }
+
+    #[test]
+    fn converted_values_are_garbage_collected() {
+        fn strings(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+            Ok(ValBuilder::from_rust(ctx, vec![String::from("a")]))
+        }
+        let mut vm = Vm::default().with_native_function("strings", strings);
+        vm.eval_str("(define kept (strings))").unwrap();
+        let temp = vm.eval_str("(strings)").unwrap().as_unsafe_val();
+        vm.collect_garbage();
+        assert!(!vm.objects.is_alive(temp));
+        assert_eq!(vm.eval_str("kept").unwrap().to_string(), "(\"a\")");
+    }
+}
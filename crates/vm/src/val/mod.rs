@@ -4,20 +4,24 @@ mod bytecode;
 pub(crate) mod custom;
 mod formatter;
 mod id;
+mod into_val;
 mod native_function;
 mod protected_val;
+mod root_guard;
 mod struct_val;
 mod symbol;
 mod unsafe_val;
 
 use std::marker::PhantomData;
 
-pub use bytecode::{ByteCode, Instruction};
+pub use bytecode::{ByteCode, InlineCache, Instruction};
 pub use custom::{CustomType, CustomVal, CustomValError, CustomValMut, CustomValRef};
-pub use formatter::ValFormatter;
+pub use formatter::{FormatOptions, ValFormatter};
 pub use id::ValId;
-pub use native_function::{NativeFunction, NativeFunctionContext, ValBuilder};
-pub use protected_val::ProtectedVal;
+pub use into_val::IntoVal;
+pub use native_function::{FromVal, NativeFunction, NativeFunctionContext, ValBuilder};
+pub use protected_val::{ProtectedVal, ProtectedValHandle};
+pub use root_guard::RootGuard;
 pub use struct_val::StructVal;
 pub use symbol::Symbol;
 pub use unsafe_val::UnsafeVal;
@@ -27,6 +31,37 @@ use crate::Vm;
 /// A container for a list.
 pub type ListVal = Vec<UnsafeVal>;
 
+/// A container for a vector. Unlike [ListVal], a vector's length is fixed when it is created.
+pub type VectorVal = Box<[UnsafeVal]>;
+
+/// A container for binary data. Like [VectorVal], the length is fixed when it is created.
+pub type BytesVal = Box<[u8]>;
+
+/// A container for an array. Like [VectorVal], an array's length is fixed when it is created but
+/// its elements may be replaced.
+#[derive(Clone, Debug, Default)]
+pub struct ArrayVal(Box<[UnsafeVal]>);
+
+impl From<Vec<UnsafeVal>> for ArrayVal {
+    fn from(elements: Vec<UnsafeVal>) -> ArrayVal {
+        ArrayVal(elements.into_boxed_slice())
+    }
+}
+
+impl std::ops::Deref for ArrayVal {
+    type Target = [UnsafeVal];
+
+    fn deref(&self) -> &[UnsafeVal] {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for ArrayVal {
+    fn deref_mut(&mut self) -> &mut [UnsafeVal] {
+        &mut self.0
+    }
+}
+
 /// Contains a [Val] from the [Vm].
 #[repr(transparent)]
 #[derive(Copy, Clone, Default)]
@@ -112,17 +147,19 @@ impl<'a> Val<'a> {
     }
 
     /// Get the underlying [&str] or `Err<Val>` if `self` is not a string.
+    ///
+    /// Accessors that take a [Vm] return `Err<Val>` if `self` was created by a different [Vm].
     pub fn try_str(self, vm: &Vm) -> Result<&str, Self> {
         match self.inner {
-            UnsafeVal::String(id) => Ok(vm.objects.get_str(id)),
+            UnsafeVal::String(id) if vm.objects.owns(id) => Ok(vm.objects.get_str(id)),
             _ => Err(self),
         }
     }
 
     /// Get the underlying list or `Err<Val>` if `self` is not a list.
-    pub fn try_list(self, vm: &Vm) -> Result<&[Val], Val<'a>> {
+    pub fn try_list(self, vm: &Vm) -> Result<&[Val<'_>], Val<'a>> {
         match self.inner {
-            UnsafeVal::List(id) => {
+            UnsafeVal::List(id) if vm.objects.owns(id) => {
                 let list = vm.objects.get_list(id);
                 // The VM is borrowed so it is ensured to not garbage collect.
                 Ok(unsafe { Val::from_unsafe_val_slice(list.as_slice()) })
@@ -131,6 +168,38 @@ impl<'a> Val<'a> {
         }
     }
 
+    /// Get the underlying vector or `Err<Val>` if `self` is not a vector.
+    pub fn try_vector(self, vm: &Vm) -> Result<&[Val<'_>], Val<'a>> {
+        match self.inner {
+            UnsafeVal::Vector(id) if vm.objects.owns(id) => {
+                let vector = vm.objects.get_vector(id);
+                // The VM is borrowed so it is ensured to not garbage collect.
+                Ok(unsafe { Val::from_unsafe_val_slice(vector) })
+            }
+            _ => Err(self),
+        }
+    }
+
+    /// Get the underlying array or `Err<Val>` if `self` is not an array.
+    pub fn try_array(self, vm: &Vm) -> Result<&[Val<'_>], Val<'a>> {
+        match self.inner {
+            UnsafeVal::Array(id) if vm.objects.owns(id) => {
+                let array = vm.objects.get_array(id);
+                // The VM is borrowed so it is ensured to not garbage collect.
+                Ok(unsafe { Val::from_unsafe_val_slice(array) })
+            }
+            _ => Err(self),
+        }
+    }
+
+    /// Get the underlying bytes or `Err<Val>` if `self` is not bytes.
+    pub fn try_bytes(self, vm: &Vm) -> Result<&[u8], Val<'a>> {
+        match self.inner {
+            UnsafeVal::Bytes(id) if vm.objects.owns(id) => Ok(vm.objects.get_bytes(id)),
+            _ => Err(self),
+        }
+    }
+
     /// Returns `true` if `self` is a struct.
     pub fn is_struct(self) -> bool {
         matches!(self.inner, UnsafeVal::Struct(_))
@@ -139,7 +208,7 @@ impl<'a> Val<'a> {
     /// Get the underlying struct or `Err<Val>` if `self` is not a struct.
     pub fn try_struct(self, vm: &Vm) -> Result<&StructVal, Val<'a>> {
         match self.inner {
-            UnsafeVal::Struct(id) => {
+            UnsafeVal::Struct(id) if vm.objects.owns(id) => {
                 let strct = vm.objects.get_struct(id);
                 // The VM is borrowed so it is ensured to not garbage collect.
                 Ok(strct)
@@ -151,7 +220,7 @@ impl<'a> Val<'a> {
     /// Get the underlying struct or `Err<Val>` if `self` is not a struct.
     pub fn try_struct_get(self, vm: &'a Vm, name: &str) -> Result<Option<Val<'a>>, Val<'a>> {
         match self.inner {
-            UnsafeVal::Struct(id) => {
+            UnsafeVal::Struct(id) if vm.objects.owns(id) => {
                 let strct = vm.objects.get_struct(id);
                 let sym = vm.get_symbol(name).ok_or(self)?;
                 let maybe_v = strct.get(sym).map(|v| unsafe { Val::from_unsafe_val(v) });
@@ -167,7 +236,7 @@ impl<'a> Val<'a> {
     /// Provides raw access to the struct val.
     pub unsafe fn try_unsafe_struct_mut(self, vm: &mut Vm) -> Result<&mut StructVal, Val<'a>> {
         match self.inner {
-            UnsafeVal::Struct(id) => {
+            UnsafeVal::Struct(id) if vm.objects.owns(id) => {
                 let strct = vm.objects.get_struct_mut(id);
                 Ok(strct)
             }
@@ -177,9 +246,9 @@ impl<'a> Val<'a> {
 
     /// Get the [Val] that the mutable box is pointing to or `Err<Val>` if `self` is not a mutable
     /// box.
-    pub fn try_mutable_box_ref(self, vm: &Vm) -> Result<Val, Val<'a>> {
+    pub fn try_mutable_box_ref(self, vm: &Vm) -> Result<Val<'_>, Val<'a>> {
         match self.inner {
-            UnsafeVal::MutableBox(id) => {
+            UnsafeVal::MutableBox(id) if vm.objects.owns(id) => {
                 let mutable_box = vm.objects.get_mutable_box(id);
                 // The VM is borrowed so it is ensured to not garbage collect.
                 Ok(unsafe { Val::from_unsafe_val(*mutable_box) })
@@ -205,6 +274,11 @@ impl<'a> Val<'a> {
         self.inner.format_quoted(vm)
     }
 
+    /// Get a formatter for the underlying type that formats according to `options`.
+    pub fn formatted_with(self, vm: &Vm, options: FormatOptions) -> impl '_ + std::fmt::Display {
+        ValFormatter::new_with_options(vm, self.inner, options)
+    }
+
     /// Returns `true` if a custom value is held.
     pub fn is_custom(&self) -> bool {
         matches!(self.inner, UnsafeVal::Custom(_))
@@ -212,8 +286,12 @@ impl<'a> Val<'a> {
 
     /// Returns the value as a custom type of `T` or [None] if [Self] is not of the given custom
     /// value.
-    pub fn try_custom<T: CustomType>(&self, vm: &'a Vm) -> Result<CustomValRef<T>, CustomValError> {
+    pub fn try_custom<T: CustomType>(
+        &self,
+        vm: &'a Vm,
+    ) -> Result<CustomValRef<'a, T>, CustomValError> {
         match self.inner {
+            UnsafeVal::Custom(id) if !vm.objects.owns(id) => Err(CustomValError::ForeignValue),
             UnsafeVal::Custom(id) => vm.objects.get_custom(id).get(),
             _ => Err(CustomValError::WrongType {
                 expected: std::any::type_name::<T>(),
@@ -227,8 +305,9 @@ impl<'a> Val<'a> {
     pub fn try_custom_mut<T: CustomType>(
         &self,
         vm: &'a Vm,
-    ) -> Result<CustomValMut<T>, CustomValError> {
+    ) -> Result<CustomValMut<'a, T>, CustomValError> {
         match self.inner {
+            UnsafeVal::Custom(id) if !vm.objects.owns(id) => Err(CustomValError::ForeignValue),
             UnsafeVal::Custom(id) => vm.objects.get_custom(id).get_mut(),
             _ => Err(CustomValError::WrongType {
                 expected: std::any::type_name::<T>(),
@@ -1,8 +1,14 @@
 use compact_str::CompactString;
 
-use crate::{error::VmResult, Vm};
+use crate::{
+    error::{VmError, VmResult},
+    Vm,
+};
 
-use super::{custom::CustomVal, CustomType, ListVal, StructVal, UnsafeVal, Val};
+use super::{
+    custom::CustomVal, ArrayVal, BytesVal, CustomType, IntoVal, ListVal, StructVal, UnsafeVal, Val,
+    VectorVal,
+};
 
 /// A function that can be executed by the Spore VM. Native functions can be registered with
 /// [Vm::with_native_function].
@@ -49,6 +55,29 @@ impl ValBuilder<'static> {
     }
 }
 
+impl<'a> ValBuilder<'a> {
+    /// Create a `ValBuilder` by converting a Rust value with [IntoVal].
+    ///
+    /// Consumes the context to ensure that the value isn't garbage collected.
+    ///
+    /// ```rust
+    /// fn squares(
+    ///     ctx: spore_vm::val::NativeFunctionContext,
+    /// ) -> spore_vm::error::VmResult<spore_vm::val::ValBuilder> {
+    ///     let squares: Vec<i64> = (1..4).map(|x| x * x).collect();
+    ///     Ok(spore_vm::val::ValBuilder::from_rust(ctx, squares))
+    /// }
+    /// ```
+    pub fn from_rust(ctx: NativeFunctionContext<'a>, value: impl IntoVal) -> ValBuilder<'a> {
+        let val = value.into_unsafe_val(ctx.vm);
+        ValBuilder {
+            // Unsafe OK: The value was just created so it does not have a chance to garbage
+            // collect.
+            val: unsafe { Val::from_unsafe_val(val) },
+        }
+    }
+}
+
 impl From<Val<'static>> for ValBuilder<'static> {
     fn from(val: Val) -> ValBuilder {
         ValBuilder { val }
@@ -72,7 +101,7 @@ pub struct NativeFunctionContext<'a> {
 impl<'a> NativeFunctionContext<'a> {
     /// # Safety
     /// - Stack start must be less than or equal to the Vm's stack length.
-    pub(crate) fn new(vm: &mut Vm) -> NativeFunctionContext {
+    pub(crate) fn new(vm: &mut Vm) -> NativeFunctionContext<'_> {
         NativeFunctionContext { vm }
     }
 
@@ -89,8 +118,9 @@ impl<'a> NativeFunctionContext<'a> {
         self.vm
     }
 
-    /// Get the `nth` argument for the function call.
-    pub fn arg(&self, nth: usize) -> Option<Val> {
+    /// Get the `nth` argument for the function call. The argument remains valid until the native
+    /// function returns.
+    pub fn arg(&self, nth: usize) -> Option<Val<'a>> {
         self.vm
             .stack
             .get(nth + self.vm.stack_frames.current.stack_start)
@@ -98,7 +128,7 @@ impl<'a> NativeFunctionContext<'a> {
     }
 
     /// Iterate through all the arguments.
-    pub fn args(&self) -> impl '_ + Iterator<Item = Val> {
+    pub fn args(&self) -> impl '_ + Iterator<Item = Val<'_>> {
         let args = &self.vm.stack[self.vm.stack_frames.current.stack_start..];
         args.iter().map(|v| unsafe { Val::from_unsafe_val(*v) })
     }
@@ -107,6 +137,196 @@ impl<'a> NativeFunctionContext<'a> {
     pub fn arg_count(&self) -> usize {
         self.vm.stack.len() - self.vm.stack_frames.current.stack_start
     }
+
+    /// Get the `nth` argument converted to `T` with [FromVal]. Returns an error naming the missing
+    /// argument index if there is no `nth` argument or a type error if the argument can not be
+    /// converted.
+    ///
+    /// ```rust
+    /// fn add_one(
+    ///     ctx: spore_vm::val::NativeFunctionContext,
+    /// ) -> spore_vm::error::VmResult<spore_vm::val::ValBuilder> {
+    ///     let n: i64 = ctx.arg_as(0)?;
+    ///     Ok(spore_vm::val::ValBuilder::new((n + 1).into()))
+    /// }
+    /// ```
+    pub fn arg_as<T: FromVal>(&self, nth: usize) -> VmResult<T> {
+        match self.arg(nth) {
+            Some(v) => T::from_val(self.vm, v),
+            None => Err(self.missing_arg_error(nth, "native function")),
+        }
+    }
+
+    /// Same as [Self::arg_as] but errors report `context`, usually the function name, instead of a
+    /// generic context.
+    pub fn arg_as_with_context<T: FromVal>(
+        &self,
+        nth: usize,
+        context: &'static str,
+    ) -> VmResult<T> {
+        let v = match self.arg(nth) {
+            Some(v) => v,
+            None => return Err(self.missing_arg_error(nth, context)),
+        };
+        match T::from_val(self.vm, v) {
+            Err(VmError::TypeError {
+                src,
+                expected,
+                actual,
+                value,
+                ..
+            }) => Err(VmError::TypeError {
+                src,
+                context,
+                expected,
+                actual,
+                value,
+            }),
+            res => res,
+        }
+    }
+
+    fn missing_arg_error(&self, nth: usize, context: &str) -> VmError {
+        VmError::CustomError {
+            src: None,
+            message: format!(
+                "{context} arg(idx = {nth}) is missing, got {count} args",
+                count = self.arg_count()
+            ),
+        }
+    }
+}
+
+/// Converts a Spore value into a Rust value. Used by [NativeFunctionContext::arg_as] to extract
+/// arguments within native functions.
+///
+/// | Spore  | Rust     |
+/// |--------|----------|
+/// | int    | `i64`    |
+/// | float  | `f64`    |
+/// | bool   | `bool`   |
+/// | string | `String` |
+/// | list   | `Vec<T>` |
+pub trait FromVal: Sized {
+    /// Convert `val` to `Self` or return a [VmError::TypeError] if `val` has the wrong type.
+    fn from_val(vm: &Vm, val: Val) -> VmResult<Self>;
+}
+
+impl FromVal for i64 {
+    fn from_val(vm: &Vm, val: Val) -> VmResult<i64> {
+        val.try_int()
+            .map_err(|v| arg_type_error(vm, UnsafeVal::INT_TYPE_NAME, v))
+    }
+}
+
+impl FromVal for f64 {
+    fn from_val(vm: &Vm, val: Val) -> VmResult<f64> {
+        val.try_float()
+            .map_err(|v| arg_type_error(vm, UnsafeVal::FLOAT_TYPE_NAME, v))
+    }
+}
+
+impl FromVal for bool {
+    fn from_val(vm: &Vm, val: Val) -> VmResult<bool> {
+        val.try_bool()
+            .map_err(|v| arg_type_error(vm, UnsafeVal::BOOL_TYPE_NAME, v))
+    }
+}
+
+impl FromVal for String {
+    fn from_val(vm: &Vm, val: Val) -> VmResult<String> {
+        val.try_str(vm)
+            .map(String::from)
+            .map_err(|v| arg_type_error(vm, UnsafeVal::STRING_TYPE_NAME, v))
+    }
+}
+
+impl<T: FromVal> FromVal for Vec<T> {
+    fn from_val(vm: &Vm, val: Val) -> VmResult<Vec<T>> {
+        let list = val
+            .try_list(vm)
+            .map_err(|v| arg_type_error(vm, UnsafeVal::LIST_TYPE_NAME, v))?;
+        list.iter().map(|v| T::from_val(vm, *v)).collect()
+    }
+}
+
+/// Define a [NativeFunction] from a Rust signature. The generated function returns a
+/// [VmError::ArityError] if it is not called with exactly the declared number of args and a
+/// [VmError::TypeError] if an arg can not be converted with [FromVal]. The result is converted with
+/// [super::IntoVal].
+///
+/// ```rust
+/// spore_vm::native_fn!(add2(a: i64, b: i64) -> i64 { a + b });
+///
+/// let mut vm = spore_vm::Vm::default().with_native_function("add2", add2);
+/// assert_eq!(vm.eval_str("(add2 1 2)").unwrap().try_int().unwrap(), 3);
+/// assert!(vm.eval_str("(add2 1)").is_err());
+/// assert!(vm.eval_str("(add2 1 \"2\")").is_err());
+/// ```
+#[macro_export]
+macro_rules! native_fn {
+    ($(#[$meta:meta])* $vis:vis $name:ident($($arg:ident: $arg_type:ty),* $(,)?) -> $ret:ty $body:block) => {
+        $(#[$meta])*
+        $vis fn $name(
+            ctx: $crate::val::NativeFunctionContext<'_>,
+        ) -> $crate::error::VmResult<$crate::val::ValBuilder<'_>> {
+            let arity = <[&str]>::len(&[$(stringify!($arg)),*]);
+            if ctx.arg_count() != arity {
+                return Err($crate::error::VmError::ArityError {
+                    src: None,
+                    function: stringify!($name).into(),
+                    expected: arity,
+                    actual: ctx.arg_count(),
+                });
+            }
+            let mut _nth = 0;
+            $(
+                let $arg: $arg_type = ctx.arg_as_with_context(_nth, stringify!($name))?;
+                _nth += 1;
+            )*
+            fn body($($arg: $arg_type),*) -> $ret $body
+            Ok($crate::val::ValBuilder::from_rust(ctx, body($($arg),*)))
+        }
+    };
+}
+
+fn arg_type_error(vm: &Vm, expected: &'static str, v: Val) -> VmError {
+    VmError::TypeError {
+        src: None,
+        context: "native function arg",
+        expected,
+        actual: v.type_name(),
+        value: v.format_quoted(vm).to_string(),
+    }
+}
+
+impl<'a> NativeFunctionContext<'a> {
+    /// Call `func` with `args` and return the result.
+    ///
+    /// The returned value remains valid until the native function returns since the garbage
+    /// collector does not run while a native function is running.
+    ///
+    /// ```rust
+    /// fn call_twice(
+    ///     mut ctx: spore_vm::val::NativeFunctionContext,
+    /// ) -> spore_vm::error::VmResult<spore_vm::val::ValBuilder> {
+    ///     let func = ctx.arg(0).unwrap();
+    ///     let once = ctx.call(func, &[1.into()])?;
+    ///     let twice = ctx.call(func, &[once])?;
+    ///     Ok(spore_vm::val::ValBuilder::new(twice.try_int().unwrap().into()))
+    /// }
+    /// ```
+    pub fn call(&mut self, func: Val, args: &[Val]) -> VmResult<Val<'a>> {
+        let func = func.as_unsafe_val();
+        let args = Val::as_unsafe_val_slice(args);
+        self.vm.objects.check_owned(func)?;
+        for arg in args.iter() {
+            self.vm.objects.check_owned(*arg)?;
+        }
+        let v = self.vm.call_reentrant(func, args)?;
+        // Unsafe OK: The garbage collector does not run while the native function is running.
+        Ok(unsafe { Val::from_unsafe_val(v) })
+    }
 }
 
 impl<'a> NativeFunctionContext<'a> {
@@ -139,6 +359,17 @@ impl<'a> NativeFunctionContext<'a> {
         }
     }
 
+    /// Create new bytes from `BytesVal`.
+    ///
+    /// Consumes the self to ensure that the value isn't garbage collected.
+    pub fn new_bytes(self, bytes: BytesVal) -> ValBuilder<'a> {
+        let bytes_id = self.vm.objects.insert_bytes(bytes);
+        ValBuilder {
+            // Unsafe OK: Bytes were just created so they do not have a chance to garbage collect.
+            val: unsafe { Val::from_unsafe_val(bytes_id.into()) },
+        }
+    }
+
     /// Create a new box from the unsafe val.
     ///
     /// Consumes the `self` to ensure that the value isn't garbage collected.
@@ -166,6 +397,32 @@ impl<'a> NativeFunctionContext<'a> {
         }
     }
 
+    /// Create a new vector from `VectorVal`.
+    ///
+    /// Consumes the self to ensure that the value isn't garbage collected.
+    ///
+    /// # Safety
+    /// `vector` must contain valid values within the vm.
+    pub unsafe fn new_vector(self, vector: VectorVal) -> ValBuilder<'a> {
+        let vector_id = self.vm.objects.insert_vector(vector);
+        ValBuilder {
+            val: Val::from_unsafe_val(vector_id.into()),
+        }
+    }
+
+    /// Create a new array from `ArrayVal`.
+    ///
+    /// Consumes the self to ensure that the value isn't garbage collected.
+    ///
+    /// # Safety
+    /// `array` must contain valid values within the vm.
+    pub unsafe fn new_array(self, array: ArrayVal) -> ValBuilder<'a> {
+        let array_id = self.vm.objects.insert_array(array);
+        ValBuilder {
+            val: Val::from_unsafe_val(array_id.into()),
+        }
+    }
+
     /// Create a new struct from `StructVal`.
     ///
     /// Consumes the self to ensure that the value isn't garbage collected.
@@ -198,3 +455,313 @@ impl<'a> ValBuilder<'a> {
         self.val.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::VmError, parser::span::Span};
+
+    use super::*;
+
+    fn call_twice(mut ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+        let func = ctx.arg(0).unwrap();
+        let arg = ctx.arg(1).unwrap();
+        let once = ctx.call(func, &[arg])?;
+        let twice = ctx.call(func, &[once])?;
+        // Unsafe OK: `twice` was just returned by the VM.
+        Ok(unsafe { ctx.with_unsafe_val(twice.as_unsafe_val()) })
+    }
+
+    #[test]
+    fn call_runs_lambda_and_returns_result() {
+        let mut vm = Vm::default().with_native_function("call-twice", call_twice);
+        assert_eq!(
+            vm.eval_str("(call-twice (lambda (x) (+ x 10)) 1)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            21
+        );
+        assert_eq!(
+            vm.eval_str("(call-twice (lambda (x) (list x)) 1)")
+                .unwrap()
+                .to_string(),
+            "((1))"
+        );
+    }
+
+    #[test]
+    fn call_within_expression_leaves_stack_intact() {
+        let mut vm = Vm::default().with_native_function("call-twice", call_twice);
+        vm.eval_str(
+            "(define (add-ten y) (+ y 10)) (define (add-twice x) (+ 1 (call-twice add-ten x) 1))",
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(+ 100 (add-twice 0))")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            122
+        );
+        assert_eq!(
+            vm.eval_str("(call-twice inc 0)")
+                .unwrap()
+                .try_int()
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn call_propagates_errors() {
+        let mut vm = Vm::default().with_native_function("call-twice", call_twice);
+        assert_eq!(
+            vm.eval_str("(call-twice (lambda () 0) 1)").unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 28).with_src("(call-twice (lambda () 0) 1)".into())),
+                function: "".into(),
+                expected: 0,
+                actual: 1
+            }
+        );
+        assert_eq!(vm.eval_str("(+ 1 2)").unwrap().try_int().unwrap(), 3);
+    }
+
+    /// Call the function in arg 0 and return `false` if it fails.
+    fn try_call(mut ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+        let func = ctx.arg(0).unwrap();
+        match ctx.call(func, &[]) {
+            // Unsafe OK: `v` was just returned by the VM.
+            Ok(v) => Ok(unsafe { ctx.with_unsafe_val(v.as_unsafe_val()) }),
+            Err(_) => Ok(ValBuilder::new(false.into())),
+        }
+    }
+
+    #[test]
+    fn call_error_handled_by_native_function_leaves_vm_intact() {
+        let mut vm = Vm::default().with_native_function("try-call", try_call);
+        vm.eval_str(
+            r#"
+(define (bad) (+ 1 "x"))
+(define (deep-bad n) (if (= n 0) (bad) (+ 1 (deep-bad (- n 1)))))
+(define (good) 7)
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vm.eval_str("(list (try-call bad) 42 (try-call good))")
+                .unwrap()
+                .to_string(),
+            "(false 42 7)"
+        );
+        assert_eq!(
+            vm.eval_str("(list (try-call (lambda () (deep-bad 10))) 42 (try-call good))")
+                .unwrap()
+                .to_string(),
+            "(false 42 7)"
+        );
+        assert_eq!(
+            vm.eval_str("(list (try-call (lambda () (list-length 1))) (try-call good))")
+                .unwrap()
+                .to_string(),
+            "(false 7)"
+        );
+        assert_eq!(
+            vm.eval_str("(list (try-call (lambda () (try-call bad) (bad))) (try-call good))")
+                .unwrap()
+                .to_string(),
+            "(false 7)"
+        );
+        assert_eq!(vm.eval_str("(+ 1 2)").unwrap().try_int().unwrap(), 3);
+    }
+
+    fn describe(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+        let n: i64 = ctx.arg_as(0)?;
+        let x: f64 = ctx.arg_as(1)?;
+        let b: bool = ctx.arg_as(2)?;
+        let s: String = ctx.arg_as(3)?;
+        let names: Vec<String> = ctx.arg_as(4)?;
+        let nested: Vec<Vec<i64>> = ctx.arg_as(5)?;
+        Ok(ctx.new_string(format!("{n} {x:?} {b} {s} {names:?} {nested:?}").into()))
+    }
+
+    #[test]
+    fn arg_as_extracts_each_supported_type() {
+        let mut vm = Vm::default().with_native_function("describe", describe);
+        assert_eq!(
+            vm.eval_str("(describe 1 2.5 true \"str\" (list \"a\" \"b\") (list (list 1) (list)))")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "1 2.5 true str [\"a\", \"b\"] [[1], []]"
+        );
+    }
+
+    #[test]
+    fn arg_as_with_wrong_type_returns_type_error() {
+        let mut vm = Vm::default().with_native_function("describe", describe);
+        for (src, expected, actual, value) in [
+            (
+                "(describe 1.0 2.5 true \"\" (list) (list))",
+                UnsafeVal::INT_TYPE_NAME,
+                UnsafeVal::FLOAT_TYPE_NAME,
+                "1.0",
+            ),
+            (
+                "(describe 1 2 true \"\" (list) (list))",
+                UnsafeVal::FLOAT_TYPE_NAME,
+                UnsafeVal::INT_TYPE_NAME,
+                "2",
+            ),
+            (
+                "(describe 1 2.5 1 \"\" (list) (list))",
+                UnsafeVal::BOOL_TYPE_NAME,
+                UnsafeVal::INT_TYPE_NAME,
+                "1",
+            ),
+            (
+                "(describe 1 2.5 true 'sym (list) (list))",
+                UnsafeVal::STRING_TYPE_NAME,
+                UnsafeVal::SYMBOL_TYPE_NAME,
+                "'sym",
+            ),
+            (
+                "(describe 1 2.5 true \"\" \"a\" (list))",
+                UnsafeVal::LIST_TYPE_NAME,
+                UnsafeVal::STRING_TYPE_NAME,
+                "\"a\"",
+            ),
+            (
+                "(describe 1 2.5 true \"\" (list \"a\" 2) (list))",
+                UnsafeVal::STRING_TYPE_NAME,
+                UnsafeVal::INT_TYPE_NAME,
+                "2",
+            ),
+        ] {
+            assert_eq!(
+                vm.eval_str(src).unwrap_err(),
+                VmError::TypeError {
+                    src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                    context: "native function arg",
+                    expected,
+                    actual,
+                    value: value.into(),
+                },
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn arg_as_with_missing_arg_returns_error_with_arg_index() {
+        let mut vm = Vm::default().with_native_function("describe", describe);
+        let src = "(describe 1 2.5)";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::CustomError {
+                src: Some(Span::new(0, 16).with_src(src.into())),
+                message: "native function arg(idx = 2) is missing, got 2 args".into(),
+            }
+        );
+    }
+
+    crate::native_fn!(add2(a: i64, b: i64) -> i64 { a + b });
+
+    /// `add2` written without [crate::native_fn].
+    fn add2_by_hand(ctx: NativeFunctionContext) -> VmResult<ValBuilder> {
+        if ctx.arg_count() != 2 {
+            return Err(VmError::ArityError {
+                src: None,
+                function: "add2".into(),
+                expected: 2,
+                actual: ctx.arg_count(),
+            });
+        }
+        let mut sum = 0;
+        for arg in ctx.args() {
+            sum += arg.try_int().map_err(|v| VmError::TypeError {
+                src: None,
+                context: "add2",
+                expected: UnsafeVal::INT_TYPE_NAME,
+                actual: v.type_name(),
+                value: v.format_quoted(ctx.vm()).to_string(),
+            })?;
+        }
+        Ok(ValBuilder::new(sum.into()))
+    }
+
+    #[test]
+    fn native_fn_checks_args_like_hand_written_function() {
+        let mut macro_vm = Vm::default().with_native_function("add2", add2);
+        let mut hand_vm = Vm::default().with_native_function("add2", add2_by_hand);
+        for src in [
+            "(add2 1 2)",
+            "(add2)",
+            "(add2 1)",
+            "(add2 1 2 3)",
+            "(add2 1.0 2)",
+            "(add2 1 \"2\")",
+        ] {
+            let got = macro_vm.eval_str(src).map(|v| v.to_string());
+            let want = hand_vm.eval_str(src).map(|v| v.to_string());
+            assert_eq!(got, want, "{src}");
+        }
+        assert_eq!(
+            macro_vm.eval_str("(add2 1 2)").unwrap().try_int().unwrap(),
+            3
+        );
+        let src = "(add2 1)";
+        assert_eq!(
+            macro_vm.eval_str(src).unwrap_err(),
+            VmError::ArityError {
+                src: Some(Span::new(0, 8).with_src(src.into())),
+                function: "add2".into(),
+                expected: 2,
+                actual: 1,
+            }
+        );
+    }
+
+    crate::native_fn!(
+        /// Join `names` with `sep`.
+        join(names: Vec<String>, sep: String) -> String {
+            names.join(&sep)
+        }
+    );
+
+    crate::native_fn!(answer() -> i64 { 42 });
+
+    #[test]
+    fn native_fn_converts_collections_and_supports_no_args() {
+        let mut vm = Vm::default()
+            .with_native_function("join", join)
+            .with_native_function("answer", answer);
+        assert_eq!(
+            vm.eval_str("(join (list \"a\" \"b\") \", \")")
+                .unwrap()
+                .try_str()
+                .unwrap(),
+            "a, b"
+        );
+        let src = "(join (list \"a\" 1) \"\")";
+        assert_eq!(
+            vm.eval_str(src).unwrap_err(),
+            VmError::TypeError {
+                src: Some(Span::new(0, src.len() as u32).with_src(src.into())),
+                context: "join",
+                expected: UnsafeVal::STRING_TYPE_NAME,
+                actual: UnsafeVal::INT_TYPE_NAME,
+                value: "1".into(),
+            }
+        );
+        assert_eq!(vm.eval_str("(answer)").unwrap().try_int().unwrap(), 42);
+        assert!(matches!(
+            vm.eval_str("(answer 1)").unwrap_err(),
+            VmError::ArityError {
+                expected: 0,
+                actual: 1,
+                ..
+            }
+        ));
+    }
+}
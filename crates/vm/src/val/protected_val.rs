@@ -1,4 +1,7 @@
-use std::ops::Deref;
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
 
 #[allow(unused_imports)]
 use log::*;
@@ -7,12 +10,15 @@ use crate::Vm;
 
 use super::{
     custom::{CustomValError, CustomValMut, CustomValRef},
-    CustomType, Val,
+    CustomType, UnsafeVal, Val,
 };
 
 /// Holds a value from the [Vm] that is guaranteed to not be garbage collected.
 ///
-/// The underlying value is protected from garbage collection until `ProtectedVal` is dropped.
+/// The underlying value is protected from garbage collection until `ProtectedVal` is dropped. As
+/// `ProtectedVal` holds a mutable reference to the [Vm], only one may exist at a time. To keep
+/// several values alive at once, use [ProtectedVal::clone_protected] to create a
+/// [ProtectedValHandle] for each value.
 pub struct ProtectedVal<'a> {
     pub(crate) vm: &'a mut Vm,
     pub(crate) val: Val<'a>,
@@ -38,8 +44,9 @@ impl<'a> ProtectedVal<'a> {
 
     /// Split the protected val into its [Vm] and [Val].
     ///
-    /// Despite the split, the returned `Val` will still be safe from garbage collection.
-    pub fn split(&mut self) -> (&mut Vm, &Val) {
+    /// Despite the split, the returned `Val` will still be safe from garbage collection. This is
+    /// useful for running more code on the [Vm] while still using the value.
+    pub fn split(&mut self) -> (&mut Vm, &Val<'a>) {
         (self.vm, &self.val)
     }
 
@@ -51,6 +58,77 @@ impl<'a> ProtectedVal<'a> {
     }
 }
 
+impl<'a> ProtectedVal<'a> {
+    /// Create a handle that keeps the value protected from garbage collection, even after `self`
+    /// is dropped. The handle does not borrow the [Vm] so many handles may be held at once.
+    ///
+    /// The value stays protected until [ProtectedValHandle::release] is called or the handle is
+    /// dropped.
+    pub fn clone_protected(&mut self) -> ProtectedValHandle {
+        self.vm.objects.keep_reachable(self.val.inner);
+        ProtectedValHandle {
+            val: self.val.inner,
+            pending_unreachable: self.vm.objects.pending_unreachable(),
+        }
+    }
+
+    /// Remove the protection from garbage collection and return the [Vm].
+    ///
+    /// This is equivalent to dropping `self`, but allows reusing the [Vm] reference.
+    pub fn unprotect(self) -> &'a mut Vm {
+        let mut this = std::mem::ManuallyDrop::new(self);
+        let val = this.val.inner;
+        this.vm.objects.allow_unreachable(val);
+        // Unsafe OK: `this` is never used or dropped after taking the `vm`.
+        unsafe { std::ptr::read(&this.vm) }
+    }
+}
+
+/// Keeps a value from the [Vm] protected from garbage collection without borrowing the [Vm].
+///
+/// The value stays protected until [Self::release] is called or the handle is dropped. Since the
+/// handle does not have access to the [Vm] when dropped, dropping releases the value on the next
+/// garbage collection.
+#[derive(Debug)]
+pub struct ProtectedValHandle {
+    val: UnsafeVal,
+    pending_unreachable: Arc<Mutex<Vec<UnsafeVal>>>,
+}
+
+impl ProtectedValHandle {
+    /// Get the underlying value.
+    pub fn get<'a>(&self, _vm: &'a Vm) -> Val<'a> {
+        // Unsafe OK: The value is kept reachable by `self`. Accessors that take a [Vm] check that
+        // the value belongs to the [Vm].
+        unsafe { Val::from_unsafe_val(self.val) }
+    }
+
+    /// Convert the handle back into a [ProtectedVal]. The value remains protected from garbage
+    /// collection until the returned [ProtectedVal] is dropped.
+    pub fn protect(self, vm: &mut Vm) -> ProtectedVal<'_> {
+        let protected = ProtectedVal::new(vm, unsafe { Val::from_unsafe_val(self.val) });
+        self.release(protected.vm);
+        protected
+    }
+
+    /// Remove the protection from garbage collection.
+    pub fn release(self, vm: &mut Vm) {
+        let this = std::mem::ManuallyDrop::new(self);
+        vm.objects.allow_unreachable(this.val);
+        // Unsafe OK: `this` is never used or dropped after taking `pending_unreachable`.
+        drop(unsafe { std::ptr::read(&this.pending_unreachable) });
+    }
+}
+
+impl Drop for ProtectedValHandle {
+    fn drop(&mut self) {
+        self.pending_unreachable
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(self.val);
+    }
+}
+
 impl<'a> Drop for ProtectedVal<'a> {
     fn drop(&mut self) {
         self.vm.objects.allow_unreachable(self.val.inner);
@@ -81,25 +159,25 @@ impl<'a> ProtectedVal<'a> {
     }
 
     /// Try to get the string as a value or return its underlying value.
-    pub fn try_str(&'a self) -> Result<&'a str, Val> {
+    pub fn try_str(&'a self) -> Result<&'a str, Val<'a>> {
         self.val.try_str(self.vm)
     }
 
     /// Try to get the custom value of type `T` or return its underlying value if `self` is not of
     /// type `T`.
-    pub fn try_custom<T: CustomType>(&self) -> Result<CustomValRef<T>, CustomValError> {
+    pub fn try_custom<T: CustomType>(&self) -> Result<CustomValRef<'_, T>, CustomValError> {
         self.val.try_custom(self.vm)
     }
 
     /// Returns the value as a custom type of `T` or [Err] if [Self] is not of the given custom
     /// value.
-    pub fn try_custom_mut<T: CustomType>(&self) -> Result<CustomValMut<T>, CustomValError> {
+    pub fn try_custom_mut<T: CustomType>(&self) -> Result<CustomValMut<'_, T>, CustomValError> {
         self.val.try_custom_mut(self.vm)
     }
 
     /// Get the [Val] that the mutable box is pointing to or `Err<Val>` if `self` is not a mutable
     /// box.
-    pub fn get_mutable_box_ref(&self) -> Result<Val, Val<'a>> {
+    pub fn get_mutable_box_ref(&self) -> Result<Val<'_>, Val<'a>> {
         self.val.try_mutable_box_ref(self.vm)
     }
 }
@@ -109,3 +187,82 @@ impl<'a> std::fmt::Display for ProtectedVal<'a> {
         self.val.format_quoted(self.vm).fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_keep_values_protected_across_evals() {
+        let mut vm = Vm::default();
+        let list = vm.eval_str("(list 1 2 3)").unwrap().clone_protected();
+        let strct = vm
+            .eval_str("(struct 'name \"spore\")")
+            .unwrap()
+            .clone_protected();
+        vm.eval_str("(list 4 5 6)").unwrap();
+        // Unsafe OK: No other values are in use.
+        unsafe { vm.run_gc() };
+
+        let list_val = list.get(&vm);
+        assert_eq!(list_val.format_quoted(&vm).to_string(), "(1 2 3)");
+        let strct_val = strct.get(&vm);
+        assert_eq!(
+            strct_val
+                .try_struct_get(&vm, "name")
+                .unwrap()
+                .unwrap()
+                .try_str(&vm)
+                .unwrap(),
+            "spore"
+        );
+
+        let protected = list.protect(&mut vm);
+        assert_eq!(protected.to_string(), "(1 2 3)");
+        let vm = protected.unprotect();
+        strct.release(vm);
+    }
+
+    #[test]
+    fn dropped_handles_are_released_on_next_gc() {
+        let mut vm = Vm::default();
+        let handle = vm.eval_str("(list 1 2 3)").unwrap().clone_protected();
+        let list = handle.get(&vm).as_unsafe_val();
+        // Unsafe OK: No other values are in use.
+        unsafe { vm.run_gc() };
+        assert!(vm.objects.is_alive(list));
+
+        drop(handle);
+        // Unsafe OK: No other values are in use.
+        unsafe { vm.run_gc() };
+        assert!(!vm.objects.is_alive(list));
+    }
+
+    #[test]
+    fn released_handles_are_not_released_again_when_dropped() {
+        let mut vm = Vm::default();
+        let mut v = vm.eval_str("(list 1 2 3)").unwrap();
+        let first = v.clone_protected();
+        let second = v.clone_protected();
+        drop(v);
+        let list = first.get(&vm).as_unsafe_val();
+
+        first.release(&mut vm);
+        // Unsafe OK: No other values are in use.
+        unsafe { vm.run_gc() };
+        assert!(vm.objects.is_alive(list));
+
+        second.release(&mut vm);
+        // Unsafe OK: No other values are in use.
+        unsafe { vm.run_gc() };
+        assert!(!vm.objects.is_alive(list));
+    }
+
+    #[test]
+    fn unprotect_returns_vm() {
+        let mut vm = Vm::default();
+        let got = vm.eval_str("(list 1 2 3)").unwrap();
+        let vm = got.unprotect();
+        assert_eq!(vm.eval_str("(+ 1 2)").unwrap().try_int().unwrap(), 3);
+    }
+}
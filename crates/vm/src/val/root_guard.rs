@@ -0,0 +1,43 @@
+use std::sync::{Arc, Mutex};
+
+use crate::Vm;
+
+use super::{UnsafeVal, Val};
+
+/// Keeps a value from the [Vm] safe from garbage collection until the guard is dropped.
+///
+/// Unlike [super::ProtectedVal], `RootGuard` does not borrow the [Vm]. This makes it suitable for
+/// storing [Vm] values in host structs. Create one with [Vm::root].
+#[derive(Debug)]
+pub struct RootGuard {
+    val: UnsafeVal,
+    pending_unreachable: Arc<Mutex<Vec<UnsafeVal>>>,
+}
+
+impl RootGuard {
+    /// Create a new guard for `val`. `val` must already be marked as reachable within `vm`.
+    pub(crate) fn new(vm: &Vm, val: UnsafeVal) -> RootGuard {
+        RootGuard {
+            val,
+            pending_unreachable: vm.objects.pending_unreachable(),
+        }
+    }
+
+    /// Get the underlying value.
+    pub fn get<'a>(&self, _vm: &'a Vm) -> Val<'a> {
+        // Unsafe OK: The value is kept reachable until `self` is dropped. Accessors that take a
+        // [Vm] check that the value belongs to the [Vm].
+        unsafe { Val::from_unsafe_val(self.val) }
+    }
+}
+
+impl Drop for RootGuard {
+    fn drop(&mut self) {
+        // The value is released on the next garbage collection since the guard does not have
+        // access to the [Vm].
+        self.pending_unreachable
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(self.val);
+    }
+}
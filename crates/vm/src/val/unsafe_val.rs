@@ -3,8 +3,8 @@ use compact_str::CompactString;
 use crate::Vm;
 
 use super::{
-    bytecode::ByteCode, custom::CustomVal, formatter::ValFormatter, ListVal, NativeFunction,
-    StructVal, Symbol, ValId,
+    bytecode::ByteCode, custom::CustomVal, formatter::ValFormatter, ArrayVal, BytesVal, ListVal,
+    NativeFunction, StructVal, Symbol, ValId, VectorVal,
 };
 
 /// Contains a Spore value. The value is considered unsafe as it may be garbage collected.
@@ -13,6 +13,7 @@ use super::{
 /// The value is considered unsafe as some variants contain references that may be mutated or
 /// garbage collected by the VM. Unsafe fields contain a `Safety` section in their documentation.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[allow(unpredictable_function_pointer_comparisons)]
 pub enum UnsafeVal {
     /// A type that contains a single value. Used to represent nothingness.
     #[default]
@@ -40,6 +41,21 @@ pub enum UnsafeVal {
     /// # Safety
     /// May be garbage collected or mutated by the VM.
     List(ValId<ListVal>),
+    /// A handle to a vector within the VM.
+    ///
+    /// # Safety
+    /// May be garbage collected by the VM.
+    Vector(ValId<VectorVal>),
+    /// A handle to an array within the VM.
+    ///
+    /// # Safety
+    /// May be garbage collected or mutated by the VM.
+    Array(ValId<ArrayVal>),
+    /// A handle to bytes within the VM.
+    ///
+    /// # Safety
+    /// May be garbage collected or mutated by the VM.
+    Bytes(ValId<BytesVal>),
     /// A handle to a struct.
     Struct(ValId<StructVal>),
     /// A handle to a function implemented in Spore's bytecode.
@@ -75,10 +91,33 @@ impl UnsafeVal {
     pub const MUTABLE_BOX_TYPE_NAME: &'static str = "mutable-box";
     /// The display name for the list type.
     pub const LIST_TYPE_NAME: &'static str = "list";
+    /// The display name for the vector type.
+    pub const VECTOR_TYPE_NAME: &'static str = "vector";
+    /// The display name for the array type.
+    pub const ARRAY_TYPE_NAME: &'static str = "array";
+    /// The display name for the bytes type.
+    pub const BYTES_TYPE_NAME: &'static str = "bytes";
     /// The display name for the struct type.
     pub const STRUCT_TYPE_NAME: &'static str = "struct";
     /// The display name for the custom type.
     pub const CUSTOM_TYPE_NAME: &'static str = "custom";
+    /// The display names for all types.
+    pub const TYPE_NAMES: &'static [&'static str] = &[
+        UnsafeVal::FUNCTION_TYPE_NAME,
+        UnsafeVal::BOOL_TYPE_NAME,
+        UnsafeVal::INT_TYPE_NAME,
+        UnsafeVal::FLOAT_TYPE_NAME,
+        UnsafeVal::VOID_TYPE_NAME,
+        UnsafeVal::SYMBOL_TYPE_NAME,
+        UnsafeVal::STRING_TYPE_NAME,
+        UnsafeVal::MUTABLE_BOX_TYPE_NAME,
+        UnsafeVal::LIST_TYPE_NAME,
+        UnsafeVal::VECTOR_TYPE_NAME,
+        UnsafeVal::ARRAY_TYPE_NAME,
+        UnsafeVal::BYTES_TYPE_NAME,
+        UnsafeVal::STRUCT_TYPE_NAME,
+        UnsafeVal::CUSTOM_TYPE_NAME,
+    ];
 
     /// Get the display name for the type of `self`.
     pub fn type_name(self) -> &'static str {
@@ -91,6 +130,9 @@ impl UnsafeVal {
             UnsafeVal::Symbol(_) => UnsafeVal::SYMBOL_TYPE_NAME,
             UnsafeVal::MutableBox(_) => UnsafeVal::MUTABLE_BOX_TYPE_NAME,
             UnsafeVal::List(_) => UnsafeVal::LIST_TYPE_NAME,
+            UnsafeVal::Vector(_) => UnsafeVal::VECTOR_TYPE_NAME,
+            UnsafeVal::Array(_) => UnsafeVal::ARRAY_TYPE_NAME,
+            UnsafeVal::Bytes(_) => UnsafeVal::BYTES_TYPE_NAME,
             UnsafeVal::Struct(_) => UnsafeVal::STRUCT_TYPE_NAME,
             UnsafeVal::ByteCodeFunction(_) => UnsafeVal::FUNCTION_TYPE_NAME,
             UnsafeVal::NativeFunction(_) => UnsafeVal::FUNCTION_TYPE_NAME,
@@ -109,7 +151,8 @@ impl UnsafeVal {
     }
 
     /// Get a display formatter for the current type. Unlike [Self::formatted], this will print
-    /// strings quoted. For example, `"hello"` will display as "hello" instead of hello.
+    /// strings quoted and symbols with a leading `'`. For example, `"hello"` will display as
+    /// "hello" instead of hello.
     pub fn format_quoted<'a>(&self, vm: &'a Vm) -> impl 'a + std::fmt::Display {
         ValFormatter::new_quoted(vm, *self)
     }
@@ -132,6 +175,9 @@ to_internal_val_impl!(NativeFunction => NativeFunction);
 to_internal_val_impl!(ValId<CompactString> => String);
 to_internal_val_impl!(ValId<UnsafeVal> => MutableBox);
 to_internal_val_impl!(ValId<ListVal> => List);
+to_internal_val_impl!(ValId<VectorVal> => Vector);
+to_internal_val_impl!(ValId<ArrayVal> => Array);
+to_internal_val_impl!(ValId<BytesVal> => Bytes);
 to_internal_val_impl!(ValId<StructVal> => Struct);
 to_internal_val_impl!(ValId<ByteCode> => ByteCodeFunction);
 to_internal_val_impl!(ValId<CustomVal> => Custom);
@@ -164,6 +210,9 @@ mod tests {
             UnsafeVal::String(Default::default()),
             UnsafeVal::MutableBox(Default::default()),
             UnsafeVal::List(Default::default()),
+            UnsafeVal::Vector(Default::default()),
+            UnsafeVal::Array(Default::default()),
+            UnsafeVal::Bytes(Default::default()),
             UnsafeVal::ByteCodeFunction(Default::default()),
             UnsafeVal::NativeFunction(crate::builtins::numbers::add),
             UnsafeVal::Custom(ValId {